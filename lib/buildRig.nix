@@ -16,7 +16,12 @@ flake:
 #   - entrypoint: null, or folder derivation with `bin/<entrypoint_executable>`
 #   - manifest: default manifest with full Nix store paths, overridable to show shorter paths (see flake.lib.genManifest for available args)
 #   - allExeNames: the list of all executable commands exposed by the rig
-#   - mcpServers: attrset of MCP server name -> { rigletName, transport, url, resolvedCommand }
+#   - rigletExes: attrset of riglet name -> { wrapped, unwrapped }, the executables that the riglet's tools expose, by whether they use the rig's XDG_CONFIG_HOME
+#   - mcpServers: attrset of MCP server name -> server config (command, or url, useSSE and headers)
+#   - mcpServerRiglets: attrset of MCP server name -> name of the riglet that declares it, or null if no riglet does
+#   - denyRules: attrset of tool name -> command patterns that entrypoints deny, merged from all riglets
+#   - rigletDenyRules: attrset of riglet name -> its own deny rules, for riglets that have some
+#   - rigletImports: attrset of riglet name -> { explicit, imports }, telling which riglets were pulled in by the imports of others
 #   - configOptions: nested attrset of options exposed by the rig, in serializable form, for discovery purposes
 {
   modules,
@@ -231,6 +236,49 @@ let
   # All commands available through the rig
   allExeNames = builtins.filter (s: s != "") (splitString "\n" (builtins.readFile allExesDeriv));
 
+  # One `<riglet>\t<wrapped|unwrapped>\t<executable>` line per executable of each riglet's tools
+  rigletExesDeriv = pkgs.runCommandLocal "rig-riglet-exes" { } ''
+    touch $out
+    ${concatStringsSep "\n" (
+      flatten (
+        mapAttrsToList (
+          rigletName: riglet:
+          map (
+            kind:
+            map (tool: ''
+              for exe in ${tool}/bin/*; do
+                if [ -e "$exe" ]; then
+                  printf '%s\t%s\t%s\n' ${escapeShellArg rigletName} ${kind} "$(basename "$exe")" >> $out
+                fi
+              done
+            '') riglet.tools.${kind}
+          ) [
+            "wrapped"
+            "unwrapped"
+          ]
+        ) evaluated.config.riglets
+      )
+    )}
+  '';
+
+  # The executables that each riglet exposes, by whether they are wrapped to use the rig's
+  # XDG_CONFIG_HOME or see the user's real one
+  rigletExes =
+    let
+      entries = map (line: splitString "\t" line) (
+        builtins.filter (s: s != "") (splitString "\n" (builtins.readFile rigletExesDeriv))
+      );
+      exesOf =
+        rigletName: kind:
+        map (entry: elemAt entry 2) (
+          builtins.filter (entry: elemAt entry 0 == rigletName && elemAt entry 1 == kind) entries
+        );
+    in
+    mapAttrs (rigletName: _: {
+      wrapped = exesOf rigletName "wrapped";
+      unwrapped = exesOf rigletName "unwrapped";
+    }) evaluated.config.riglets;
+
   # Collect promptCommands from all riglets
   promptCommands =
     let
@@ -252,6 +300,113 @@ let
       mapAttrsToList (n: r: r // { name = n; }) evaluated.config.riglets
     );
 
+  # Where the riglets and MCP servers of the rig come from, as evalModules saw it. Modules are
+  # identified by their file (`_file`, which anonymous modules inherit from the module importing
+  # them), as are the definitions of options
+  moduleFiles = rec {
+    # The files defining each name of an option (`riglets` or `mcpServers`). Definitions under
+    # mkIf, mkMerge... are already resolved by evalModules
+    definingFiles =
+      option:
+      foldl' (
+        acc: def:
+        acc
+        // genAttrs (filter (name: evaluated.config.${option} ? ${name}) (attrNames def.value)) (
+          name: (acc.${name} or [ ]) ++ [ def.file ]
+        )
+      ) { } evaluated.options.${option}.definitionsWithLocations;
+
+    # file -> the riglets it defines
+    rigletsDefinedIn =
+      let
+        rigletFiles = definingFiles "riglets";
+      in
+      foldl' (
+        acc: riglet:
+        foldl' (
+          acc: file: acc // { ${file} = (acc.${file} or [ ]) ++ [ riglet ]; }
+        ) acc rigletFiles.${riglet}
+      ) { } (attrNames rigletFiles);
+
+    # The modules that evalModules collected, each one once. Its `graph` is missing with versions
+    # of nixpkgs' lib older than it
+    nodes = filter (node: !node.disabled) (
+      builtins.genericClosure {
+        startSet = evaluated.graph or [ ];
+        operator = node: node.imports;
+      }
+    );
+
+    # file -> the other files that its modules import
+    importedFiles = foldl' (
+      acc: node:
+      acc
+      // {
+        ${node.file} = unique (
+          (acc.${node.file} or [ ])
+          ++ filter (file: file != node.file) (map (child: child.file) node.imports)
+        );
+      }
+    ) { } nodes;
+
+    # The riglets that a file stands for: those it defines, or if it defines none, those of the
+    # files it imports
+    rigletsOf =
+      seen: file:
+      if rigletsDefinedIn ? ${file} then
+        rigletsDefinedIn.${file}
+      else if elem file seen then
+        [ ]
+      else
+        concatMap (rigletsOf (seen ++ [ file ])) (importedFiles.${file} or [ ]);
+  };
+
+  # How the riglets of the rig were pulled in: riglet name -> { explicit, imports }
+  #   - explicit: whether the riglet comes from a module given to buildRig (i.e. listed in the rig
+  #     or one of the rigs it extends), rather than only from the imports of another riglet
+  #   - imports: the riglets that the riglet's module imports (through modules that define no
+  #     riglet if needed)
+  rigletImports =
+    with moduleFiles;
+    let
+      explicit = concatMap (node: rigletsOf [ ] node.file) (evaluated.graph or [ ]);
+
+      imports = foldl' (
+        acc: file:
+        let
+          riglets = rigletsDefinedIn.${file};
+          imported = concatMap (rigletsOf [ file ]) (importedFiles.${file} or [ ]);
+        in
+        acc
+        // genAttrs riglets (
+          riglet: unique ((acc.${riglet} or [ ]) ++ filter (name: !(elem name riglets)) imported)
+        )
+      ) { } (attrNames rigletsDefinedIn);
+    in
+    if !(evaluated ? graph) then
+      throw "rigletImports needs a version of nixpkgs whose lib.evalModules returns the module graph (`graph`)"
+    else
+      mapAttrs (riglet: _: {
+        explicit = elem riglet explicit;
+        imports = imports.${riglet} or [ ];
+      }) evaluated.config.riglets;
+
+  # The name of the riglet that declares each MCP server of the rig (null if it is declared
+  # elsewhere, e.g. in the config of a rig): the riglet defined in the same file as the server,
+  # or else the one that this file imports
+  mcpServerRiglets =
+    with moduleFiles;
+    let
+      serverFiles = definingFiles "mcpServers";
+    in
+    mapAttrs (
+      name: _:
+      let
+        riglets = concatMap (rigletsOf [ ]) (serverFiles.${name} or [ ]);
+      in
+      if riglets == [ ] then null else head riglets
+    ) evaluated.config.mcpServers;
+
   # Collect deny rules from all riglets
   # Structure: { toolName = ["subcommand1" "subcommand2" ...]; }
   denyRules =
@@ -262,6 +417,11 @@ let
     in
     foldl' mergeDenyRules { } (attrValues evaluated.config.riglets);
 
+  # The deny rules of each riglet, to tell which riglet denies what
+  rigletDenyRules = filterAttrs (_: rules: rules != { }) (
+    mapAttrs (_: riglet: riglet.denyRules) evaluated.config.riglets
+  );
+
   # Convert an option to a serializable format for inspection
   serializeOption =
     opt:
@@ -356,6 +516,7 @@ let
     meta = rigMeta;
     inherit (evaluated.config) mcpServers;
     inherit
+      mcpServerRiglets
       toolRoot
       configRoot
       docAttrs
@@ -364,9 +525,12 @@ let
       shell
       manifest
       allExeNames
+      rigletExes
       modules
       promptCommands
+      rigletImports
       denyRules
+      rigletDenyRules
       configOptions
       ;
   };
@@ -5,6 +5,7 @@ rigupFlake:
   flake, # The current project's flake
   system, # Target system (e.g., "x86_64-linux")
   includeInputs ? false, # Whether to include flake inputs or just self
+  inputNames ? null, # If not null, only process these inputs ("self" being the flake itself). Overrides includeInputs
 }:
 let
   # Get nixpkgs from the flake
@@ -58,10 +59,14 @@ let
       { };
 
   # Select which inputs to process
-  inputsToProcess = {
-    self = flake;
-  }
-  // pkgs.lib.optionalAttrs includeInputs flake.inputs;
+  inputsToProcess =
+    if inputNames != null then
+      pkgs.lib.getAttrs inputNames ({ self = flake; } // flake.inputs)
+    else
+      {
+        self = flake;
+      }
+      // pkgs.lib.optionalAttrs includeInputs flake.inputs;
 in
 # Map over selected inputs and collect metadata
 pkgs.lib.concatMapAttrs processInput inputsToProcess
@@ -18,7 +18,12 @@ pkgs.rustPlatform.buildRustPackage {
 
   postInstall = ''
     wrapProgram $out/bin/rigup \
-      --prefix PATH : ${pkgs.lib.makeBinPath [ pkgs.less ]}
+      --prefix PATH : ${
+        pkgs.lib.makeBinPath [
+          pkgs.less
+          pkgs.curl
+        ]
+      }
   '';
 
   meta = with pkgs.lib; {
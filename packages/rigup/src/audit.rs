@@ -0,0 +1,314 @@
+use crate::permissions::DenyRule;
+use crate::types::McpServerInfo;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A kind of finding of `rigup audit`, that can be made to fail the audit with `--fail-on`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuditCheck {
+    /// A tool that is not wrapped, so that it sees the user's real XDG_CONFIG_HOME
+    UnwrappedTool,
+    /// A remote MCP server
+    RemoteMcp,
+    /// A remote MCP server that is not reached over `https://`
+    RemoteMcpWithoutTls,
+    /// A riglet marked as broken
+    BrokenRiglet,
+    /// A riglet whose status is `draft`
+    DraftRiglet,
+    /// A riglet whose status is `deprecated`
+    DeprecatedRiglet,
+}
+
+impl AuditCheck {
+    /// The name of the check, as given to `--fail-on`
+    pub fn name(self) -> String {
+        self.to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// A riglet's status, as far as the audit goes
+#[derive(Debug, Deserialize)]
+pub struct RigletStatus {
+    pub status: String,
+    pub broken: bool,
+}
+
+/// The executables of a riglet's tools (see `rigletExes` in buildRig.nix)
+#[derive(Debug, Deserialize)]
+pub struct RigletExes {
+    pub wrapped: Vec<String>,
+    pub unwrapped: Vec<String>,
+}
+
+/// An MCP server of a rig, with the riglet that declares it
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AuditedMcpServer {
+    #[serde(flatten)]
+    pub info: McpServerInfo,
+    #[serde(rename = "rigletName")]
+    pub riglet: Option<String>,
+}
+
+/// What the audit looks at in a rig, as evaluated by Nix
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditedRig {
+    pub riglets: BTreeMap<String, RigletStatus>,
+    pub riglet_exes: BTreeMap<String, RigletExes>,
+    pub mcp_servers: BTreeMap<String, AuditedMcpServer>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Executable {
+    pub name: String,
+    pub riglet: String,
+    /// Whether it uses the rig's XDG_CONFIG_HOME rather than the user's
+    pub wrapped: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RigletEntry {
+    pub name: String,
+    pub status: String,
+    pub broken: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    pub check: AuditCheck,
+    pub riglet: Option<String>,
+    pub message: String,
+    /// Whether the check was given to `--fail-on`
+    pub failing: bool,
+}
+
+/// Everything that a reviewer should look at before approving a rig
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditReport {
+    pub rig: String,
+    pub executables: Vec<Executable>,
+    pub mcp_servers: BTreeMap<String, AuditedMcpServer>,
+    pub deny_rules: Vec<DenyRule>,
+    /// The riglets marked broken, draft or deprecated
+    pub flagged_riglets: Vec<RigletEntry>,
+    pub findings: Vec<Finding>,
+}
+
+/// ` (riglet `<riglet>`)`, to tell where a finding comes from
+fn declared_by(riglet: Option<&str>) -> String {
+    riglet
+        .map(|riglet| format!(" (riglet `{}`)", riglet))
+        .unwrap_or_default()
+}
+
+impl AuditReport {
+    pub fn new(
+        rig: String,
+        audited: AuditedRig,
+        deny_rules: Vec<DenyRule>,
+        fail_on: &[AuditCheck],
+    ) -> Self {
+        let mut findings = Vec::new();
+        let mut find = |check, riglet: Option<&str>, message: String| {
+            findings.push(Finding {
+                check,
+                riglet: riglet.map(str::to_string),
+                message,
+                failing: fail_on.contains(&check),
+            });
+        };
+
+        let mut executables = Vec::new();
+        for (riglet, exes) in audited.riglet_exes {
+            for name in &exes.unwrapped {
+                find(
+                    AuditCheck::UnwrappedTool,
+                    Some(&riglet),
+                    format!(
+                        "`{}`{} is unwrapped, so it sees the user's real XDG_CONFIG_HOME",
+                        name,
+                        declared_by(Some(&riglet))
+                    ),
+                );
+            }
+            let wrapped = exes.wrapped.into_iter().map(|name| (name, true));
+            let unwrapped = exes.unwrapped.into_iter().map(|name| (name, false));
+            executables.extend(wrapped.chain(unwrapped).map(|(name, wrapped)| Executable {
+                name,
+                riglet: riglet.clone(),
+                wrapped,
+            }));
+        }
+        executables.sort_by(|a, b| (&a.name, &a.riglet).cmp(&(&b.name, &b.riglet)));
+
+        for (name, server) in &audited.mcp_servers {
+            let Some(url) = &server.info.url else {
+                continue;
+            };
+            let riglet = server.riglet.as_deref();
+            find(
+                AuditCheck::RemoteMcp,
+                riglet,
+                format!(
+                    "MCP server `{}`{} is remote: {}",
+                    name,
+                    declared_by(riglet),
+                    url
+                ),
+            );
+            if !url.to_ascii_lowercase().starts_with("https://") {
+                find(
+                    AuditCheck::RemoteMcpWithoutTls,
+                    riglet,
+                    format!(
+                        "MCP server `{}`{} is reached without TLS: {}",
+                        name,
+                        declared_by(riglet),
+                        url
+                    ),
+                );
+            }
+        }
+
+        let mut flagged_riglets = Vec::new();
+        for (name, riglet) in audited.riglets {
+            if riglet.broken {
+                find(
+                    AuditCheck::BrokenRiglet,
+                    Some(&name),
+                    format!("Riglet `{}` is marked as broken", name),
+                );
+            }
+            let status_check = match riglet.status.as_str() {
+                "draft" => Some(AuditCheck::DraftRiglet),
+                "deprecated" => Some(AuditCheck::DeprecatedRiglet),
+                _ => None,
+            };
+            if let Some(check) = status_check {
+                find(
+                    check,
+                    Some(&name),
+                    format!("Riglet `{}` is {}", name, riglet.status),
+                );
+            }
+            if riglet.broken || status_check.is_some() {
+                flagged_riglets.push(RigletEntry {
+                    name,
+                    status: riglet.status,
+                    broken: riglet.broken,
+                });
+            }
+        }
+
+        AuditReport {
+            rig,
+            executables,
+            mcp_servers: audited.mcp_servers,
+            deny_rules,
+            flagged_riglets,
+            findings,
+        }
+    }
+
+    pub fn failing_findings(&self) -> impl Iterator<Item = &Finding> {
+        self.findings.iter().filter(|finding| finding.failing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_findings() {
+        let audited: AuditedRig = serde_json::from_value(serde_json::json!({
+            "riglets": {
+                "github": { "status": "draft", "broken": false },
+                "git-setup": { "status": "stable", "broken": false },
+                "old": { "status": "deprecated", "broken": true },
+            },
+            "rigletExes": {
+                "github": { "wrapped": [], "unwrapped": ["gh"] },
+                "git-setup": { "wrapped": ["git", "git-shell"], "unwrapped": [] },
+                "old": { "wrapped": [], "unwrapped": [] },
+            },
+            "mcpServers": {
+                "docs": { "transport": "http", "url": "https://docs.example/mcp", "headers": ["Authorization"], "rigletName": "github" },
+                "local": { "transport": "stdio", "command": "/nix/store/x/bin/local", "rigletName": null },
+                "plain": { "transport": "sse", "url": "http://10.0.0.1/sse", "rigletName": null },
+            },
+        }))
+        .unwrap();
+        let report = AuditReport::new(
+            "default".to_string(),
+            audited,
+            vec![],
+            &[AuditCheck::UnwrappedTool, AuditCheck::RemoteMcpWithoutTls],
+        );
+
+        let executables: Vec<(&str, bool)> = report
+            .executables
+            .iter()
+            .map(|exe| (exe.name.as_str(), exe.wrapped))
+            .collect();
+        assert_eq!(
+            executables,
+            [("gh", false), ("git", true), ("git-shell", true)]
+        );
+        let findings: Vec<(String, &str, bool)> = report
+            .findings
+            .iter()
+            .map(|finding| {
+                (
+                    finding.check.name(),
+                    finding.message.as_str(),
+                    finding.failing,
+                )
+            })
+            .collect();
+        assert_eq!(
+            findings,
+            [
+                (
+                    "unwrapped-tool".to_string(),
+                    "`gh` (riglet `github`) is unwrapped, so it sees the user's real XDG_CONFIG_HOME",
+                    true
+                ),
+                (
+                    "remote-mcp".to_string(),
+                    "MCP server `docs` (riglet `github`) is remote: https://docs.example/mcp",
+                    false
+                ),
+                (
+                    "remote-mcp".to_string(),
+                    "MCP server `plain` is remote: http://10.0.0.1/sse",
+                    false
+                ),
+                (
+                    "remote-mcp-without-tls".to_string(),
+                    "MCP server `plain` is reached without TLS: http://10.0.0.1/sse",
+                    true
+                ),
+                ("draft-riglet".to_string(), "Riglet `github` is draft", false),
+                (
+                    "broken-riglet".to_string(),
+                    "Riglet `old` is marked as broken",
+                    false
+                ),
+                (
+                    "deprecated-riglet".to_string(),
+                    "Riglet `old` is deprecated",
+                    false
+                ),
+            ]
+        );
+        assert_eq!(report.flagged_riglets.len(), 2);
+        assert_eq!(report.failing_findings().count(), 2);
+    }
+}
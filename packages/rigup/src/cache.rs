@@ -0,0 +1,214 @@
+use crate::nix::{get_system, run_nix_eval_json};
+use miette::{IntoDiagnostic, Result};
+use owo_colors::OwoColorize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime};
+
+/// Cache entries that have not been used for that long are deleted
+const MAX_ENTRY_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Folder where evaluation results are cached: `$XDG_CACHE_HOME/rigup`, or `$HOME/.cache/rigup`
+fn cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|dir| dir.join("rigup"))
+}
+
+/// Get the narHash of a flake's locked contents, via `nix flake metadata --json`
+///
+/// Returns None if the flake cannot be locked (in which case its evaluation should not be cached)
+fn locked_nar_hash(flake: &str) -> Option<String> {
+    let output = Command::new("nix")
+        .args(["flake", "metadata", "--json", flake])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let metadata: Value = serde_json::from_slice(&output.stdout).ok()?;
+    metadata
+        .get("locked")?
+        .get("narHash")?
+        .as_str()
+        .map(str::to_string)
+}
+
+/// Key of a cache entry. The narHash covers the flake's whole locked contents, flake.lock included
+fn cache_key(nar_hash: &str, system: &str, eval_expr: &str) -> String {
+    let mut hasher = Sha256::new();
+    for part in [nar_hash, system, eval_expr] {
+        hasher.update(part.as_bytes());
+        // Separator, so that parts cannot be shifted to produce the same key
+        hasher.update([0u8]);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Delete the cache entries (and leftover temp files) not used for more than `max_age`.
+/// Every flake change yields new entries, so without this the cache would grow forever
+fn prune_cache(dir: &Path, max_age: Duration) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > max_age);
+        if expired {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Write a cache entry through a temp file, so that concurrent rigup calls never read a partial one
+fn write_cache_entry(file: &Path, value: &Value) -> Result<()> {
+    if let Some(dir) = file.parent() {
+        std::fs::create_dir_all(dir).into_diagnostic()?;
+    }
+    let tmp_file = file.with_extension(format!("json.{}.tmp", std::process::id()));
+    std::fs::write(&tmp_file, serde_json::to_vec(value).into_diagnostic()?).into_diagnostic()?;
+    std::fs::rename(&tmp_file, file).into_diagnostic()
+}
+
+/// Cache of the evaluations of a given flake
+///
+/// Results are reused when the flake's locked contents (and thus its flake.lock, hence its
+/// inputs), the system and the expression are all unchanged
+pub struct EvalCache {
+    flake: String,
+    /// None if the flake cannot be locked or no cache folder is available, in which case
+    /// nothing is cached
+    nar_hash_and_dir: Option<(String, PathBuf)>,
+    /// Always re-evaluate (and update the cached results)
+    refresh: bool,
+}
+
+impl EvalCache {
+    pub fn new(flake: &str, refresh: bool) -> Self {
+        EvalCache {
+            flake: flake.to_string(),
+            nar_hash_and_dir: locked_nar_hash(flake).zip(cache_dir()),
+            refresh,
+        }
+    }
+
+    fn entry_path(&self, eval_expr: &str) -> Option<PathBuf> {
+        let (nar_hash, dir) = self.nar_hash_and_dir.as_ref()?;
+        let key = cache_key(nar_hash, &get_system(), eval_expr);
+        Some(dir.join(format!("{}.json", key)))
+    }
+
+    /// Evaluate an expression with `run` (e.g. `run_nix_eval_json`), unless it is already cached
+    pub fn eval_json(
+        &self,
+        eval_expr: &str,
+        run: fn(&str, &str) -> Result<Value>,
+    ) -> Result<Value> {
+        let entry = self.entry_path(eval_expr);
+
+        if let (Some(file), false) = (&entry, self.refresh) {
+            // A corrupted cache entry just gets re-evaluated
+            if let Some(value) = std::fs::read(file)
+                .ok()
+                .and_then(|contents| serde_json::from_slice(&contents).ok())
+            {
+                // Mark the entry as used, so that it is not pruned
+                let _ = std::fs::File::options()
+                    .append(true)
+                    .open(file)
+                    .and_then(|file| file.set_modified(SystemTime::now()));
+                return Ok(value);
+            }
+        }
+
+        let value = run(&self.flake, eval_expr)?;
+
+        if let Some(file) = entry {
+            // Caching is best-effort: failing to write the entry must not fail the command
+            let _ = write_cache_entry(&file, &value);
+            if let Some(dir) = file.parent() {
+                prune_cache(dir, MAX_ENTRY_AGE);
+            }
+        }
+
+        Ok(value)
+    }
+}
+
+/// Like `run_nix_eval_json`, but reuses the result of a previous evaluation of the same
+/// expression on the same flake contents (see `EvalCache`). With `refresh`, always re-evaluate
+pub fn cached_nix_eval_json(flake: &str, eval_expr: &str, refresh: bool) -> Result<Value> {
+    let cache = EvalCache::new(flake, refresh);
+    if !refresh
+        && cache
+            .entry_path(eval_expr)
+            .is_some_and(|file| file.exists())
+    {
+        eprintln!(
+            "> Using cached evaluation {}",
+            "(pass --refresh to re-evaluate)".bright_black()
+        );
+    }
+    cache.eval_json(eval_expr, run_nix_eval_json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_key() {
+        let key = cache_key("sha256-flake", "x86_64-linux", "rig.name");
+        assert_eq!(key, cache_key("sha256-flake", "x86_64-linux", "rig.name"));
+        // An updated input or flake.lock changes the flake's narHash
+        assert_ne!(
+            key,
+            cache_key("sha256-relocked", "x86_64-linux", "rig.name")
+        );
+        assert_ne!(key, cache_key("sha256-flake", "aarch64-darwin", "rig.name"));
+        assert_ne!(
+            key,
+            cache_key("sha256-flake", "x86_64-linux", "rig.riglets")
+        );
+        assert_ne!(
+            cache_key("a", "bc", "expr"),
+            cache_key("ab", "c", "expr"),
+            "parts must not be shifted into each other"
+        );
+    }
+
+    #[test]
+    fn test_prune_cache() {
+        let dir = std::env::temp_dir().join(format!("rigup-cache-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let old = dir.join("old.json");
+        let recent = dir.join("recent.json");
+        for file in [&old, &recent] {
+            std::fs::write(file, "{}").unwrap();
+        }
+        std::fs::File::options()
+            .append(true)
+            .open(&old)
+            .and_then(|file| file.set_modified(SystemTime::now() - 2 * MAX_ENTRY_AGE))
+            .unwrap();
+
+        prune_cache(&dir, MAX_ENTRY_AGE);
+        assert!(!old.exists());
+        assert!(recent.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,250 @@
+use crate::audit::{AuditCheck, AuditReport, AuditedRig};
+use crate::cache::cached_nix_eval_json;
+use crate::display::{with_output, write_json, OutputFormat};
+use crate::error::RigupError;
+use crate::extensions::RigExtensions;
+use crate::nix::{build_flake_ref, check_rig_ref, get_system, parse_flake_ref, resolve_flake_path};
+use crate::nix_expr::NixExpr;
+use crate::overrides::ConfigOverrides;
+use crate::permissions::RigDenyRules;
+use miette::{IntoDiagnostic, Result};
+use owo_colors::OwoColorize;
+use serde::Deserialize;
+use std::io::Write;
+
+/// What the audit evaluates of a rig
+#[derive(Debug, Deserialize)]
+struct EvaluatedRig {
+    #[serde(flatten)]
+    audited: AuditedRig,
+    #[serde(flatten)]
+    deny_rules: RigDenyRules,
+}
+
+fn write_report(output: &mut dyn Write, report: &AuditReport) -> std::io::Result<()> {
+    writeln!(output, "🛡️  Audit of rig {}", report.rig.bold())?;
+
+    writeln!(output)?;
+    writeln!(
+        output,
+        "⚙️  {} ({})",
+        "Executables".bold(),
+        report.executables.len()
+    )?;
+    let width = report
+        .executables
+        .iter()
+        .map(|exe| exe.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    for exe in &report.executables {
+        let wrapping = if exe.wrapped {
+            "wrapped".dimmed().to_string()
+        } else {
+            "unwrapped, uses the user's XDG_CONFIG_HOME"
+                .yellow()
+                .to_string()
+        };
+        writeln!(
+            output,
+            "   {}  {}  {}",
+            format!("{:width$}", exe.name, width = width).cyan(),
+            format!("from riglet {}", exe.riglet).dimmed(),
+            wrapping
+        )?;
+    }
+
+    writeln!(output)?;
+    writeln!(
+        output,
+        "🔌 {} ({})",
+        "MCP servers".bold(),
+        report.mcp_servers.len()
+    )?;
+    for (name, server) in &report.mcp_servers {
+        let target = server
+            .info
+            .url
+            .as_deref()
+            .or(server.info.command.as_deref())
+            .unwrap_or("<unknown>");
+        let headers = if server.info.headers.is_empty() {
+            String::new()
+        } else {
+            format!(" (headers: {})", server.info.headers.join(", "))
+        };
+        let declared_by = server
+            .riglet
+            .as_ref()
+            .map(|riglet| format!("  from riglet {}", riglet).dimmed().to_string())
+            .unwrap_or_default();
+        writeln!(
+            output,
+            "   {} [{}] {}{}{}",
+            name.cyan(),
+            server.info.transport,
+            target,
+            headers,
+            declared_by
+        )?;
+    }
+
+    writeln!(output)?;
+    writeln!(
+        output,
+        "🚫 {} ({})",
+        "Deny rules".bold(),
+        report.deny_rules.len()
+    )?;
+    for rule in &report.deny_rules {
+        let declared_by = rule
+            .riglet
+            .as_ref()
+            .map(|riglet| format!("  from riglet {}", riglet).dimmed().to_string())
+            .unwrap_or_default();
+        writeln!(
+            output,
+            "   {}{}",
+            format!("{} {} …", rule.tool, rule.pattern).red(),
+            declared_by
+        )?;
+    }
+
+    if !report.flagged_riglets.is_empty() {
+        writeln!(output)?;
+        writeln!(output, "🧩 {}", "Riglets to look at".bold())?;
+        for riglet in &report.flagged_riglets {
+            let broken = if riglet.broken {
+                format!(", {}", "broken".red())
+            } else {
+                String::new()
+            };
+            writeln!(
+                output,
+                "   {} ({}{})",
+                riglet.name.cyan(),
+                riglet.status.yellow(),
+                broken
+            )?;
+        }
+    }
+
+    writeln!(output)?;
+    writeln!(
+        output,
+        "🔎 {} ({})",
+        "Findings".bold(),
+        report.findings.len()
+    )?;
+    for finding in &report.findings {
+        let mark = if finding.failing {
+            "✗".red().to_string()
+        } else {
+            "!".yellow().to_string()
+        };
+        writeln!(
+            output,
+            "   {} {} {}",
+            mark,
+            format!("[{}]", finding.check.name()).dimmed(),
+            finding.message
+        )?;
+    }
+    Ok(())
+}
+
+/// Report everything that matters to approve a rig: its executables and whether they are
+/// wrapped, its MCP servers, its deny rules and its riglets marked broken, draft or deprecated.
+/// Fails if there are findings of the checks given in `fail_on`
+#[allow(clippy::too_many_arguments)]
+pub fn audit_rig(
+    flake_ref: String,
+    fail_on: Vec<AuditCheck>,
+    format: OutputFormat,
+    no_pager: bool,
+    no_stage: bool,
+    refresh: bool,
+    extensions: &RigExtensions,
+    overrides: &ConfigOverrides,
+) -> Result<()> {
+    let (flake_path, rig_attrpath) = parse_flake_ref(Some(&flake_ref))?;
+    let rig_attrpath = extensions.apply(rig_attrpath);
+    let flake_expr = resolve_flake_path(&flake_path, no_stage)?;
+    let flake_ref = build_flake_ref(&flake_path, &rig_attrpath, &get_system(), None, no_stage)?;
+    eprintln!("> Auditing {}", &flake_ref);
+
+    let rig = check_rig_ref(
+        overrides.rig_expr(&flake_expr, &rig_attrpath),
+        &flake_expr,
+        &rig_attrpath,
+        false,
+    )?;
+    let eval_expr = format!(
+        r###"
+            let
+                rig = {rig};
+            in {{
+                riglets = builtins.mapAttrs (_: meta: {{
+                    inherit (meta) status broken;
+                }}) rig.meta;
+                rigletExes = rig.rigletExes or (throw ("Rig " + {rig_name} + " was built by a version of rigup's lib that does not record the executables of each riglet. Update the flake's rigup input"));
+                mcpServers = builtins.mapAttrs (name: server:
+                    (if server ? command then {{
+                        transport = "stdio";
+                        command = "${{server.command}}/bin/${{server.command.meta.mainProgram
+                            or (builtins.parseDrvName server.command.name).name}}";
+                    }} else {{
+                        transport = if server.useSSE or false then "sse" else "http";
+                        inherit (server) url;
+                        headers = builtins.attrNames (server.headers or {{ }});
+                    }}) // {{ rigletName = rig.mcpServerRiglets.${{name}} or null; }}
+                ) (rig.mcpServers or {{ }});
+                denyRules = rig.denyRules or {{ }};
+                rigletDenyRules = rig.rigletDenyRules or null;
+            }}
+        "###,
+        rig = rig,
+        rig_name = NixExpr::string(&rig_attrpath.to_string()),
+    );
+    let evaluated = check_rig_ref(
+        cached_nix_eval_json(&flake_expr, &eval_expr, refresh),
+        &flake_expr,
+        &rig_attrpath,
+        false,
+    )?;
+    let evaluated: EvaluatedRig = serde_json::from_value(evaluated)
+        .map_err(|e| RigupError::MetadataParseError { source: e })?;
+
+    let report = AuditReport::new(
+        rig_attrpath.to_string(),
+        evaluated.audited,
+        evaluated.deny_rules.into_rules(),
+        &fail_on,
+    );
+    match format {
+        OutputFormat::Json => write_json(&report)?,
+        // Markdown is not accepted by `--format` here
+        OutputFormat::Tree | OutputFormat::Markdown => {
+            eprintln!();
+            with_output(no_pager, |output| {
+                write_report(output, &report).into_diagnostic()
+            })?;
+        }
+    }
+
+    let failing: Vec<RigupError> = report
+        .failing_findings()
+        .map(|finding| RigupError::AuditFinding {
+            check: finding.check.name(),
+            message: finding.message.clone(),
+        })
+        .collect();
+    if !failing.is_empty() {
+        return Err(RigupError::AuditFailed {
+            rig: report.rig,
+            findings: failing,
+        }
+        .into());
+    }
+    Ok(())
+}
@@ -1,4 +1,7 @@
-use crate::nix::{build_flake_ref, get_system, parse_flake_ref, run_command_inherit};
+use crate::extensions::RigExtensions;
+use crate::nix::{
+    build_flake_ref, check_rig_ref, get_system, parse_flake_ref, run_command_inherit,
+};
 use miette::Result;
 use std::env;
 use std::path::PathBuf;
@@ -8,9 +11,11 @@ pub fn browse_rig_docs(
     browser: Option<String>,
     flake_ref: Option<String>,
     no_stage: bool,
+    extensions: &RigExtensions,
 ) -> Result<()> {
     let system = get_system();
     let (flake_path, rig) = parse_flake_ref(flake_ref.as_deref())?;
+    let rig = extensions.apply(rig);
     let full_ref = build_flake_ref(&flake_path, &rig, &system, Some("docRoot"), no_stage)?;
 
     eprintln!("> Building {}", full_ref);
@@ -23,7 +28,15 @@ pub fn browse_rig_docs(
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(miette::miette!("Failed to get docRoot: {}", stderr));
+        let (resolved_path, _) = full_ref
+            .split_once('#')
+            .expect("flake refs built by build_flake_ref have a fragment");
+        return check_rig_ref(
+            Err(miette::miette!("Failed to get docRoot: {}", stderr)),
+            resolved_path,
+            &rig,
+            false,
+        );
     }
 
     let doc_path_str = String::from_utf8(output.stdout)
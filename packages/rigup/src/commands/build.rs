@@ -1,15 +1,34 @@
+use crate::extensions::RigExtensions;
 use crate::nix::{
-    build_flake_ref, get_flake_root, get_system, parse_flake_ref, run_command_inherit,
+    get_flake_root, get_system, parse_flake_ref, rig_installable, run_nix, RigInstallable,
 };
+use crate::overrides::ConfigOverrides;
 use miette::{IntoDiagnostic, Result};
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
 
-pub fn build_rig(flake_ref: Option<String>, no_stage: bool) -> Result<()> {
+pub fn build_rig(
+    flake_ref: Option<String>,
+    no_stage: bool,
+    extensions: &RigExtensions,
+    overrides: &ConfigOverrides,
+) -> Result<()> {
     let system = get_system();
     let (flake_path, rig) = parse_flake_ref(flake_ref.as_deref())?;
-    let full_ref = build_flake_ref(&flake_path, &rig, &system, Some("home"), no_stage)?;
+    let rig = extensions.apply(rig);
+    let installable = rig_installable(&flake_path, &rig, &system, "home", no_stage, overrides)?;
 
+    let output_path = output_dir(&flake_path)?.join(rig.to_file_name());
+    build_installable(&installable, &output_path)?;
+
+    eprintln!("> Rig built at: {}", output_path.display());
+    Ok(())
+}
+
+/// Folder where rigs are built: the repo's `.rigup` for the current repo, or else `$HOME/.rigup`.
+/// Created if it does not exist
+pub fn output_dir(flake_path: &str) -> Result<PathBuf> {
     let output_dir = if flake_path == "." {
         // Using current repo - output to repo's .rigup
         let flake_root = get_flake_root()?;
@@ -27,12 +46,27 @@ pub fn build_rig(flake_ref: Option<String>, no_stage: bool) -> Result<()> {
         std::fs::create_dir_all(&output_dir).into_diagnostic()?;
     }
 
-    let output_path = output_dir.join(&rig);
-    let output_path_str = output_path.to_string_lossy().to_string();
+    Ok(output_dir)
+}
 
-    eprintln!("> Building {}", full_ref);
-    run_command_inherit("nix", vec!["build", &full_ref, "-o", &output_path_str])?;
+/// Build a rig component with `nix build` without linking it, and return its Nix store path
+pub fn build_store_path(installable: &RigInstallable) -> Result<PathBuf> {
+    eprintln!("> Building {}", installable.label);
+    let mut args = vec!["build", "--no-link", "--print-out-paths"];
+    args.extend(installable.args.iter().map(|s| s.as_str()));
+    let stdout = installable.check_failure(run_nix(&installable.flake, &args, Stdio::piped()))?;
+    let stdout = String::from_utf8(stdout).into_diagnostic()?;
+    Ok(PathBuf::from(stdout.trim()))
+}
 
-    eprintln!("> Rig built at: {}", output_path.display());
+/// Build a rig's home with `nix build`, as a symlink at `output_path`
+pub fn build_installable(home: &RigInstallable, output_path: &Path) -> Result<()> {
+    let output_path_str = output_path.to_string_lossy().to_string();
+
+    eprintln!("> Building {}", home.label);
+    let mut args = vec!["build"];
+    args.extend(home.args.iter().map(|s| s.as_str()));
+    args.extend(["-o", &output_path_str]);
+    home.check_failure(run_nix(&home.flake, &args, Stdio::inherit()))?;
     Ok(())
 }
@@ -0,0 +1,130 @@
+use crate::commands::edit::open_defining_file;
+use crate::error::RigupError;
+use crate::nix::{resolve_flake_path, rig_expr};
+use crate::nix_expr::AttrPath;
+use crate::overrides::{check_setting, fetch_config_options, parse_cli_value, toml_to_json};
+use crate::suggest::did_you_mean;
+use crate::types::{flatten_options, ConfigOption, ConfigValue};
+use miette::{IntoDiagnostic, Result};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Evaluate the options of a rig of the current project
+fn rig_options(rig: &str, no_stage: bool) -> Result<BTreeMap<String, ConfigValue>> {
+    let flake_expr = resolve_flake_path(".", no_stage)?;
+    fetch_config_options(&flake_expr, &rig_expr(&flake_expr, &AttrPath::new([rig])))
+}
+
+/// Find an option, or a group of options, in a tree of options
+fn lookup<'a>(
+    options: &'a BTreeMap<String, ConfigValue>,
+    path: &[String],
+) -> Option<&'a ConfigValue> {
+    let (name, rest) = path.split_first()?;
+    match (options.get(name)?, rest.is_empty()) {
+        (value, true) => Some(value),
+        (ConfigValue::Nested(children), false) => lookup(children, rest),
+        (ConfigValue::Option(_), false) => None,
+    }
+}
+
+/// The current value of an option, or else its default
+fn option_value(option: &ConfigOption) -> Value {
+    option
+        .value
+        .clone()
+        .or_else(|| option.default.clone())
+        .unwrap_or(Value::Null)
+}
+
+/// The values of a tree of options, as a JSON object
+fn options_values(options: &BTreeMap<String, ConfigValue>) -> Value {
+    options
+        .iter()
+        .map(|(name, option)| {
+            let value = match option {
+                ConfigValue::Option(option) => option_value(option),
+                ConfigValue::Nested(nested) => options_values(nested),
+            };
+            (name.clone(), value)
+        })
+        .collect::<Map<_, _>>()
+        .into()
+}
+
+/// Print the value of a config option of a rig, or the values of a group of options, as JSON
+pub fn config_get(rig: String, option: String, no_stage: bool) -> Result<()> {
+    let path = AttrPath::parse(&option)?;
+    open_defining_file(&rig)?;
+    let options = rig_options(&rig, no_stage)?;
+
+    let value = match lookup(&options, path.names()) {
+        Some(ConfigValue::Option(option)) => option_value(option),
+        Some(ConfigValue::Nested(children)) => options_values(children),
+        None => {
+            return Err(RigupError::UnknownName {
+                kind: "option",
+                name: option,
+                scope: format!("rig `{}`", rig),
+                did_you_mean: did_you_mean(
+                    &path.names().join("."),
+                    flatten_options(&options).keys(),
+                ),
+            }
+            .into())
+        }
+    };
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&value).into_diagnostic()?
+    );
+    Ok(())
+}
+
+/// Set a config option of a rig in the TOML file that defines it, after checking the value
+/// against the option's type
+pub fn config_set(rig: String, option: String, value: String, no_stage: bool) -> Result<()> {
+    let path = AttrPath::parse(&option)?;
+    let value = parse_cli_value(&value);
+    let mut file = open_defining_file(&rig)?;
+    let options = rig_options(&rig, no_stage)?;
+    check_setting(&rig, &options, &path, &toml_to_json(&value))?;
+
+    let shown_value = value.to_string();
+    if !file.set_config(&rig, path.names(), value) {
+        return Err(miette::miette!(
+            "Cannot set `{}` in rig `{}`: {} sets one of its parents to a value that is not a table",
+            option,
+            rig,
+            file.name()
+        ));
+    }
+    file.save()?;
+    eprintln!(
+        "> Set {} = {} in rig `{}` ({})",
+        option,
+        shown_value.trim(),
+        rig,
+        file.name()
+    );
+    Ok(())
+}
+
+/// Remove a config option from the config of a rig, in the TOML file that defines it, so that it
+/// gets back its default value (or the one set by the rigs it extends)
+pub fn config_unset(rig: String, option: String) -> Result<()> {
+    let path = AttrPath::parse(&option)?;
+    let mut file = open_defining_file(&rig)?;
+    if !file.unset_config(&rig, path.names()) {
+        return Err(RigupError::UnknownName {
+            kind: "option",
+            name: option,
+            scope: format!("the config of rig `{}` in {}", rig, file.name()),
+            did_you_mean: did_you_mean(&path.names().join("."), &file.config_paths(&rig)),
+        }
+        .into());
+    }
+    file.save()?;
+    eprintln!("> Unset {} in rig `{}` ({})", option, rig, file.name());
+    Ok(())
+}
@@ -0,0 +1,494 @@
+use crate::commands::inspect::fetch_rig_inspection;
+use crate::display::{format_value, with_output, write_json, OutputFormat};
+use crate::nix::{parse_flake_ref, resolve_flake_path, resolve_flake_path_at_rev};
+use crate::nix_expr::AttrPath;
+use crate::overrides::ConfigOverrides;
+use crate::types::{flatten_options, RigInspection};
+use itertools::Itertools;
+use miette::{IntoDiagnostic, Result};
+use owo_colors::OwoColorize;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// A value that differs between the two rigs. `None` means absent (or null) on that side
+#[derive(Serialize, Debug)]
+pub struct Change {
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+/// Differences between two evaluated rigs
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RigDiff {
+    pub old: String,
+    pub new: String,
+    pub riglets_added: Vec<String>,
+    pub riglets_removed: Vec<String>,
+    /// Riglets present in both rigs, with the metadata fields that changed
+    pub riglets_changed: BTreeMap<String, BTreeMap<String, Change>>,
+    pub entrypoint: Option<Change>,
+    /// Options only present in the new rig, by dotted path, with their effective value
+    pub options_added: BTreeMap<String, Option<Value>>,
+    /// Options only present in the old rig, by dotted path, with their effective value
+    pub options_removed: BTreeMap<String, Option<Value>>,
+    /// Options present in both rigs whose effective value changed, by dotted path
+    pub options_changed: BTreeMap<String, Change>,
+}
+
+impl RigDiff {
+    pub fn is_empty(&self) -> bool {
+        self.riglets_added.is_empty()
+            && self.riglets_removed.is_empty()
+            && self.riglets_changed.is_empty()
+            && self.entrypoint.is_none()
+            && !self.has_options()
+    }
+
+    fn has_options(&self) -> bool {
+        !self.options_added.is_empty()
+            || !self.options_removed.is_empty()
+            || !self.options_changed.is_empty()
+    }
+}
+
+/// Compare two evaluated rigs
+pub fn compute_diff(
+    old_label: String,
+    old: &RigInspection,
+    new_label: String,
+    new: &RigInspection,
+) -> Result<RigDiff> {
+    let riglets_added = new
+        .riglets
+        .keys()
+        .filter(|name| !old.riglets.contains_key(*name))
+        .cloned()
+        .collect();
+    let riglets_removed = old
+        .riglets
+        .keys()
+        .filter(|name| !new.riglets.contains_key(*name))
+        .cloned()
+        .collect();
+
+    let mut riglets_changed = BTreeMap::new();
+    for (name, old_meta) in &old.riglets {
+        let Some(new_meta) = new.riglets.get(name) else {
+            continue;
+        };
+        // Compare metadata generically, field by field, through their JSON form
+        let old_fields = serde_json::to_value(old_meta).into_diagnostic()?;
+        let new_fields = serde_json::to_value(new_meta).into_diagnostic()?;
+        let (Value::Object(old_fields), Value::Object(new_fields)) = (old_fields, new_fields)
+        else {
+            continue;
+        };
+        let changes: BTreeMap<String, Change> = old_fields
+            .keys()
+            .chain(new_fields.keys())
+            .filter(|field| old_fields.get(*field) != new_fields.get(*field))
+            .map(|field| {
+                (
+                    field.clone(),
+                    Change {
+                        old: old_fields.get(field).cloned(),
+                        new: new_fields.get(field).cloned(),
+                    },
+                )
+            })
+            .collect();
+        if !changes.is_empty() {
+            riglets_changed.insert(name.clone(), changes);
+        }
+    }
+
+    let entrypoint = (old.entrypoint != new.entrypoint).then(|| Change {
+        old: old.entrypoint.clone().map(Value::String),
+        new: new.entrypoint.clone().map(Value::String),
+    });
+
+    let old_options = flatten_options(&old.options);
+    let new_options = flatten_options(&new.options);
+    let options_added = new_options
+        .iter()
+        .filter(|(path, _)| !old_options.contains_key(*path))
+        .map(|(path, opt)| (path.clone(), opt.value.clone()))
+        .collect();
+    let options_removed = old_options
+        .iter()
+        .filter(|(path, _)| !new_options.contains_key(*path))
+        .map(|(path, opt)| (path.clone(), opt.value.clone()))
+        .collect();
+    let options_changed = old_options
+        .into_iter()
+        .filter_map(|(path, old_opt)| {
+            let new_opt = new_options.get(&path)?;
+            (old_opt.value != new_opt.value).then(|| {
+                (
+                    path,
+                    Change {
+                        old: old_opt.value.clone(),
+                        new: new_opt.value.clone(),
+                    },
+                )
+            })
+        })
+        .collect();
+
+    Ok(RigDiff {
+        old: old_label,
+        new: new_label,
+        riglets_added,
+        riglets_removed,
+        riglets_changed,
+        entrypoint,
+        options_added,
+        options_removed,
+        options_changed,
+    })
+}
+
+fn format_side(value: &Option<Value>) -> String {
+    match value {
+        Some(v) => format_value(v),
+        None => "null".to_string(),
+    }
+}
+
+/// Display a rig diff with tree formatting
+pub fn display_diff(output: &mut dyn Write, diff: &RigDiff) -> Result<()> {
+    writeln!(
+        output,
+        "📟 {} → {}",
+        diff.old.bright_blue().bold(),
+        diff.new.bright_blue().bold()
+    )
+    .into_diagnostic()?;
+
+    if diff.is_empty() {
+        writeln!(output, " └─ {}", "No differences".italic()).into_diagnostic()?;
+        return Ok(());
+    }
+
+    let has_riglets = !diff.riglets_added.is_empty()
+        || !diff.riglets_removed.is_empty()
+        || !diff.riglets_changed.is_empty();
+    let sections_count = [has_riglets, diff.entrypoint.is_some(), diff.has_options()]
+        .into_iter()
+        .filter(|&shown| shown)
+        .count();
+    let mut section_idx = 0;
+    let mut next_section = || {
+        section_idx += 1;
+        if section_idx == sections_count {
+            ("└─", "   ")
+        } else {
+            ("├─", " │ ")
+        }
+    };
+
+    if has_riglets {
+        let (section_branch, section_prefix) = next_section();
+        writeln!(output, " {}🧩 {}", section_branch, "Riglets".bold()).into_diagnostic()?;
+
+        let lines: Vec<(String, Vec<String>)> = diff
+            .riglets_added
+            .iter()
+            .map(|name| (format!("{} {}", "+".green(), name.green()), vec![]))
+            .chain(
+                diff.riglets_removed
+                    .iter()
+                    .map(|name| (format!("{} {}", "-".red(), name.red()), vec![])),
+            )
+            .chain(diff.riglets_changed.iter().map(|(name, changes)| {
+                (
+                    format!("{} {}", "~".yellow(), name.cyan()),
+                    changes
+                        .iter()
+                        .map(|(field, change)| {
+                            format!(
+                                "{}: {} → {}",
+                                field,
+                                format_side(&change.old).red(),
+                                format_side(&change.new).green()
+                            )
+                        })
+                        .collect(),
+                )
+            }))
+            .collect();
+
+        let count = lines.len();
+        for (idx, (line, details)) in lines.into_iter().enumerate() {
+            let is_last = idx == count - 1;
+            let branch = if is_last { "└─" } else { "├─" };
+            let continuation = if is_last { "   " } else { " │ " };
+            writeln!(output, "{} {} {}", section_prefix, branch, line).into_diagnostic()?;
+            for detail in details {
+                writeln!(output, "{}{}  {}", section_prefix, continuation, detail)
+                    .into_diagnostic()?;
+            }
+        }
+    }
+
+    if let Some(change) = &diff.entrypoint {
+        let (section_branch, _) = next_section();
+        writeln!(
+            output,
+            " {}🚀 {}: {} → {}",
+            section_branch,
+            "Entrypoint".bold(),
+            format_side(&change.old).red(),
+            format_side(&change.new).green()
+        )
+        .into_diagnostic()?;
+    }
+
+    if diff.has_options() {
+        let (section_branch, section_prefix) = next_section();
+        writeln!(output, " {}⚙️  {}", section_branch, "Configuration".bold()).into_diagnostic()?;
+
+        let lines: Vec<String> = diff
+            .options_added
+            .iter()
+            .map(|(path, value)| {
+                format!(
+                    "{} {} = {}",
+                    "+".green(),
+                    path.green(),
+                    format_side(value).green()
+                )
+            })
+            .chain(diff.options_removed.iter().map(|(path, value)| {
+                format!(
+                    "{} {} = {}",
+                    "-".red(),
+                    path.red(),
+                    format_side(value).red()
+                )
+            }))
+            .chain(diff.options_changed.iter().map(|(path, change)| {
+                format!(
+                    "{} {} = {} → {}",
+                    "~".yellow(),
+                    path.cyan(),
+                    format_side(&change.old).red(),
+                    format_side(&change.new).green()
+                )
+            }))
+            .collect();
+
+        let count = lines.len();
+        for (idx, line) in lines.into_iter().enumerate() {
+            let branch = if idx == count - 1 { "└─" } else { "├─" };
+            writeln!(output, "{} {} {}", section_prefix, branch, line).into_diagnostic()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Escape a value so it can be put in a markdown table cell
+fn md_cell(value: &Option<Value>) -> String {
+    format!("`{}`", format_side(value).replace('|', "\\|"))
+}
+
+/// Display a rig diff as markdown
+pub fn display_diff_markdown(output: &mut dyn Write, diff: &RigDiff) -> Result<()> {
+    writeln!(output, "### Rig diff: `{}` → `{}`\n", diff.old, diff.new).into_diagnostic()?;
+
+    if diff.is_empty() {
+        writeln!(output, "_No differences_").into_diagnostic()?;
+        return Ok(());
+    }
+
+    if !diff.riglets_added.is_empty()
+        || !diff.riglets_removed.is_empty()
+        || !diff.riglets_changed.is_empty()
+    {
+        writeln!(output, "**Riglets**\n").into_diagnostic()?;
+        writeln!(output, "| | Riglet | Changes |\n|---|---|---|").into_diagnostic()?;
+        for name in &diff.riglets_added {
+            writeln!(output, "| ➕ | `{}` | added |", name).into_diagnostic()?;
+        }
+        for name in &diff.riglets_removed {
+            writeln!(output, "| ➖ | `{}` | removed |", name).into_diagnostic()?;
+        }
+        for (name, changes) in &diff.riglets_changed {
+            let changes = changes
+                .iter()
+                .map(|(field, change)| {
+                    format!(
+                        "{}: {} → {}",
+                        field,
+                        md_cell(&change.old),
+                        md_cell(&change.new)
+                    )
+                })
+                .join("<br>");
+            writeln!(output, "| ✏️ | `{}` | {} |", name, changes).into_diagnostic()?;
+        }
+        writeln!(output).into_diagnostic()?;
+    }
+
+    if let Some(change) = &diff.entrypoint {
+        writeln!(
+            output,
+            "**Entrypoint**: {} → {}\n",
+            md_cell(&change.old),
+            md_cell(&change.new)
+        )
+        .into_diagnostic()?;
+    }
+
+    if diff.has_options() {
+        writeln!(output, "**Configuration**\n").into_diagnostic()?;
+        writeln!(output, "| | Option | Old | New |\n|---|---|---|---|").into_diagnostic()?;
+        for (path, value) in &diff.options_added {
+            writeln!(output, "| ➕ | `{}` | | {} |", path, md_cell(value)).into_diagnostic()?;
+        }
+        for (path, value) in &diff.options_removed {
+            writeln!(output, "| ➖ | `{}` | {} | |", path, md_cell(value)).into_diagnostic()?;
+        }
+        for (path, change) in &diff.options_changed {
+            writeln!(
+                output,
+                "| ✏️ | `{}` | {} | {} |",
+                path,
+                md_cell(&change.old),
+                md_cell(&change.new)
+            )
+            .into_diagnostic()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Compare two rigs, or with `rev`, a rig of the current repo with itself at some git revision
+pub fn diff_rigs(
+    old_ref: String,
+    new_ref: Option<String>,
+    rev: Option<String>,
+    no_pager: bool,
+    no_stage: bool,
+    refresh: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    // (label, resolved flake, rig name) for both sides
+    let [(old_label, old_flake, old_rig), (new_label, new_flake, new_rig)] = match (new_ref, rev) {
+        (Some(new_ref), None) => {
+            let side = |flake_ref: String| -> Result<(String, String, AttrPath)> {
+                let (flake_path, rig) = parse_flake_ref(Some(&flake_ref))?;
+                let flake_expr = resolve_flake_path(&flake_path, no_stage)?;
+                Ok((flake_ref, flake_expr, rig))
+            };
+            [side(old_ref)?, side(new_ref)?]
+        }
+        (None, Some(rev)) => {
+            let (flake_path, rig) = parse_flake_ref(Some(&old_ref))?;
+            if flake_path != "." {
+                return Err(miette::miette!(
+                    "--rev can only be used with a rig of the current repo (`.#<rig>`), not '{}'",
+                    old_ref
+                ));
+            }
+            [
+                (
+                    format!("{} (at {})", old_ref, rev),
+                    resolve_flake_path_at_rev(&rev)?,
+                    rig.clone(),
+                ),
+                (
+                    format!("{} (working tree)", old_ref),
+                    resolve_flake_path(&flake_path, no_stage)?,
+                    rig,
+                ),
+            ]
+        }
+        _ => {
+            return Err(miette::miette!(
+                "Give either a second rig to compare to, or a git revision with --rev"
+            ))
+        }
+    };
+
+    let no_overrides = ConfigOverrides::default();
+    eprintln!(
+        "> Inspecting {}#{}",
+        old_flake,
+        old_rig.to_flake_fragment()?
+    );
+    let old = fetch_rig_inspection(&old_flake, &old_rig, &no_overrides, refresh)?;
+    eprintln!(
+        "> Inspecting {}#{}",
+        new_flake,
+        new_rig.to_flake_fragment()?
+    );
+    let new = fetch_rig_inspection(&new_flake, &new_rig, &no_overrides, refresh)?;
+
+    let diff = compute_diff(old_label, &old, new_label, &new)?;
+
+    match format {
+        OutputFormat::Json => write_json(&diff),
+        OutputFormat::Markdown => display_diff_markdown(&mut std::io::stdout().lock(), &diff),
+        OutputFormat::Tree => {
+            eprintln!();
+            with_output(no_pager, |output| display_diff(output, &diff))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn inspection(riglets: &[&str], options: Value) -> RigInspection {
+        serde_json::from_value(json!({
+            "name": "rig",
+            "riglets": riglets.iter().map(|name| (*name, json!({}))).collect::<BTreeMap<_, _>>(),
+            "options": options,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_options_on_one_side() {
+        let old = inspection(
+            &["jj", "nix"],
+            json!({
+                "jj": { "user": { "name": { "value": "Alice" } } },
+                "nix": { "flakes": { "value": true } },
+            }),
+        );
+        let new = inspection(
+            &["jj", "python"],
+            json!({
+                "jj": { "user": { "name": { "value": "Bob" } } },
+                "python": { "version": { "value": "3.12" } },
+            }),
+        );
+        let diff = compute_diff("old".into(), &old, "new".into(), &new).unwrap();
+        assert_eq!(diff.riglets_added, ["python"]);
+        assert_eq!(diff.riglets_removed, ["nix"]);
+        assert_eq!(
+            diff.options_added.get("python.version"),
+            Some(&Some(json!("3.12")))
+        );
+        assert_eq!(
+            diff.options_removed.get("nix.flakes"),
+            Some(&Some(json!(true)))
+        );
+        assert_eq!(
+            diff.options_changed.keys().collect::<Vec<_>>(),
+            ["jj.user.name"]
+        );
+
+        let same = compute_diff("old".into(), &old, "new".into(), &old).unwrap();
+        assert!(same.is_empty());
+    }
+}
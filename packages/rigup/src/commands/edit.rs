@@ -0,0 +1,308 @@
+use crate::commands::show::list_flake;
+use crate::commands::try_rig::parse_input_ref;
+use crate::error::RigupError;
+use crate::nix::{get_flake_root, resolve_flake_path};
+use crate::rigup_toml::TomlFileMut;
+use crate::suggest::did_you_mean;
+use itertools::Itertools;
+use miette::Result;
+
+/// The project's TOML file to edit: rigup.toml, or with `local`, rigup.local.toml
+fn toml_file_name(local: bool) -> &'static str {
+    if local {
+        "rigup.local.toml"
+    } else {
+        "rigup.toml"
+    }
+}
+
+/// Open the TOML file where a rig is to be edited. A rig may be defined in only one of the
+/// project's TOML files, so editing it in the other one is refused
+fn open_rig_file(rig: &str, local: bool) -> Result<TomlFileMut> {
+    let flake_root = get_flake_root()?;
+    let file = TomlFileMut::open(&flake_root, toml_file_name(local))?;
+    if !file.has_rig(rig) {
+        let other = TomlFileMut::open(&flake_root, toml_file_name(!local))?;
+        if other.has_rig(rig) {
+            return Err(miette::miette!(
+                help = if local {
+                    "Drop `--local` to edit it in rigup.toml"
+                } else {
+                    "Use `--local` to edit it in rigup.local.toml"
+                },
+                "Rig `{}` is defined in {}, not in {}",
+                rig,
+                other.name(),
+                file.name()
+            ));
+        }
+    }
+    Ok(file)
+}
+
+/// Open the TOML file of the current project that defines a rig
+pub fn open_defining_file(rig: &str) -> Result<TomlFileMut> {
+    let flake_root = get_flake_root()?;
+    let files = [
+        TomlFileMut::open(&flake_root, toml_file_name(false))?,
+        TomlFileMut::open(&flake_root, toml_file_name(true))?,
+    ];
+    let rig_names: Vec<String> = files.iter().flat_map(TomlFileMut::rig_names).collect();
+    files
+        .into_iter()
+        .find(|file| file.has_rig(rig))
+        .ok_or_else(|| {
+            RigupError::UnknownName {
+                kind: "rig",
+                name: rig.to_string(),
+                scope: "rigup.toml or rigup.local.toml".to_string(),
+                did_you_mean: did_you_mean(rig, &rig_names),
+            }
+            .into()
+        })
+}
+
+/// Check that riglets and rigs of the current project's inputs exist, given as `(<input>, <name>)`
+/// pairs. The project's own rigs are looked up in its TOML files, everything else in the data
+/// that `listFlake` gives
+fn check_input_refs(riglets: &[(&str, &str)], rigs: &[(&str, &str)], no_stage: bool) -> Result<()> {
+    let unknown = |kind, name: &str, scope: String, candidates: Vec<&String>| {
+        Err(RigupError::UnknownName {
+            kind,
+            name: name.to_string(),
+            scope,
+            did_you_mean: did_you_mean(name, candidates),
+        }
+        .into())
+    };
+
+    let (own_rigs, input_rigs): (Vec<_>, Vec<_>) = rigs
+        .iter()
+        .copied()
+        .partition(|(input, _)| *input == "self");
+    if !own_rigs.is_empty() {
+        let flake_root = get_flake_root()?;
+        let mut project_rigs = TomlFileMut::open(&flake_root, toml_file_name(false))?.rig_names();
+        project_rigs.extend(TomlFileMut::open(&flake_root, toml_file_name(true))?.rig_names());
+        for (_, rig) in own_rigs {
+            if !project_rigs.iter().any(|name| name == rig) {
+                let scope = "rigup.toml or rigup.local.toml".to_string();
+                return unknown("rig", rig, scope, project_rigs.iter().collect());
+            }
+        }
+    }
+    if riglets.is_empty() && input_rigs.is_empty() {
+        return Ok(());
+    }
+
+    let flake_expr = resolve_flake_path(".", no_stage)?;
+    let with_inputs = riglets
+        .iter()
+        .chain(&input_rigs)
+        .any(|(input, _)| *input != "self");
+    let all_data = list_flake(&flake_expr, with_inputs, false)?;
+    for (input, riglet) in riglets {
+        let Some(data) = all_data.get(*input).filter(|data| !data.riglets.is_empty()) else {
+            let inputs_with_riglets = all_data
+                .iter()
+                .filter(|(_, data)| !data.riglets.is_empty())
+                .map(|(input, _)| input);
+            let scope = "the current project".to_string();
+            return unknown(
+                "input with riglets",
+                input,
+                scope,
+                inputs_with_riglets.collect(),
+            );
+        };
+        if !data.riglets.contains_key(*riglet) {
+            let scope = format!("input `{}`", input);
+            return unknown("riglet", riglet, scope, data.riglets.keys().collect());
+        }
+    }
+    for (input, rig) in input_rigs {
+        let Some(data) = all_data.get(input).filter(|data| !data.rigs.is_empty()) else {
+            let inputs_with_rigs = all_data
+                .iter()
+                .filter(|(_, data)| !data.rigs.is_empty())
+                .map(|(input, _)| input);
+            let scope = "the current project".to_string();
+            return unknown("input with rigs", input, scope, inputs_with_rigs.collect());
+        };
+        if !data.rigs.contains_key(rig) {
+            let scope = format!("input `{}`", input);
+            return unknown("rig", rig, scope, data.rigs.keys().collect());
+        }
+    }
+    Ok(())
+}
+
+/// Add riglets to a rig of the current project, creating the rig if it does not exist. The
+/// riglets are checked against those that the flake and its inputs provide
+pub fn add_riglets(
+    rig: String,
+    riglet_refs: Vec<String>,
+    local: bool,
+    no_stage: bool,
+) -> Result<()> {
+    let mut file = open_rig_file(&rig, local)?;
+    let riglets: Vec<(&str, &str)> = riglet_refs.iter().map(|r| parse_input_ref(r)).collect();
+    check_input_refs(&riglets, &[], no_stage)?;
+
+    let is_new_rig = !file.has_rig(&rig);
+    for (input, riglet) in &riglets {
+        if file.add_riglet(&rig, input, riglet) {
+            eprintln!(
+                "> Added {}.{} to rig `{}` in {}",
+                input,
+                riglet,
+                rig,
+                file.name()
+            );
+        } else {
+            eprintln!("> Rig `{}` already uses {}.{}", rig, input, riglet);
+        }
+    }
+    if is_new_rig {
+        eprintln!("> Created rig `{}` in {}", rig, file.name());
+    }
+    file.save()
+}
+
+/// Remove riglets from a rig of the current project
+pub fn remove_riglets(rig: String, riglet_refs: Vec<String>, local: bool) -> Result<()> {
+    let mut file = open_rig_file(&rig, local)?;
+    if !file.has_rig(&rig) {
+        return Err(RigupError::UnknownName {
+            kind: "rig",
+            name: rig.clone(),
+            scope: file.name().to_string(),
+            did_you_mean: did_you_mean(&rig, &file.rig_names()),
+        }
+        .into());
+    }
+    let listed: Vec<String> = file
+        .rig_riglets(&rig)
+        .into_iter()
+        .map(|(input, riglet)| format!("{}.{}", input, riglet))
+        .collect();
+
+    for riglet_ref in &riglet_refs {
+        let (input, riglet) = parse_input_ref(riglet_ref);
+        if !file.remove_riglet(&rig, input, riglet) {
+            return Err(RigupError::UnknownName {
+                kind: "riglet",
+                name: format!("{}.{}", input, riglet),
+                scope: format!("the riglets of rig `{}` in {}", rig, file.name()),
+                did_you_mean: did_you_mean(&format!("{}.{}", input, riglet), &listed),
+            }
+            .into());
+        }
+        eprintln!(
+            "> Removed {}.{} from rig `{}` in {}",
+            input,
+            riglet,
+            rig,
+            file.name()
+        );
+    }
+    file.save()
+}
+
+/// Define a new rig in rigup.toml (or rigup.local.toml), extending other rigs and using some
+/// riglets. Like `resolveProject` does, refuse a rig that is already defined in the other file
+pub fn create_rig(
+    rig: String,
+    extends: Vec<String>,
+    riglet_refs: Vec<String>,
+    local: bool,
+    no_stage: bool,
+) -> Result<()> {
+    let flake_root = get_flake_root()?;
+    let mut file = TomlFileMut::open(&flake_root, toml_file_name(local))?;
+    let other = TomlFileMut::open(&flake_root, toml_file_name(!local))?;
+    if file.has_rig(&rig) {
+        return Err(miette::miette!(
+            "Rig `{}` already exists in {}",
+            rig,
+            file.name()
+        ));
+    }
+    if other.has_rig(&rig) {
+        return Err(miette::miette!(
+            help = "A rig can be defined in only one TOML file. To override some rig's config in \
+                    another TOML file, define a new rig that extends it",
+            "Rig `{}` is already defined in {}",
+            rig,
+            other.name()
+        ));
+    }
+
+    let base_rigs: Vec<(&str, &str)> = extends.iter().map(|r| parse_input_ref(r)).collect();
+    let riglets: Vec<(&str, &str)> = riglet_refs.iter().map(|r| parse_input_ref(r)).collect();
+    check_input_refs(&riglets, &base_rigs, no_stage)?;
+
+    file.create_rig(&rig, &base_rigs, &riglets);
+    file.save()?;
+    eprintln!("> Created rig `{}` in {}", rig, file.name());
+    Ok(())
+}
+
+/// Rename a rig of the current project, and update the rigs that extend it
+pub fn rename_rig(old: String, new: String) -> Result<()> {
+    let flake_root = get_flake_root()?;
+    let mut files = [
+        TomlFileMut::open(&flake_root, toml_file_name(false))?,
+        TomlFileMut::open(&flake_root, toml_file_name(true))?,
+    ];
+    if let Some(file) = files.iter().find(|file| file.has_rig(&new)) {
+        return Err(miette::miette!(
+            "Rig `{}` already exists in {}",
+            new,
+            file.name()
+        ));
+    }
+    if !files.iter_mut().any(|file| file.rename_rig(&old, &new)) {
+        let rig_names: Vec<String> = files.iter().flat_map(TomlFileMut::rig_names).collect();
+        return Err(RigupError::UnknownName {
+            kind: "rig",
+            did_you_mean: did_you_mean(&old, &rig_names),
+            name: old,
+            scope: "rigup.toml or rigup.local.toml".to_string(),
+        }
+        .into());
+    }
+    eprintln!("> Renamed rig `{}` to `{}`", old, new);
+    for file in &mut files {
+        for rig in file.replace_base_rig(&old, &new) {
+            eprintln!("> Rig `{}` ({}) now extends `{}`", rig, file.name(), new);
+        }
+        file.save()?;
+    }
+    Ok(())
+}
+
+/// Delete a rig of the current project. Refused if other rigs extend it
+pub fn delete_rig(rig: String) -> Result<()> {
+    let flake_root = get_flake_root()?;
+    let mut file = open_defining_file(&rig)?;
+    let extending: Vec<String> = [toml_file_name(false), toml_file_name(true)]
+        .into_iter()
+        .map(|name| TomlFileMut::open(&flake_root, name))
+        .collect::<Result<Vec<_>>>()?
+        .iter()
+        .flat_map(|file| file.rigs_extending(&rig))
+        .collect();
+    if !extending.is_empty() {
+        return Err(miette::miette!(
+            help = "Delete them first, or make them extend another rig",
+            "Rig `{}` is extended by {}",
+            rig,
+            extending.iter().map(|r| format!("`{}`", r)).join(", ")
+        ));
+    }
+    file.delete_rig(&rig);
+    file.save()?;
+    eprintln!("> Deleted rig `{}` from {}", rig, file.name());
+    Ok(())
+}
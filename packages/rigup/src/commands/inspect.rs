@@ -1,45 +1,19 @@
-use crate::display::{display_riglet, with_output, wrap_with_prefix};
-use crate::error::RigupError;
-use crate::nix::{
-    build_flake_ref, get_system, parse_flake_ref, resolve_flake_path, run_nix_eval_json,
+use crate::cache::cached_nix_eval_json;
+use crate::display::{
+    display_riglet, format_value, with_output, wrap_with_prefix, write_json, OutputFormat,
 };
+use crate::error::RigupError;
+use crate::extensions::RigExtensions;
+use crate::nix::{build_flake_ref, check_rig_ref, get_system, parse_flake_ref, resolve_flake_path};
+use crate::nix_expr::{AttrPath, NixExpr};
+use crate::overrides::ConfigOverrides;
 use crate::types::{ConfigOption, ConfigValue, RigInspection};
 use itertools::Itertools;
 use miette::{IntoDiagnostic, Result};
 use owo_colors::OwoColorize;
-use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::io::Write;
 
-/// Format a JSON value for display
-fn format_value(value: &Value) -> String {
-    match value {
-        Value::String(s) => format!("\"{}\"", s),
-        Value::Number(n) => n.to_string(),
-        Value::Bool(b) => b.to_string(),
-        Value::Null => "null".to_string(),
-        Value::Array(arr) => {
-            if arr.len() <= 3 {
-                format!("[{}]", arr.iter().map(format_value).join(" "))
-            } else {
-                format!("[...{} items...]", arr.len())
-            }
-        }
-        Value::Object(obj) => {
-            if obj.len() <= 3 {
-                format!(
-                    "{{ {}}}",
-                    obj.iter()
-                        .map(|(k, v)| format!("{} = {}; ", k, format_value(v)))
-                        .join("")
-                )
-            } else {
-                format!("{{...{} items...}}", obj.len())
-            }
-        }
-    }
-}
-
 /// Display a config option with tree formatting
 fn display_config_option(
     output: &mut dyn Write,
@@ -134,19 +108,16 @@ fn display_config_option(
 /// Display config values recursively
 fn display_config_values(
     output: &mut dyn Write,
-    values: &HashMap<String, ConfigValue>,
+    values: &BTreeMap<String, ConfigValue>,
     prefix: &str,
     terminal_width: usize,
     detailed: bool,
     no_descriptions: bool,
 ) -> Result<()> {
-    let mut sorted_keys: Vec<_> = values.keys().collect();
-    sorted_keys.sort();
-    let count = sorted_keys.len();
+    let count = values.len();
 
-    for (idx, key) in sorted_keys.into_iter().enumerate() {
+    for (idx, (key, value)) in values.iter().enumerate() {
         let is_last = idx == count - 1;
-        let value = &values[key];
 
         match value {
             ConfigValue::Option(opt) => {
@@ -183,50 +154,88 @@ fn display_config_values(
     Ok(())
 }
 
+/// Evaluate a rig's metadata, config options, MCP servers, prompt commands and deny rules,
+/// once config overrides are applied to it. With `refresh`, bypass the evaluation cache
+pub fn fetch_rig_inspection(
+    flake_expr: &str,
+    rig_attrpath: &AttrPath,
+    overrides: &ConfigOverrides,
+    refresh: bool,
+) -> Result<RigInspection> {
+    let rig = overrides.rig_expr(flake_expr, rig_attrpath)?;
+
+    // Use inspectRig to get detailed rig information
+    let eval_expr = format!(
+        r###"
+            let
+                rig = {rig};
+            in {{
+                name = {rig_name};
+                riglets = rig.meta or {{ }};
+                entrypoint = rig.entrypoint.name or null;
+                options = rig.configOptions or {{ }};
+                mcpServers = builtins.mapAttrs (_: server:
+                    if server ? command then {{
+                        transport = "stdio";
+                        command = "${{server.command}}/bin/${{server.command.meta.mainProgram
+                            or (builtins.parseDrvName server.command.name).name}}";
+                    }} else {{
+                        transport = if server.useSSE or false then "sse" else "http";
+                        inherit (server) url;
+                        headers = builtins.attrNames (server.headers or {{ }});
+                    }}
+                ) (rig.mcpServers or {{ }});
+                promptCommands = builtins.mapAttrs (_: cmd: {{
+                    inherit (cmd) description useSubAgent;
+                }}) (rig.promptCommands or {{ }});
+                denyRules = rig.denyRules or {{ }};
+            }}
+        "###,
+        rig = rig,
+        rig_name = NixExpr::string(&rig_attrpath.to_string()),
+    );
+
+    // Run nix eval (unless cached) and parse the result
+    let result = cached_nix_eval_json(flake_expr, &eval_expr, refresh)?;
+
+    // Parse the rig inspection data
+    serde_json::from_value(result).map_err(|e| RigupError::MetadataParseError { source: e }.into())
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn inspect_rig(
     flake_ref: Option<String>,
     no_pager: bool,
     detailed: bool,
     no_descriptions: bool,
     no_stage: bool,
+    refresh: bool,
+    format: OutputFormat,
+    extensions: &RigExtensions,
+    overrides: &ConfigOverrides,
 ) -> Result<()> {
     let system = get_system();
 
     // Parse flake reference
     let (flake_path, rig_attrpath) = parse_flake_ref(flake_ref.as_deref())?;
+    let rig_attrpath = extensions.apply(rig_attrpath);
 
     // Resolve flake path and ensure rigup.local.toml is staged if needed
     let flake_expr = resolve_flake_path(&flake_path, no_stage)?;
 
-    // Use inspectRig to get detailed rig information
-    let eval_expr = format!(
-        r###"
-            let
-                flake = builtins.getFlake "{flake}";
-                rig = flake.rigs.{system}.{rig} or (throw ''
-                    Flake '{flake}' does not output {rig}
-                '');
-            in {{
-                name = "{rig}";
-                riglets = rig.meta or {{ }};
-                entrypoint = rig.entrypoint.name or null;
-                options = rig.configOptions or {{ }};
-            }}
-        "###,
-        flake = flake_expr,
-        system = system,
-        rig = rig_attrpath
-    );
-
     let flake_ref = build_flake_ref(&flake_path, &rig_attrpath, &system, None, no_stage)?;
     eprintln!("> Inspecting {}", &flake_ref);
 
-    // Run nix eval and parse the result
-    let result = run_nix_eval_json(&eval_expr)?;
+    let inspection = check_rig_ref(
+        fetch_rig_inspection(&flake_expr, &rig_attrpath, overrides, refresh),
+        &flake_expr,
+        &rig_attrpath,
+        false,
+    )?;
 
-    // Parse the rig inspection data
-    let inspection: RigInspection =
-        serde_json::from_value(result).map_err(|e| RigupError::MetadataParseError { source: e })?;
+    if format == OutputFormat::Json {
+        return write_json(&inspection);
+    }
 
     eprintln!("");
 
@@ -250,15 +259,35 @@ pub fn inspect_rig(
         )
         .into_diagnostic()?;
 
+        // Sections are only shown when non-empty, the last one shown gets a closing branch
+        let sections_count = [
+            !inspection.riglets.is_empty(),
+            !inspection.mcp_servers.is_empty(),
+            !inspection.prompt_commands.is_empty(),
+            !inspection.deny_rules.is_empty(),
+            !inspection.options.is_empty(),
+        ]
+        .into_iter()
+        .filter(|&shown| shown)
+        .count();
+        let mut section_idx = 0;
+        let mut next_section = || {
+            section_idx += 1;
+            if section_idx == sections_count {
+                ("└─", "   ")
+            } else {
+                ("├─", " │ ")
+            }
+        };
+
         // Display riglets section
         if !inspection.riglets.is_empty() {
-            writeln!(output, " ├─🧩 {}", "Riglets".bold()).into_diagnostic()?;
+            let (section_branch, section_prefix) = next_section();
+            writeln!(output, " {}🧩 {}", section_branch, "Riglets".bold()).into_diagnostic()?;
 
-            let mut riglets_vec: Vec<_> = inspection.riglets.iter().collect();
-            riglets_vec.sort_by(|a, b| a.0.cmp(b.0));
-            let riglets_count = riglets_vec.len();
+            let riglets_count = inspection.riglets.len();
 
-            for (idx, (riglet_name, meta)) in riglets_vec.into_iter().enumerate() {
+            for (idx, (riglet_name, meta)) in inspection.riglets.iter().enumerate() {
                 let is_last = idx == riglets_count - 1;
                 // display_riglet still uses String internally for wrapping,
                 // but we write it out immediately
@@ -266,7 +295,7 @@ pub fn inspect_rig(
                     output,
                     riglet_name,
                     meta,
-                    " │ ",
+                    section_prefix,
                     is_last,
                     terminal_width,
                     detailed,
@@ -275,14 +304,107 @@ pub fn inspect_rig(
             }
         }
 
+        // Display MCP servers section
+        if !inspection.mcp_servers.is_empty() {
+            let (section_branch, section_prefix) = next_section();
+            writeln!(output, " {}🔌 {}", section_branch, "MCP servers".bold()).into_diagnostic()?;
+
+            let servers_count = inspection.mcp_servers.len();
+            for (idx, (server_name, server)) in inspection.mcp_servers.iter().enumerate() {
+                let branch = if idx == servers_count - 1 {
+                    "└─"
+                } else {
+                    "├─"
+                };
+                let target = server
+                    .url
+                    .as_deref()
+                    .or(server.command.as_deref())
+                    .unwrap_or("<unknown>");
+                let headers = if server.headers.is_empty() {
+                    String::new()
+                } else {
+                    format!(" (headers: {})", server.headers.join(", "))
+                        .bright_black()
+                        .to_string()
+                };
+                writeln!(
+                    output,
+                    "{} {} {} {} {}{}",
+                    section_prefix,
+                    branch,
+                    server_name.cyan(),
+                    server.transport.blue(),
+                    target.italic(),
+                    headers
+                )
+                .into_diagnostic()?;
+            }
+        }
+
+        // Display prompt commands section
+        if !inspection.prompt_commands.is_empty() {
+            let (section_branch, section_prefix) = next_section();
+            writeln!(output, " {}💬 {}", section_branch, "Prompt commands".bold())
+                .into_diagnostic()?;
+
+            let commands_count = inspection.prompt_commands.len();
+            for (idx, (command_name, command)) in inspection.prompt_commands.iter().enumerate() {
+                let is_last = idx == commands_count - 1;
+                let branch = if is_last { "└─" } else { "├─" };
+                let continuation = if is_last { "   " } else { " │ " };
+                writeln!(
+                    output,
+                    "{} {} {}{}",
+                    section_prefix,
+                    branch,
+                    command_name.cyan(),
+                    if command.use_sub_agent {
+                        format!(" {}", "sub-agent".magenta())
+                    } else {
+                        String::new()
+                    }
+                )
+                .into_diagnostic()?;
+                if !no_descriptions {
+                    let item_prefix = format!("{}{}  ", section_prefix, continuation);
+                    writeln!(
+                        output,
+                        "{}",
+                        wrap_with_prefix(&command.description, &item_prefix, terminal_width)
+                    )
+                    .into_diagnostic()?;
+                }
+            }
+        }
+
+        // Display deny rules section
+        if !inspection.deny_rules.is_empty() {
+            let (section_branch, section_prefix) = next_section();
+            writeln!(output, " {}🚫 {}", section_branch, "Deny rules".bold()).into_diagnostic()?;
+
+            let tools_count = inspection.deny_rules.len();
+            for (idx, (tool, patterns)) in inspection.deny_rules.iter().enumerate() {
+                let branch = if idx == tools_count - 1 {
+                    "└─"
+                } else {
+                    "├─"
+                };
+                writeln!(
+                    output,
+                    "{} {} {}: {}",
+                    section_prefix,
+                    branch,
+                    tool.cyan(),
+                    patterns.iter().map(|p| p.red().to_string()).join(", ")
+                )
+                .into_diagnostic()?;
+            }
+        }
+
         // Display config options section
         if !inspection.options.is_empty() {
-            let section_branch = if inspection.riglets.is_empty() {
-                "└─"
-            } else {
-                "└─"
-            };
-            let section_prefix = "   ";
+            let (section_branch, section_prefix) = next_section();
 
             writeln!(output, " {}⚙️  {}", section_branch, "Configuration".bold())
                 .into_diagnostic()?;
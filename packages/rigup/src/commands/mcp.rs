@@ -0,0 +1,241 @@
+use crate::commands::build::build_store_path;
+use crate::error::RigupError;
+use crate::extensions::RigExtensions;
+use crate::mcp::{RigServer, RigletTools};
+use crate::mcp_client::{check_server, ServerEndpoint};
+use crate::nix::{
+    get_system, parse_flake_ref, resolve_flake_path, rig_installable, run_nix_eval_json,
+    RigInstallable,
+};
+use crate::nix_expr::AttrPath;
+use crate::overrides::ConfigOverrides;
+use crate::prompt_template::PromptCommand;
+use miette::{IntoDiagnostic, Result};
+use owo_colors::OwoColorize;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// What the MCP server needs from the rig besides its home
+#[derive(Debug, Deserialize)]
+struct ServedRig {
+    prompts: BTreeMap<String, PromptCommand>,
+    riglets: BTreeMap<String, RigletTools>,
+}
+
+/// Build a rig's home and serve it over MCP on stdin/stdout, until the client closes stdin.
+/// Progress is reported on stderr, so that stdout only carries the protocol's messages
+pub fn serve_rig(
+    flake_ref: Option<String>,
+    tool_timeout: Duration,
+    no_stage: bool,
+    extensions: &RigExtensions,
+    overrides: &ConfigOverrides,
+) -> Result<()> {
+    let system = get_system();
+    let (flake_path, rig) = parse_flake_ref(flake_ref.as_deref())?;
+    let rig = extensions.apply(rig);
+
+    // Build the home without linking it: the server reads it from the Nix store
+    let home = rig_installable(&flake_path, &rig, &system, "home", no_stage, overrides)?;
+    let home_path = build_store_path(&home)?;
+    let flake_expr = resolve_flake_path(&flake_path, no_stage)?;
+    let rig_expr = overrides.rig_expr(&flake_expr, &rig)?;
+    let eval_expr = format!(
+        r#"
+            let
+                rig = {rig};
+            in {{
+                prompts = builtins.mapAttrs (_: cmd: {{
+                    inherit (cmd) description template;
+                }}) (rig.promptCommands or {{ }});
+                riglets = builtins.mapAttrs (_: meta: {{
+                    inherit (meta) description commandNames;
+                }}) rig.meta;
+            }}
+        "#,
+        rig = rig_expr,
+    );
+    let served: ServedRig =
+        serde_json::from_value(run_nix_eval_json(&flake_expr, &eval_expr)?).into_diagnostic()?;
+
+    let server = RigServer {
+        rig: rig.to_string(),
+        home: home_path,
+        prompts: served.prompts,
+        riglets: served.riglets,
+        tool_timeout,
+    };
+    eprintln!(
+        "> Serving rig `{}` over MCP on stdio ({} prompts)",
+        server.rig,
+        server.prompts.len()
+    );
+    server.serve(std::io::stdin().lock(), std::io::stdout().lock())
+}
+
+/// An MCP server of a rig, as `mcpServers` in buildRig.nix gives it
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct McpServerConfig {
+    /// Null for servers that no riglet declares, and with rigup libs older than this field
+    riglet_name: Option<String>,
+    /// Name of the executable of a local server
+    main_program: Option<String>,
+    url: Option<String>,
+    #[serde(default, rename = "useSSE")]
+    use_sse: bool,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+}
+
+/// Start or connect to each MCP server of a rig and go through the MCP handshake (`initialize`
+/// then `tools/list`), reporting what each server says about itself. Local servers are built
+/// first. Servers that fail are reported as diagnostics naming the riglet that declares them
+pub fn check_mcp_servers(
+    flake_ref: Option<String>,
+    timeout: Duration,
+    no_stage: bool,
+    extensions: &RigExtensions,
+    overrides: &ConfigOverrides,
+) -> Result<()> {
+    let (flake_path, rig) = parse_flake_ref(flake_ref.as_deref())?;
+    let rig = extensions.apply(rig);
+    let flake_expr = resolve_flake_path(&flake_path, no_stage)?;
+    let rig_expr = overrides.rig_expr(&flake_expr, &rig)?;
+    let eval_expr = format!(
+        r#"
+            let
+                rig = {rig};
+            in builtins.mapAttrs (name: server: {{
+                rigletName = rig.mcpServerRiglets.${{name}} or null;
+                mainProgram =
+                    if server ? command then
+                        server.command.meta.mainProgram or (builtins.parseDrvName server.command.name).name
+                    else null;
+                url = server.url or null;
+                useSSE = server.useSSE or false;
+                headers = server.headers or {{ }};
+            }}) (rig.mcpServers or {{ }})
+        "#,
+        rig = rig_expr,
+    );
+    let servers: BTreeMap<String, McpServerConfig> =
+        serde_json::from_value(run_nix_eval_json(&flake_expr, &eval_expr)?).into_diagnostic()?;
+    if servers.is_empty() {
+        eprintln!("> Rig `{}` has no MCP servers", rig);
+        return Ok(());
+    }
+
+    let mut failures = Vec::new();
+    for (name, server) in &servers {
+        let failed = |reason: String, help: Option<String>| RigupError::McpServerFailed {
+            server: name.clone(),
+            riglet: server.riglet_name.clone(),
+            reason,
+            help,
+        };
+        let endpoint = match (&server.main_program, &server.url) {
+            (Some(main_program), _) => {
+                let command = rig_expr.select(&AttrPath::new(["mcpServers", name, "command"]));
+                let label = format!("command of MCP server `{}`", name);
+                match build_store_path(&RigInstallable::from_expr(&flake_expr, label, &command)) {
+                    Ok(path) => ServerEndpoint::Command(path.join("bin").join(main_program)),
+                    Err(error) => {
+                        let reason = "its command failed to build".to_string();
+                        failures.push(failed(reason, Some(error.to_string())));
+                        continue;
+                    }
+                }
+            }
+            (None, Some(url)) => ServerEndpoint::Http {
+                url: url.clone(),
+                headers: server.headers.clone(),
+                sse: server.use_sse,
+            },
+            (None, None) => {
+                let reason = "it has neither a command nor a url".to_string();
+                failures.push(failed(reason, None));
+                continue;
+            }
+        };
+
+        let declared_by = server
+            .riglet_name
+            .as_ref()
+            .map(|riglet| format!("riglet {}, ", riglet))
+            .unwrap_or_default();
+        let server_label = format!(
+            "{} ({}{})",
+            name.bold(),
+            declared_by,
+            endpoint.transport_name()
+        );
+        match check_server(&endpoint, timeout) {
+            Ok(report) => {
+                let tools = match report.tool_count {
+                    Some(count) => format!("{} tools", count),
+                    None => "no tools capability".to_string(),
+                };
+                eprintln!(
+                    "> {} {}: {} {}, protocol {}, {}",
+                    "✓".green(),
+                    server_label,
+                    report.name,
+                    report.version,
+                    report.protocol_version,
+                    tools
+                );
+            }
+            Err(failure) => {
+                eprintln!("> {} {}: {}", "✗".red(), server_label, failure.reason);
+                let help = match failure.stderr {
+                    Some(stderr) => Some(format!("The server printed on stderr:\n{}", stderr)),
+                    None if failure.reason.contains("did not answer") => {
+                        Some("Use `--timeout` to wait longer".to_string())
+                    }
+                    None => None,
+                };
+                failures.push(failed(failure.reason, help));
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        return Err(RigupError::McpCheckFailed {
+            total: servers.len(),
+            failures,
+        }
+        .into());
+    }
+    eprintln!(
+        "> All {} MCP servers of rig `{}` answered",
+        servers.len(),
+        rig
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_config() {
+        let server: McpServerConfig = serde_json::from_value(serde_json::json!({
+            "rigletName": "docs",
+            "mainProgram": null,
+            "url": "https://docs.example/sse",
+            "useSSE": true,
+            "headers": { "Authorization": "Bearer x" },
+        }))
+        .unwrap();
+        assert_eq!(server.riglet_name.as_deref(), Some("docs"));
+        assert!(server.use_sse);
+        assert_eq!(server.headers.len(), 1);
+        let server: McpServerConfig =
+            serde_json::from_value(serde_json::json!({ "mainProgram": "local" })).unwrap();
+        assert_eq!(server.riglet_name, None);
+        assert!(!server.use_sse);
+    }
+}
@@ -1,16 +1,39 @@
+pub mod audit;
 pub mod browse;
 pub mod build;
-
+pub mod config;
+pub mod diff;
+pub mod edit;
 pub mod inspect;
+pub mod mcp;
 pub mod new;
+pub mod permissions;
+pub mod prompt;
+pub mod riglet_graph;
+pub mod rigs;
 pub mod run;
+pub mod search;
 pub mod shell;
 pub mod show;
+pub mod try_rig;
+pub mod validate;
 
+pub use audit::audit_rig;
 pub use browse::browse_rig_docs;
 pub use build::build_rig;
+pub use config::{config_get, config_set, config_unset};
+pub use diff::diff_rigs;
+pub use edit::{add_riglets, create_rig, delete_rig, remove_riglets, rename_rig};
 pub use inspect::inspect_rig;
+pub use mcp::{check_mcp_servers, serve_rig};
 pub use new::new_project;
+pub use permissions::{check_permission, list_permissions};
+pub use prompt::{list_prompts, render_prompt};
+pub use riglet_graph::show_riglet_graph;
+pub use rigs::list_rigs;
 pub use run::run_entrypoint;
+pub use search::search_riglets;
 pub use shell::enter_shell;
 pub use show::show_flake;
+pub use try_rig::try_riglets;
+pub use validate::validate_project;
@@ -0,0 +1,177 @@
+use crate::cache::cached_nix_eval_json;
+use crate::display::{with_output, wrap_with_prefix};
+use crate::error::RigupError;
+use crate::extensions::RigExtensions;
+use crate::nix::{parse_flake_ref, resolve_flake_path};
+use crate::overrides::ConfigOverrides;
+use crate::permissions::{Permissions, RigDenyRules, Verdict};
+use itertools::Itertools;
+use miette::{IntoDiagnostic, Result};
+use owo_colors::OwoColorize;
+use serde::Deserialize;
+
+/// What entrypoints build their permissions from (see `allExeNames`, `denyRules` and
+/// `rigletDenyRules` in buildRig.nix)
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RigPermissions {
+    all_exe_names: Vec<String>,
+    #[serde(flatten)]
+    deny_rules: RigDenyRules,
+}
+
+/// Evaluate the permissions that the entrypoints of a rig grant
+fn fetch_permissions(
+    flake_ref: Option<&str>,
+    no_stage: bool,
+    refresh: bool,
+    extensions: &RigExtensions,
+    overrides: &ConfigOverrides,
+) -> Result<(String, Permissions)> {
+    let (flake_path, rig_attrpath) = parse_flake_ref(flake_ref)?;
+    let rig_attrpath = extensions.apply(rig_attrpath);
+    let flake_expr = resolve_flake_path(&flake_path, no_stage)?;
+    let rig = overrides.rig_expr(&flake_expr, &rig_attrpath)?;
+    eprintln!("> Evaluating the permissions of rig `{}`", rig_attrpath);
+    let eval_expr = format!(
+        r#"
+            let
+                rig = {rig};
+            in {{
+                inherit (rig) allExeNames;
+                denyRules = rig.denyRules or {{ }};
+                rigletDenyRules = rig.rigletDenyRules or null;
+            }}
+        "#,
+        rig = rig,
+    );
+    let rig_permissions: RigPermissions =
+        serde_json::from_value(cached_nix_eval_json(&flake_expr, &eval_expr, refresh)?)
+            .map_err(|e| RigupError::MetadataParseError { source: e })?;
+
+    let permissions = Permissions {
+        allowed: rig_permissions.all_exe_names.into_iter().collect(),
+        denied: rig_permissions.deny_rules.into_rules(),
+    };
+    Ok((rig_attrpath.to_string(), permissions))
+}
+
+/// Tell whether the entrypoints of a rig would let an agent run a shell command without asking.
+/// Fails if the command is denied
+pub fn check_permission(
+    flake_ref: String,
+    command: Vec<String>,
+    no_stage: bool,
+    refresh: bool,
+    extensions: &RigExtensions,
+    overrides: &ConfigOverrides,
+) -> Result<()> {
+    let (rig, permissions) =
+        fetch_permissions(Some(&flake_ref), no_stage, refresh, extensions, overrides)?;
+    let command_line = command.join(" ");
+    match permissions.check(&command_line) {
+        Verdict::Allowed => {
+            println!(
+                "{} `{}` is allowed: rig `{}` provides its executables and no deny rule matches",
+                "✓".green(),
+                command_line,
+                rig
+            );
+            Ok(())
+        }
+        Verdict::NotCovered(command) => {
+            println!(
+                "{} `{}` is not covered: `{}` is not an executable of rig `{}`, so the harness \
+                 will ask before running it (or apply its own settings)",
+                "?".yellow(),
+                command.join(" "),
+                command[0],
+                rig
+            );
+            Ok(())
+        }
+        Verdict::Denied(command, rule) => Err(RigupError::CommandDenied {
+            command: command.join(" "),
+            rule: format!("{} {}", rule.tool, rule.pattern),
+            riglet: rule.riglet.clone(),
+        }
+        .into()),
+        Verdict::Uncheckable(reason) => Err(RigupError::CommandUncheckable {
+            command: command_line,
+            reason: reason.to_string(),
+        }
+        .into()),
+    }
+}
+
+/// Print what the entrypoints of a rig let an agent run: the rig's executables with any
+/// arguments, but for the commands its deny rules match
+pub fn list_permissions(
+    flake_ref: Option<String>,
+    no_pager: bool,
+    no_stage: bool,
+    refresh: bool,
+    extensions: &RigExtensions,
+    overrides: &ConfigOverrides,
+) -> Result<()> {
+    let (_, permissions) = fetch_permissions(
+        flake_ref.as_deref(),
+        no_stage,
+        refresh,
+        extensions,
+        overrides,
+    )?;
+    eprintln!();
+
+    let terminal_width = terminal_size::terminal_size()
+        .map(|(w, _)| w.0 as usize)
+        .unwrap_or(80);
+
+    with_output(no_pager, |output| {
+        writeln!(
+            output,
+            "✅ {} ({} executables, with any arguments)",
+            "Allowed".bold(),
+            permissions.allowed.len()
+        )
+        .into_diagnostic()?;
+        let executables = permissions.allowed.iter().join(", ");
+        writeln!(
+            output,
+            "{}",
+            wrap_with_prefix(&executables, "   ", terminal_width).cyan()
+        )
+        .into_diagnostic()?;
+
+        writeln!(output).into_diagnostic()?;
+        if permissions.denied.is_empty() {
+            writeln!(output, "🚫 {}: none", "Denied".bold()).into_diagnostic()?;
+            return Ok(());
+        }
+        writeln!(output, "🚫 {}", "Denied".bold()).into_diagnostic()?;
+        let rules: Vec<(String, &Option<String>)> = permissions
+            .denied
+            .iter()
+            .map(|rule| (format!("{} {} …", rule.tool, rule.pattern), &rule.riglet))
+            .sorted()
+            .collect();
+        let width = rules
+            .iter()
+            .map(|(rule, _)| rule.chars().count())
+            .max()
+            .unwrap_or(0);
+        for (rule, riglet) in rules {
+            match riglet {
+                Some(riglet) => writeln!(
+                    output,
+                    "   {}  {}",
+                    format!("{:width$}", rule, width = width).red(),
+                    format!("from riglet {}", riglet).dimmed()
+                ),
+                None => writeln!(output, "   {}", rule.red()),
+            }
+            .into_diagnostic()?;
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,139 @@
+use crate::cache::cached_nix_eval_json;
+use crate::display::with_output;
+use crate::error::RigupError;
+use crate::extensions::RigExtensions;
+use crate::nix::{parse_flake_ref, resolve_flake_path};
+use crate::overrides::ConfigOverrides;
+use crate::prompt_template::{self, PromptCommand};
+use crate::suggest::did_you_mean;
+use miette::{IntoDiagnostic, Result};
+use owo_colors::OwoColorize;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Evaluate the prompt commands of a rig, by namespaced `riglet:command` name
+fn fetch_prompt_commands(
+    flake_ref: Option<&str>,
+    no_stage: bool,
+    refresh: bool,
+    extensions: &RigExtensions,
+    overrides: &ConfigOverrides,
+) -> Result<(String, BTreeMap<String, PromptCommand>)> {
+    let (flake_path, rig_attrpath) = parse_flake_ref(flake_ref)?;
+    let rig_attrpath = extensions.apply(rig_attrpath);
+    let flake_expr = resolve_flake_path(&flake_path, no_stage)?;
+    let rig = overrides.rig_expr(&flake_expr, &rig_attrpath)?;
+    let eval_expr = format!(
+        r#"
+            let
+                rig = {rig};
+            in builtins.mapAttrs (_: cmd: {{
+                inherit (cmd) description template useSubAgent;
+            }}) (rig.promptCommands or {{ }})
+        "#,
+        rig = rig,
+    );
+    let commands = serde_json::from_value(cached_nix_eval_json(&flake_expr, &eval_expr, refresh)?)
+        .map_err(|e| RigupError::MetadataParseError { source: e })?;
+    Ok((rig_attrpath.to_string(), commands))
+}
+
+/// How a prompt command takes its arguments, e.g. `<$1> <$2>` or `[$ARGUMENTS...]`
+fn usage(template: &str) -> String {
+    let mut usage: Vec<String> = (1..=prompt_template::positional_count(template))
+        .map(|position| format!("<${}>", position))
+        .collect();
+    if prompt_template::uses_all_arguments(template) {
+        usage.push("[$ARGUMENTS...]".to_string());
+    }
+    usage.join(" ")
+}
+
+/// List the prompt commands of a rig, with the arguments they take and their description
+pub fn list_prompts(
+    flake_ref: Option<String>,
+    no_pager: bool,
+    no_stage: bool,
+    refresh: bool,
+    extensions: &RigExtensions,
+    overrides: &ConfigOverrides,
+) -> Result<()> {
+    let (rig, commands) = fetch_prompt_commands(
+        flake_ref.as_deref(),
+        no_stage,
+        refresh,
+        extensions,
+        overrides,
+    )?;
+    if commands.is_empty() {
+        eprintln!("> Rig `{}` has no prompt commands", rig);
+        return Ok(());
+    }
+    eprintln!("> {} prompt commands in rig `{}`", commands.len(), rig);
+    eprintln!();
+
+    with_output(no_pager, |output| {
+        for (name, command) in &commands {
+            write!(output, "{}", name.bold()).into_diagnostic()?;
+            let usage = usage(&command.template);
+            if !usage.is_empty() {
+                write!(output, " {}", usage.cyan()).into_diagnostic()?;
+            }
+            if command.use_sub_agent {
+                write!(output, " {}", "(sub-agent)".dimmed()).into_diagnostic()?;
+            }
+            writeln!(output).into_diagnostic()?;
+            writeln!(output, "    {}", command.description).into_diagnostic()?;
+        }
+        Ok(())
+    })
+}
+
+/// Print a prompt command of a rig to stdout, with its arguments substituted like harnesses do:
+/// `$ARGUMENTS` by all of them joined with spaces, and `$1`, `$2`... by each of them
+pub fn render_prompt(
+    flake_ref: String,
+    name: String,
+    args: Vec<String>,
+    no_stage: bool,
+    refresh: bool,
+    extensions: &RigExtensions,
+    overrides: &ConfigOverrides,
+) -> Result<()> {
+    let (rig, commands) =
+        fetch_prompt_commands(Some(&flake_ref), no_stage, refresh, extensions, overrides)?;
+    let command = commands.get(&name).ok_or_else(|| RigupError::UnknownName {
+        kind: "prompt command",
+        name: name.clone(),
+        scope: format!("rig `{}`", rig),
+        did_you_mean: did_you_mean(&name, commands.keys()),
+    })?;
+
+    let expected = prompt_template::positional_count(&command.template);
+    if args.len() < expected {
+        eprintln!(
+            "> `{}` uses {} arguments but only {} were given, the missing ones are left empty",
+            name,
+            expected,
+            args.len()
+        );
+    }
+    let prompt = prompt_template::render(&command.template, &args.join(" "), &args);
+    print!("{}", prompt);
+    if !prompt.ends_with('\n') {
+        println!();
+    }
+    std::io::stdout().flush().into_diagnostic()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage() {
+        assert_eq!(usage("Review $2 against $1"), "<$1> <$2>");
+        assert_eq!(usage("Fix $1: $ARGUMENTS"), "<$1> [$ARGUMENTS...]");
+        assert_eq!(usage("No arguments"), "");
+    }
+}
@@ -0,0 +1,179 @@
+use crate::cache::cached_nix_eval_json;
+use crate::display::with_output;
+use crate::extensions::RigExtensions;
+use crate::graph::{Graph, GraphFormat, GraphNode};
+use crate::nix::{
+    build_flake_ref, check_rig_ref, get_system, parse_flake_ref, resolve_flake_path, rig_expr,
+};
+use crate::nix_expr::NixExpr;
+use itertools::Itertools;
+use miette::{IntoDiagnostic, Result};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// How a riglet was pulled into a rig (see `rigletImports` in buildRig.nix)
+#[derive(Debug, Deserialize)]
+struct RigletImports {
+    /// Whether the riglet is listed by the rig or one of the rigs it extends
+    explicit: bool,
+    /// The riglets that the riglet's module imports
+    imports: Vec<String>,
+}
+
+/// For each riglet listed explicitly, the other explicit riglets that already import it, directly
+/// or not. Listing the former is redundant
+fn redundant_riglets(riglets: &BTreeMap<String, RigletImports>) -> BTreeMap<&str, BTreeSet<&str>> {
+    let mut redundant: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for (name, riglet) in riglets.iter().filter(|(_, riglet)| riglet.explicit) {
+        let mut reached = BTreeSet::new();
+        let mut stack: Vec<&str> = riglet.imports.iter().map(String::as_str).collect();
+        while let Some(imported) = stack.pop() {
+            if imported == name || !reached.insert(imported) {
+                continue;
+            }
+            if let Some(imported_riglet) = riglets.get(imported) {
+                stack.extend(imported_riglet.imports.iter().map(String::as_str));
+            }
+        }
+        for imported in reached {
+            if riglets.get(imported).is_some_and(|riglet| riglet.explicit) {
+                redundant.entry(imported).or_default().insert(name);
+            }
+        }
+    }
+    redundant
+}
+
+/// The import graph of a rig's riglets, edges going from riglets to those they import. Riglets
+/// that were only pulled in by imports are external nodes
+fn import_graph(riglets: &BTreeMap<String, RigletImports>) -> Graph {
+    let redundant = redundant_riglets(riglets);
+    let mut graph = Graph::new("🧩");
+    let indices: BTreeMap<&str, usize> = riglets
+        .iter()
+        .map(|(name, riglet)| {
+            let details = redundant
+                .get(name.as_str())
+                .map(|importers| {
+                    format!(
+                        "! listed explicitly, but already imported by {}",
+                        importers.iter().join(", ")
+                    )
+                })
+                .into_iter()
+                .collect();
+            let index = graph.add_node(GraphNode {
+                label: name.clone(),
+                note: (!riglet.explicit).then(|| "imported".to_string()),
+                details,
+                external: !riglet.explicit,
+            });
+            (name.as_str(), index)
+        })
+        .collect();
+    for (name, riglet) in riglets {
+        for imported in &riglet.imports {
+            if let Some(imported_index) = indices.get(imported.as_str()) {
+                graph.add_edge(indices[name.as_str()], *imported_index, false);
+            }
+        }
+    }
+    graph
+}
+
+/// Show which riglets of a rig were listed explicitly (by the rig or the rigs it extends) and
+/// which were pulled in by the imports of other riglets, and flag the riglets that are listed
+/// although other listed riglets already import them
+pub fn show_riglet_graph(
+    flake_ref: Option<String>,
+    format: GraphFormat,
+    no_pager: bool,
+    no_stage: bool,
+    refresh: bool,
+    extensions: &RigExtensions,
+) -> Result<()> {
+    let (flake_path, rig_attrpath) = parse_flake_ref(flake_ref.as_deref())?;
+    let rig_attrpath = extensions.apply(rig_attrpath);
+    let flake_expr = resolve_flake_path(&flake_path, no_stage)?;
+    let flake_ref = build_flake_ref(&flake_path, &rig_attrpath, &get_system(), None, no_stage)?;
+    eprintln!("> Evaluating the riglet imports of {}", &flake_ref);
+
+    let eval_expr = format!(
+        r#"
+            let
+                rig = {rig} or (throw ("Flake " + {flake} + " does not output " + {rig_name}));
+            in rig.rigletImports or (throw ("Rig " + {rig_name} + " was built by a version of rigup's lib that does not record riglet imports. Update the flake's rigup input"))
+        "#,
+        rig = rig_expr(&flake_expr, &rig_attrpath),
+        flake = NixExpr::string(&flake_expr),
+        rig_name = NixExpr::string(&rig_attrpath.to_string()),
+    );
+    let riglets = check_rig_ref(
+        cached_nix_eval_json(&flake_expr, &eval_expr, refresh),
+        &flake_expr,
+        &rig_attrpath,
+        false,
+    )?;
+    let riglets: BTreeMap<String, RigletImports> =
+        serde_json::from_value(riglets).into_diagnostic()?;
+
+    let graph = import_graph(&riglets);
+    match format {
+        GraphFormat::Tree => {
+            let explicit_count = riglets.values().filter(|riglet| riglet.explicit).count();
+            eprintln!(
+                "> {} riglets: {} listed explicitly, {} pulled in by imports",
+                riglets.len(),
+                explicit_count,
+                riglets.len() - explicit_count
+            );
+            eprintln!();
+            with_output(no_pager, |output| graph.write_tree(output))
+        }
+        GraphFormat::Dot => {
+            println!("{}", graph.to_dot(&rig_attrpath.to_string()));
+            Ok(())
+        }
+        GraphFormat::Mermaid => {
+            println!("{}", graph.to_mermaid());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redundant_riglets() {
+        let riglet = |explicit, imports: &[&str]| RigletImports {
+            explicit,
+            imports: imports.iter().map(|name| name.to_string()).collect(),
+        };
+        let riglets = BTreeMap::from([
+            ("agent-identity".to_string(), riglet(true, &[])),
+            ("claude-code".to_string(), riglet(true, &["models"])),
+            ("git-setup".to_string(), riglet(true, &["agent-identity"])),
+            ("jj".to_string(), riglet(true, &["git-setup"])),
+            ("models".to_string(), riglet(false, &[])),
+        ]);
+        let redundant = redundant_riglets(&riglets);
+        assert_eq!(
+            redundant,
+            BTreeMap::from([
+                ("agent-identity", BTreeSet::from(["git-setup", "jj"])),
+                ("git-setup", BTreeSet::from(["jj"])),
+            ])
+        );
+
+        let graph = import_graph(&riglets);
+        assert_eq!(graph.nodes[4].note.as_deref(), Some("imported"));
+        assert_eq!(
+            graph.nodes[0].details,
+            ["! listed explicitly, but already imported by git-setup, jj"]
+        );
+        let edges: Vec<(usize, usize)> = graph.edges.iter().map(|e| (e.from, e.to)).collect();
+        assert_eq!(edges, [(1, 4), (2, 0), (3, 2)]);
+    }
+}
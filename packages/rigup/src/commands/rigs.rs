@@ -0,0 +1,271 @@
+use crate::commands::validate::{find_project_root, TOML_FILES};
+use crate::display::with_output;
+use crate::graph::{Graph, GraphFormat, GraphNode};
+use crate::rigup_toml::TomlFile;
+use itertools::Itertools;
+use miette::{IntoDiagnostic, Result};
+use owo_colors::OwoColorize;
+use std::collections::{BTreeMap, BTreeSet};
+
+const RIG_ICON: &str = "📟";
+
+/// A reference to a riglet or a rig, as written in rigup.toml: just `<name>` for the project's
+/// own ones
+fn input_ref(input: &str, name: &str) -> String {
+    if input == "self" {
+        name.to_string()
+    } else {
+        format!("{}.{}", input, name)
+    }
+}
+
+/// A rig of the project, as its TOML files define it
+struct RigDefinition<'a> {
+    name: String,
+    file: &'a TomlFile,
+    extends: Vec<(String, String)>,
+}
+
+impl RigDefinition<'_> {
+    fn riglets(&self) -> Vec<(String, String)> {
+        self.file.rig_entries(&self.name, "riglets")
+    }
+
+    fn config(&self) -> Vec<String> {
+        self.file.config_value_paths(&self.name)
+    }
+}
+
+fn load_rigs(files: &[TomlFile]) -> Vec<RigDefinition<'_>> {
+    files
+        .iter()
+        .flat_map(|file| {
+            file.rig_names().into_iter().map(move |name| RigDefinition {
+                extends: file.rig_entries(&name, "extends"),
+                name,
+                file,
+            })
+        })
+        .collect()
+}
+
+/// The riglets and config paths that a project rig gets from the project rigs it extends,
+/// directly or not. The rigs of other inputs are unknown without Nix
+fn inherited(
+    rigs: &[RigDefinition],
+    rig: &RigDefinition,
+) -> (BTreeSet<(String, String)>, BTreeSet<String>) {
+    let mut riglets = BTreeSet::new();
+    let mut config = BTreeSet::new();
+    let mut visited = BTreeSet::new();
+    let mut stack: Vec<&str> = rig
+        .extends
+        .iter()
+        .filter(|(input, _)| input == "self")
+        .map(|(_, base)| base.as_str())
+        .collect();
+    while let Some(name) = stack.pop() {
+        if !visited.insert(name) {
+            continue;
+        }
+        let Some(base) = rigs.iter().find(|base| base.name == name) else {
+            continue;
+        };
+        riglets.extend(base.riglets());
+        config.extend(base.config());
+        stack.extend(
+            base.extends
+                .iter()
+                .filter(|(input, _)| input == "self")
+                .map(|(_, base)| base.as_str()),
+        );
+    }
+    (riglets, config)
+}
+
+/// Describe what a rig adds on top of the rigs it extends: its riglets (noting those it lists
+/// again), the config values it sets and those it overrides
+fn rig_details(rigs: &[RigDefinition], rig: &RigDefinition) -> Vec<String> {
+    let (inherited_riglets, inherited_config) = inherited(rigs, rig);
+    let (repeated_riglets, new_riglets): (Vec<_>, Vec<_>) = rig
+        .riglets()
+        .into_iter()
+        .partition(|riglet| inherited_riglets.contains(riglet));
+    let (overridden_config, new_config): (Vec<_>, Vec<_>) =
+        rig.config().into_iter().partition(|path| {
+            // Setting a table replaces the values set inside it, and the other way around
+            inherited_config.iter().any(|inherited| {
+                let (short, long) = if inherited.len() < path.len() {
+                    (inherited, path)
+                } else {
+                    (path, inherited)
+                };
+                long.strip_prefix(short.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+            })
+        });
+
+    let riglet_list = |riglets: Vec<(String, String)>| {
+        riglets
+            .iter()
+            .map(|(input, riglet)| input_ref(input, riglet))
+            .join(", ")
+    };
+    let mut details = Vec::new();
+    if !new_riglets.is_empty() {
+        details.push(format!("+ riglets: {}", riglet_list(new_riglets)));
+    }
+    if !repeated_riglets.is_empty() {
+        details.push(format!(
+            "= riglets already inherited: {}",
+            riglet_list(repeated_riglets)
+        ));
+    }
+    if !new_config.is_empty() {
+        details.push(format!("+ config: {}", new_config.join(", ")));
+    }
+    if !overridden_config.is_empty() {
+        details.push(format!("~ config: {}", overridden_config.join(", ")));
+    }
+    details
+}
+
+/// The `extends` graph of the project's rigs, edges going from base rigs to the rigs that extend
+/// them. Rigs of other inputs, and missing rigs, are added as external nodes
+fn extends_graph(rigs: &[RigDefinition]) -> Graph {
+    let mut graph = Graph::new(RIG_ICON);
+    let mut indices: BTreeMap<String, usize> = BTreeMap::new();
+    let show_files = rigs.iter().map(|rig| rig.file.name()).unique().count() > 1;
+    for rig in rigs {
+        // A rig defined twice is invalid (see `rigup validate`): keep the first one
+        if indices.contains_key(&rig.name) {
+            continue;
+        }
+        let index = graph.add_node(GraphNode {
+            label: rig.name.clone(),
+            note: show_files.then(|| rig.file.name().to_string()),
+            details: rig_details(rigs, rig),
+            external: false,
+        });
+        indices.insert(rig.name.clone(), index);
+    }
+    for rig in rigs {
+        let derived = indices[&rig.name];
+        for (input, base) in &rig.extends {
+            let label = input_ref(input, base);
+            let base_index = match indices.get(&label) {
+                Some(index) => *index,
+                None => {
+                    let note = if input == "self" {
+                        "not defined".to_string()
+                    } else {
+                        format!("from input `{}`", input)
+                    };
+                    let index = graph.add_node(GraphNode {
+                        label: label.clone(),
+                        note: Some(note),
+                        details: Vec::new(),
+                        external: true,
+                    });
+                    indices.insert(label, index);
+                    index
+                }
+            };
+            graph.add_edge(base_index, derived, false);
+        }
+    }
+    graph
+}
+
+/// List the rigs that the current project defines, reading its TOML files without Nix. With a
+/// `format`, show instead how they extend each other: as a tree detailing what each rig adds to
+/// its base rigs, or as a DOT or Mermaid graph
+pub fn list_rigs(format: Option<GraphFormat>, no_pager: bool) -> Result<()> {
+    let flake_root = find_project_root()?;
+    let files: Vec<TomlFile> = TOML_FILES
+        .iter()
+        .filter_map(|name| TomlFile::load_checked(&flake_root, name))
+        .collect::<Result<_, _>>()?;
+    let rigs = load_rigs(&files);
+    if rigs.is_empty() {
+        eprintln!("> No rigs defined in rigup.toml or rigup.local.toml");
+        return Ok(());
+    }
+
+    match format {
+        None => with_output(no_pager, |output| {
+            for rig in &rigs {
+                let mut line = format!("{} {}", RIG_ICON, rig.name.cyan().bold());
+                if files.len() > 1 {
+                    line.push_str(&format!(
+                        " {}",
+                        format!("({})", rig.file.name()).bright_black()
+                    ));
+                }
+                if !rig.extends.is_empty() {
+                    let bases = rig
+                        .extends
+                        .iter()
+                        .map(|(input, base)| input_ref(input, base))
+                        .join(", ");
+                    line.push_str(&format!(" {} {}", "extends".bright_black(), bases));
+                }
+                writeln!(output, "{}", line).into_diagnostic()?;
+            }
+            Ok(())
+        }),
+        Some(GraphFormat::Tree) => {
+            let graph = extends_graph(&rigs);
+            with_output(no_pager, |output| graph.write_tree(output))
+        }
+        Some(GraphFormat::Dot) => {
+            println!("{}", extends_graph(&rigs).to_dot("rigs"));
+            Ok(())
+        }
+        Some(GraphFormat::Mermaid) => {
+            println!("{}", extends_graph(&rigs).to_mermaid());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extends_graph() {
+        let files = [TomlFile::parse(
+            "rigup.toml",
+            r#"
+[rigs.base]
+riglets.self = ["coreutils", "git-setup"]
+config.agent.identity.name = "Bot"
+
+[rigs.default]
+extends = { self = "base", rigup = "example-rig" }
+riglets.self = ["coreutils", "jj"]
+config.agent.identity = { name = "Other" }
+config.jj.editor = "vim"
+"#
+            .to_string(),
+        )
+        .unwrap()];
+        let rigs = load_rigs(&files);
+        let graph = extends_graph(&rigs);
+        let labels: Vec<&str> = graph.nodes.iter().map(|node| node.label.as_str()).collect();
+        assert_eq!(labels, ["base", "default", "rigup.example-rig"]);
+        assert_eq!(
+            graph.nodes[1].details,
+            [
+                "+ riglets: jj",
+                "= riglets already inherited: coreutils",
+                "+ config: jj.editor",
+                "~ config: agent.identity.name",
+            ]
+        );
+        assert_eq!(graph.nodes[2].note.as_deref(), Some("from input `rigup`"));
+        let edges: Vec<(usize, usize)> = graph.edges.iter().map(|e| (e.from, e.to)).collect();
+        assert_eq!(edges, [(0, 1), (2, 1)]);
+    }
+}
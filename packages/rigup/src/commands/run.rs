@@ -1,4 +1,7 @@
-use crate::nix::{build_flake_ref, get_system, parse_flake_ref};
+use crate::commands::build::build_store_path;
+use crate::extensions::RigExtensions;
+use crate::nix::{get_system, parse_flake_ref, rig_installable, RigInstallable};
+use crate::overrides::ConfigOverrides;
 use miette::{diagnostic, IntoDiagnostic, Report, Result};
 use std::process::Command;
 
@@ -6,35 +9,53 @@ pub fn run_entrypoint(
     flake_ref: Option<String>,
     extra_args: &[String],
     no_stage: bool,
+    extensions: &RigExtensions,
+    overrides: &ConfigOverrides,
 ) -> Result<()> {
     let system = get_system();
     let (flake_path, rig) = parse_flake_ref(flake_ref.as_deref())?;
-    let entrypoint_ref = build_flake_ref(&flake_path, &rig, &system, Some("entrypoint"), no_stage)?;
+    let rig = extensions.apply(rig);
+    let entrypoint = rig_installable(
+        &flake_path,
+        &rig,
+        &system,
+        "entrypoint",
+        no_stage,
+        overrides,
+    )?;
 
-    eprintln!("> Running {}", entrypoint_ref);
-
-    // Use nix run directly on the entrypoint derivation
-    let mut cmd = Command::new("nix");
-    cmd.args(&["run", &entrypoint_ref]);
+    run_installable(&entrypoint, extra_args)
+}
 
-    // Forward extra arguments to the entrypoint after --
-    if !extra_args.is_empty() {
-        cmd.arg("--");
-        cmd.args(extra_args);
-    }
+/// Build a rig's entrypoint, then run the executable in its `bin/` folder. Building first keeps
+/// the failures of Nix, which get diagnosed, apart from the exit code of the entrypoint
+pub fn run_installable(entrypoint: &RigInstallable, extra_args: &[String]) -> Result<()> {
+    let bin = build_store_path(entrypoint)?.join("bin");
+    let executables: Vec<_> = std::fs::read_dir(&bin)
+        .into_diagnostic()?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()
+        .into_diagnostic()?;
+    let [executable] = executables.as_slice() else {
+        return Err(Report::from(diagnostic!(
+            "The entrypoint {} should contain a single executable, found {}",
+            bin.display(),
+            executables.len()
+        )));
+    };
 
-    let status = cmd.status().into_diagnostic()?;
+    eprintln!("> Running {}", entrypoint.label);
+    let status = Command::new(executable)
+        .args(extra_args)
+        .status()
+        .into_diagnostic()?;
 
     if status.success() {
         Ok(())
     } else {
-        Err(Report::from(
-            diagnostic!("nix run exited with code {}", status.code().unwrap_or(1)).with_help(
-                format!(
-                    "Check that rig '{}' exists and does provide an entrypoint",
-                    rig
-                ),
-            ),
-        ))
+        Err(Report::from(diagnostic!(
+            "The entrypoint exited with code {}",
+            status.code().unwrap_or(1)
+        )))
     }
 }
@@ -0,0 +1,222 @@
+use crate::commands::show::list_flake;
+use crate::display::{with_output, wrap_with_prefix};
+use crate::nix::resolve_flake_path;
+use crate::types::RigletMeta;
+use itertools::Itertools;
+use miette::{IntoDiagnostic, Result};
+use owo_colors::OwoColorize;
+
+/// A riglet that matched the search query
+struct SearchHit<'a> {
+    input: &'a str,
+    name: &'a str,
+    meta: &'a RigletMeta,
+    score: f64,
+    /// Names of the metadata fields that matched at least one query term
+    matched_fields: Vec<&'static str>,
+}
+
+/// Score how well a single (lowercased) query term matches a (lowercased) text.
+/// Returns 0 when there is no match at all
+fn score_term(term: &str, text: &str) -> f64 {
+    if text == term {
+        return 1.0;
+    }
+    if text
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| word == term)
+    {
+        // Whole word match
+        return 0.8;
+    }
+    if text.contains(term) {
+        return 0.6;
+    }
+    // Fuzzy match: all chars of the term appear in order in the text. The tighter they
+    // are packed, the better. Too short terms would match almost anything this way
+    if term.chars().count() < 3 {
+        return 0.0;
+    }
+    let term_chars: Vec<char> = term.chars().collect();
+    let text_chars: Vec<char> = text.chars().collect();
+    // Length of the shortest window of the text containing the whole term as a subsequence
+    let shortest_span = (0..text_chars.len())
+        .filter(|&start| text_chars[start] == term_chars[0])
+        .filter_map(|start| {
+            let mut remaining = term_chars[1..].iter().peekable();
+            for (offset, c) in text_chars[start + 1..].iter().enumerate() {
+                match remaining.peek() {
+                    Some(&&expected) if expected == *c => {
+                        remaining.next();
+                    }
+                    Some(_) => {}
+                    None => return Some(offset + 1),
+                }
+            }
+            remaining.peek().is_none().then(|| text_chars.len() - start)
+        })
+        .min();
+    match shortest_span {
+        Some(span) => 0.4 * term_chars.len() as f64 / span as f64,
+        None => 0.0,
+    }
+}
+
+/// Score a riglet against all the query terms. Every term must match at least one field
+fn score_riglet(terms: &[String], name: &str, meta: &RigletMeta) -> (f64, Vec<&'static str>) {
+    // (field name, weight, texts)
+    let fields: [(&'static str, f64, Vec<&str>); 5] = [
+        ("name", 5.0, vec![name]),
+        (
+            "keywords",
+            4.0,
+            meta.keywords.iter().map(String::as_str).collect(),
+        ),
+        (
+            "commandNames",
+            3.0,
+            meta.command_names.iter().map(String::as_str).collect(),
+        ),
+        ("description", 2.0, vec![meta.description.as_str()]),
+        (
+            "whenToUse",
+            1.0,
+            meta.when_to_use.iter().map(String::as_str).collect(),
+        ),
+    ];
+
+    let mut total = 0.0;
+    let mut matched_fields = Vec::new();
+    for term in terms {
+        let mut best_for_term = 0.0;
+        for (field, weight, texts) in &fields {
+            let field_score = texts
+                .iter()
+                .map(|t| score_term(term, &t.to_lowercase()))
+                .fold(0.0, f64::max);
+            if field_score > 0.0 && !matched_fields.contains(field) {
+                matched_fields.push(*field);
+            }
+            best_for_term = f64::max(best_for_term, weight * field_score);
+        }
+        if best_for_term == 0.0 {
+            return (0.0, Vec::new());
+        }
+        total += best_for_term;
+    }
+    (total, matched_fields)
+}
+
+pub fn search_riglets(
+    query: Vec<String>,
+    flake: Option<String>,
+    limit: usize,
+    no_pager: bool,
+    no_stage: bool,
+    refresh: bool,
+) -> Result<()> {
+    let flake_path = flake.unwrap_or_else(|| ".".to_string());
+    let flake_expr = resolve_flake_path(&flake_path, no_stage)?;
+
+    let terms: Vec<String> = query
+        .iter()
+        .flat_map(|q| q.split_whitespace())
+        .map(str::to_lowercase)
+        .collect();
+    if terms.is_empty() {
+        return Err(miette::miette!("Search query is empty"));
+    }
+
+    let all_data = list_flake(&flake_expr, true, refresh)?;
+
+    let terms = &terms;
+    let hits: Vec<SearchHit> = all_data
+        .iter()
+        .flat_map(|(input, data)| {
+            data.riglets.iter().map(move |(name, meta)| {
+                let (score, matched_fields) = score_riglet(terms, name, meta);
+                SearchHit {
+                    input,
+                    name,
+                    meta,
+                    score,
+                    matched_fields,
+                }
+            })
+        })
+        .filter(|hit| hit.score > 0.0)
+        // Best scores first, then alphabetical for stable output
+        .sorted_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| (a.input, a.name).cmp(&(b.input, b.name)))
+        })
+        .take(limit)
+        .collect();
+
+    eprintln!();
+
+    if hits.is_empty() {
+        eprintln!("No riglet matches '{}'", terms.join(" "));
+        return Ok(());
+    }
+
+    let terminal_width = terminal_size::terminal_size()
+        .map(|(w, _)| w.0 as usize)
+        .unwrap_or(80);
+
+    with_output(no_pager, |output| {
+        for hit in &hits {
+            writeln!(
+                output,
+                "🧩 {} {} ({}) {}",
+                hit.name.cyan(),
+                format!("from {}", hit.input).bright_blue(),
+                hit.meta.version,
+                format!("matched {}", hit.matched_fields.join(", ")).bright_black()
+            )
+            .into_diagnostic()?;
+            writeln!(
+                output,
+                "{}",
+                wrap_with_prefix(
+                    &hit.meta.description.bold().to_string(),
+                    "   ",
+                    terminal_width
+                )
+            )
+            .into_diagnostic()?;
+            writeln!(
+                output,
+                "   {} = [\"{}\"]",
+                hit.input.green(),
+                hit.name.green()
+            )
+            .into_diagnostic()?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_term_ordering() {
+        let exact = score_term("jj", "jj");
+        let whole_word = score_term("jj", "jj basics");
+        let prefix = score_term("juju", "jujutsu-basics");
+        let fuzzy = score_term("jjtsu", "jujutsu-basics");
+        assert_eq!(exact, 1.0);
+        assert!(exact > whole_word);
+        assert!(whole_word > prefix);
+        assert!(prefix > fuzzy);
+        assert!(fuzzy > 0.0);
+        // Tightly packed fuzzy matches rank above loose ones
+        assert!(score_term("gtcmt", "git-commit") > score_term("gtcmt", "git-some-thing-commit"));
+        // Short terms and terms out of order don't fuzzy match
+        assert_eq!(score_term("gc", "git-commit"), 0.0);
+        assert_eq!(score_term("tig", "git-commit"), 0.0);
+    }
+}
@@ -1,20 +1,43 @@
-use crate::nix::{build_flake_ref, get_system, parse_flake_ref, run_command_inherit};
+use crate::extensions::RigExtensions;
+use crate::nix::{
+    get_system, parse_flake_ref, rig_installable, run_command_inherit, run_nix, RigInstallable,
+};
+use crate::overrides::ConfigOverrides;
 use miette::Result;
+use std::process::Stdio;
 
-pub fn enter_shell(flake_ref: Option<String>, command: Vec<String>, no_stage: bool) -> Result<()> {
+pub fn enter_shell(
+    flake_ref: Option<String>,
+    command: Vec<String>,
+    no_stage: bool,
+    extensions: &RigExtensions,
+    overrides: &ConfigOverrides,
+) -> Result<()> {
     let system = get_system();
     let (flake_path, rig) = parse_flake_ref(flake_ref.as_deref())?;
-    let full_ref = build_flake_ref(&flake_path, &rig, &system, Some("shell"), no_stage)?;
+    let rig = extensions.apply(rig);
+    let installable = rig_installable(&flake_path, &rig, &system, "shell", no_stage, overrides)?;
 
-    eprintln!("> Opening {}", full_ref);
+    develop_installable(&installable, &command)
+}
+
+/// Open a rig's shell with `nix develop`, or run a command in it. The shell is evaluated first,
+/// so that the failures of Nix, which get diagnosed, are kept apart from the exit code of the
+/// shell
+pub fn develop_installable(shell: &RigInstallable, command: &[String]) -> Result<()> {
+    eprintln!("> Evaluating {}", shell.label);
+    let mut args = vec!["path-info", "--derivation"];
+    args.extend(shell.args.iter().map(|s| s.as_str()));
+    shell.check_failure(run_nix(&shell.flake, &args, Stdio::null()))?;
 
-    let mut args = vec!["develop", &full_ref];
+    eprintln!("> Opening {}", shell.label);
+    let mut args = vec!["develop"];
+    args.extend(shell.args.iter().map(|s| s.as_str()));
 
     if !command.is_empty() {
         args.push("--command");
         args.extend(command.iter().map(|s| s.as_str()));
     }
 
-    run_command_inherit("nix", args)?;
-    Ok(())
+    run_command_inherit("nix", args)
 }
@@ -1,186 +1,403 @@
-use crate::display::{display_riglet, with_output, wrap_with_prefix};
+use crate::cache::{cached_nix_eval_json, EvalCache};
+use crate::display::{display_riglet, with_output, wrap_with_prefix, write_json, OutputFormat};
 use crate::error::RigupError;
-use crate::nix::{get_system, resolve_flake_path, run_nix_eval_json};
+use crate::filter::RigletFilter;
+use crate::nix::{get_system, resolve_flake_path, run_nix_eval_json, run_nix_eval_json_captured};
+use crate::nix_expr::NixExpr;
 use crate::types::InputData;
 use miette::{IntoDiagnostic, Result};
 use owo_colors::OwoColorize;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::{mpsc, Mutex};
+use std::thread;
 
-pub fn show_flake(
-    flake: Option<String>,
-    with_inputs: bool,
-    no_pager: bool,
-    detailed: bool,
-    no_descriptions: bool,
-    no_stage: bool,
-) -> Result<()> {
-    let system = get_system();
-    let flake_path = flake.unwrap_or_else(|| ".".to_string());
-
-    // Resolve flake path and ensure rigup.local.toml is staged if needed
-    let flake_expr = resolve_flake_path(&flake_path, no_stage)?;
-
-    // Use the helper function from rigup.lib to discover all riglets and rigs
-    let eval_expr = format!(
+/// Build an expression evaluating `body` in a scope where `flake` is the given flake, `listFlake`
+/// the function from rigup.lib (taken from the flake itself if it is rigup.nix, or else from its
+/// `rigup` input), and `list` a function calling `listFlake` with extra args
+fn listflake_expr(flake_expr: &str, body: &str) -> String {
+    format!(
         r###"
             let
-                flake = builtins.getFlake "{flake}";
+                flake = {flake};
                 listFlake = if flake ? lib && flake.lib ? listFlake
                     then flake.lib.listFlake
-                    else flake.inputs.rigup.lib.listFlake or (throw ''
-                        Flake {flake_expr} does not seem to be using rigup.nix. It must have an input named 'rigup'
-                    '');
-            in listFlake {{
-                inherit flake;
-                system = "{system}";
-                includeInputs = {with_inputs};
-            }}
+                    else flake.inputs.rigup.lib.listFlake or (throw ("Flake " + {flake_url}
+                        + " does not seem to be using rigup.nix. It must have an input named 'rigup'"));
+                list = args: listFlake ({{
+                    inherit flake;
+                    system = {system};
+                }} // args);
+            in {body}
         "###,
-        flake = flake_expr,
-        system = system,
-        with_inputs = with_inputs
+        flake = NixExpr::get_flake(flake_expr),
+        flake_url = NixExpr::string(flake_expr),
+        system = NixExpr::string(&get_system()),
+        body = body,
+    )
+}
+
+/// Evaluate `listFlake` on a (resolved) flake, to get the metadata of all its riglets and rigs,
+/// grouped by input name. With `refresh`, bypass the evaluation cache
+pub fn list_flake(
+    flake_expr: &str,
+    with_inputs: bool,
+    refresh: bool,
+) -> Result<BTreeMap<String, InputData>> {
+    // Use the helper function from rigup.lib to discover all riglets and rigs
+    let eval_expr = listflake_expr(
+        flake_expr,
+        &format!("list {{ includeInputs = {}; }}", NixExpr::bool(with_inputs)),
     );
 
     eprintln!(
         "> Analyzing {flake}#riglets and #rigs.{system}",
         flake = flake_expr,
-        system = system
+        system = get_system()
     );
 
-    // Run nix eval and parse the result
-    let result = run_nix_eval_json(&eval_expr)?;
+    // Run nix eval (unless cached) and parse the result
+    let result = cached_nix_eval_json(flake_expr, &eval_expr, refresh)?;
 
     // Parse the nested structure: { input-name -> { riglets = {...}, rigs = {...} } }
-    let all_data: HashMap<String, InputData> =
+    serde_json::from_value(result).map_err(|e| RigupError::MetadataParseError { source: e }.into())
+}
+
+/// Get the names of all the inputs of a flake (including "self"), if its `listFlake` can process
+/// them one by one. Flakes using an older rigup.nix cannot
+fn list_flake_inputs(flake_expr: &str) -> Result<Option<Vec<String>>> {
+    let eval_expr = listflake_expr(
+        flake_expr,
+        r#"if (builtins.functionArgs listFlake) ? inputNames
+            then [ "self" ] ++ builtins.attrNames flake.inputs
+            else null"#,
+    );
+    serde_json::from_value(run_nix_eval_json(flake_expr, &eval_expr)?)
+        .map_err(|e| RigupError::MetadataParseError { source: e }.into())
+}
+
+/// Evaluate `listFlake` for a single input. Returns None if it has neither riglets nor rigs
+fn list_flake_input(
+    cache: &EvalCache,
+    flake_expr: &str,
+    input_name: &str,
+) -> Result<Option<InputData>> {
+    let eval_expr = listflake_expr(
+        flake_expr,
+        &format!(
+            "list {{ inputNames = {}; }}",
+            NixExpr::list([NixExpr::string(input_name)])
+        ),
+    );
+    let result = cache.eval_json(&eval_expr, run_nix_eval_json_captured)?;
+    let mut data: BTreeMap<String, InputData> =
         serde_json::from_value(result).map_err(|e| RigupError::MetadataParseError { source: e })?;
+    Ok(data.remove(input_name))
+}
 
-    if all_data.is_empty() {
-        eprintln!("No inputs with riglets or rigs found");
-        return Ok(());
-    }
+/// Evaluate `listFlake` on each input separately, running at most `jobs` evaluations
+/// concurrently. `on_result` is called (from the current thread) as soon as each input is ready
+fn list_flake_inputs_parallel<F>(
+    flake_expr: &str,
+    input_names: Vec<String>,
+    jobs: usize,
+    refresh: bool,
+    mut on_result: F,
+) -> Result<()>
+where
+    F: FnMut(String, Result<Option<InputData>>) -> Result<()>,
+{
+    let cache = &EvalCache::new(flake_expr, refresh);
+    let queue = &Mutex::new(input_names.into_iter());
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.max(1) {
+            let sender = sender.clone();
+            scope.spawn(move || {
+                // The queue's lock is released as soon as the next input name is taken
+                while let Some(input_name) = queue.lock().ok().and_then(|mut q| q.next()) {
+                    let result = list_flake_input(cache, flake_expr, &input_name);
+                    // The receiver is gone if on_result failed: just stop
+                    if sender.send((input_name, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        // So that the loop below ends when all workers are done
+        drop(sender);
+
+        for (input_name, result) in receiver {
+            on_result(input_name, result)?;
+        }
+        Ok(())
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn show_flake(
+    flake: Option<String>,
+    with_inputs: bool,
+    jobs: usize,
+    no_pager: bool,
+    detailed: bool,
+    no_descriptions: bool,
+    no_stage: bool,
+    refresh: bool,
+    filter: &RigletFilter,
+    format: OutputFormat,
+) -> Result<()> {
+    let flake_path = flake.unwrap_or_else(|| ".".to_string());
 
-    eprintln!("");
+    // Resolve flake path and ensure rigup.local.toml is staged if needed
+    let flake_expr = resolve_flake_path(&flake_path, no_stage)?;
 
     // Get terminal width, default to 80 if not available
     let terminal_width = terminal_size::terminal_size()
         .map(|(w, _)| w.0 as usize)
         .unwrap_or(80);
 
-    // Sort inputs alphabetically for stable output
-    let mut sorted_inputs: Vec<_> = all_data.into_iter().collect();
-    sorted_inputs.sort_by(|a, b| a.0.cmp(&b.0));
+    let no_results = || {
+        if filter.is_empty() {
+            eprintln!("No inputs with riglets or rigs found");
+        } else {
+            eprintln!("No riglets or rigs match the given filters");
+        }
+    };
+
+    let input_names = if with_inputs {
+        list_flake_inputs(&flake_expr)?
+    } else {
+        None
+    };
 
-    // Stream output directly through less or stdout
-    with_output(no_pager, |output| {
-        for (input_name, data) in sorted_inputs {
-            let has_riglets = !data.riglets.is_empty();
-            let has_rigs = !data.rigs.is_empty();
+    let Some(input_names) = input_names else {
+        // Evaluate everything at once
+        let all_data = filter.apply(list_flake(&flake_expr, with_inputs, refresh)?);
 
-            if !has_riglets && !has_rigs {
-                continue;
-            }
+        if format == OutputFormat::Json {
+            return write_json(&all_data);
+        }
 
-            writeln!(output, "📦 {}", input_name.bright_blue().bold()).into_diagnostic()?;
+        if all_data.is_empty() {
+            no_results();
+            return Ok(());
+        }
 
-            // Count total sections (riglets and rigs)
-            let section_count = (if has_riglets { 1 } else { 0 }) + (if has_rigs { 1 } else { 0 });
-            let mut section_idx = 0;
+        eprintln!();
 
-            // Display riglets section
-            if has_riglets {
-                section_idx += 1;
-                let is_last_section = section_idx == section_count;
-                let section_branch = if is_last_section { "└─" } else { "├─" };
-                // Emoji takes 2 chars, so we need extra space for alignment
-                let section_prefix = if is_last_section { "   " } else { " │ " };
+        // Stream output directly through less or stdout
+        return with_output(no_pager, |output| {
+            for (input_name, data) in all_data {
+                display_input(
+                    output,
+                    &input_name,
+                    data,
+                    terminal_width,
+                    detailed,
+                    no_descriptions,
+                )?;
+            }
+            Ok(())
+        });
+    };
 
-                writeln!(output, " {}🧩 {}", section_branch, "Riglets".bold()).into_diagnostic()?;
+    eprintln!(
+        "> Analyzing {flake}#riglets and #rigs.{system} and those of its {count} inputs, {jobs} at a time",
+        flake = flake_expr,
+        system = get_system(),
+        count = input_names.len() - 1,
+        jobs = jobs
+    );
+
+    // A failing input does not prevent the others from being listed
+    let filter_result = |result: Result<Option<InputData>>| match result {
+        Ok(data) => data.and_then(|data| filter.apply_input(data)),
+        Err(err) => Some(InputData {
+            error: Some(err.to_string()),
+            ..Default::default()
+        }),
+    };
+
+    if format == OutputFormat::Json {
+        let mut all_data = BTreeMap::new();
+        list_flake_inputs_parallel(
+            &flake_expr,
+            input_names,
+            jobs,
+            refresh,
+            |input_name, result| {
+                if let Some(data) = filter_result(result) {
+                    all_data.insert(input_name, data);
+                }
+                Ok(())
+            },
+        )?;
+        return write_json(&all_data);
+    }
 
-                let mut riglets_vec: Vec<_> = data.riglets.into_iter().collect();
-                riglets_vec.sort_by(|a, b| a.0.cmp(&b.0));
-                let riglets_count = riglets_vec.len();
+    eprintln!();
 
-                for (idx, (riglet_name, meta)) in riglets_vec.into_iter().enumerate() {
-                    let is_last = idx == riglets_count - 1;
-                    // display_riglet still uses String internally for wrapping,
-                    // but we write it out immediately
-                    display_riglet(
+    let mut shown_count = 0;
+    // Display each input as soon as it is evaluated
+    with_output(no_pager, |output| {
+        list_flake_inputs_parallel(
+            &flake_expr,
+            input_names,
+            jobs,
+            refresh,
+            |input_name, result| {
+                let Some(data) = filter_result(result) else {
+                    return Ok(());
+                };
+                shown_count += 1;
+                match &data.error {
+                    Some(error) => display_input_error(output, &input_name, error, terminal_width),
+                    None => display_input(
                         output,
-                        &riglet_name,
-                        &meta,
-                        section_prefix,
-                        is_last,
+                        &input_name,
+                        data,
                         terminal_width,
                         detailed,
                         no_descriptions,
-                    )?;
+                    ),
                 }
-            }
+            },
+        )
+    })?;
 
-            // Display rigs section
-            if has_rigs {
-                section_idx += 1;
-                let is_last_section = section_idx == section_count;
-                let section_branch = if is_last_section { "└─" } else { "├─" };
-                // Emoji takes 2 chars, so we need extra space for alignment
-                let section_prefix = if is_last_section { "   " } else { " │ " };
+    if shown_count == 0 {
+        no_results();
+    }
 
-                writeln!(output, " {}📟 {}", section_branch, "Rigs".bold()).into_diagnostic()?;
+    Ok(())
+}
 
-                let mut rigs_vec: Vec<_> = data.rigs.into_iter().collect();
-                rigs_vec.sort_by(|a, b| a.0.cmp(&b.0));
-                let rigs_count = rigs_vec.len();
+/// Display the riglets and rigs of one input with tree formatting
+fn display_input(
+    output: &mut dyn Write,
+    input_name: &str,
+    data: InputData,
+    terminal_width: usize,
+    detailed: bool,
+    no_descriptions: bool,
+) -> Result<()> {
+    let has_riglets = !data.riglets.is_empty();
+    let has_rigs = !data.rigs.is_empty();
 
-                for (idx, (rig_name, rig_meta)) in rigs_vec.into_iter().enumerate() {
-                    let is_last_rig = idx == rigs_count - 1;
-                    let rig_branch = if is_last_rig { "└─" } else { "├─" };
-                    let rig_continuation = if is_last_rig { "   " } else { " │ " };
+    if !has_riglets && !has_rigs {
+        return Ok(());
+    }
 
-                    // Build entrypoint suffix if defined
-                    let entrypoint_suffix = if let Some(program) = &rig_meta.entrypoint {
-                        format!(" (entrypoint: {})", program.magenta())
-                    } else {
-                        String::new()
-                    };
+    writeln!(output, "📦 {}", input_name.bright_blue().bold()).into_diagnostic()?;
 
-                    writeln!(
-                        output,
-                        "{} {} {}{}",
-                        section_prefix,
-                        rig_branch,
-                        rig_name.green(),
-                        entrypoint_suffix
-                    )
-                    .into_diagnostic()?;
-
-                    // Display riglets in this rig as comma-separated list (like keywords)
-                    if detailed && !rig_meta.riglets.is_empty() {
-                        let mut riglet_list: Vec<String> = rig_meta
-                            .riglets
-                            .iter()
-                            .map(|(name, _)| name.clone())
-                            .collect();
-                        riglet_list.sort();
-
-                        // Add 2 extra spaces for detail indentation
-                        let item_prefix = format!("{}{}  ", section_prefix, rig_continuation);
-                        let wrapped =
-                            wrap_with_prefix(&riglet_list.join(", "), &item_prefix, terminal_width);
-
-                        for line in wrapped.lines() {
-                            if let Some(text) = line.strip_prefix(&item_prefix) {
-                                writeln!(output, "{}{}", item_prefix, text).into_diagnostic()?;
-                            } else {
-                                writeln!(output, "{}", line).into_diagnostic()?;
-                            }
-                        }
+    // Count total sections (riglets and rigs)
+    let section_count = (if has_riglets { 1 } else { 0 }) + (if has_rigs { 1 } else { 0 });
+    let mut section_idx = 0;
+
+    // Display riglets section
+    if has_riglets {
+        section_idx += 1;
+        let is_last_section = section_idx == section_count;
+        let section_branch = if is_last_section { "└─" } else { "├─" };
+        // Emoji takes 2 chars, so we need extra space for alignment
+        let section_prefix = if is_last_section { "   " } else { " │ " };
+
+        writeln!(output, " {}🧩 {}", section_branch, "Riglets".bold()).into_diagnostic()?;
+
+        let riglets_count = data.riglets.len();
+
+        for (idx, (riglet_name, meta)) in data.riglets.into_iter().enumerate() {
+            let is_last = idx == riglets_count - 1;
+            // display_riglet still uses String internally for wrapping,
+            // but we write it out immediately
+            display_riglet(
+                output,
+                &riglet_name,
+                &meta,
+                section_prefix,
+                is_last,
+                terminal_width,
+                detailed,
+                no_descriptions,
+            )?;
+        }
+    }
+
+    // Display rigs section
+    if has_rigs {
+        section_idx += 1;
+        let is_last_section = section_idx == section_count;
+        let section_branch = if is_last_section { "└─" } else { "├─" };
+        // Emoji takes 2 chars, so we need extra space for alignment
+        let section_prefix = if is_last_section { "   " } else { " │ " };
+
+        writeln!(output, " {}📟 {}", section_branch, "Rigs".bold()).into_diagnostic()?;
+
+        let rigs_count = data.rigs.len();
+
+        for (idx, (rig_name, rig_meta)) in data.rigs.into_iter().enumerate() {
+            let is_last_rig = idx == rigs_count - 1;
+            let rig_branch = if is_last_rig { "└─" } else { "├─" };
+            let rig_continuation = if is_last_rig { "   " } else { " │ " };
+
+            // Build entrypoint suffix if defined
+            let entrypoint_suffix = if let Some(program) = &rig_meta.entrypoint {
+                format!(" (entrypoint: {})", program.magenta())
+            } else {
+                String::new()
+            };
+
+            writeln!(
+                output,
+                "{} {} {}{}",
+                section_prefix,
+                rig_branch,
+                rig_name.green(),
+                entrypoint_suffix
+            )
+            .into_diagnostic()?;
+
+            // Display riglets in this rig as comma-separated list (like keywords)
+            if detailed && !rig_meta.riglets.is_empty() {
+                let riglet_list: Vec<&str> = rig_meta.riglets.keys().map(String::as_str).collect();
+
+                // Add 2 extra spaces for detail indentation
+                let item_prefix = format!("{}{}  ", section_prefix, rig_continuation);
+                let wrapped =
+                    wrap_with_prefix(&riglet_list.join(", "), &item_prefix, terminal_width);
+
+                for line in wrapped.lines() {
+                    if let Some(text) = line.strip_prefix(&item_prefix) {
+                        writeln!(output, "{}{}", item_prefix, text).into_diagnostic()?;
+                    } else {
+                        writeln!(output, "{}", line).into_diagnostic()?;
                     }
                 }
             }
         }
+    }
 
-        Ok(())
-    })?;
+    Ok(())
+}
 
+/// Display an input whose evaluation failed
+fn display_input_error(
+    output: &mut dyn Write,
+    input_name: &str,
+    error: &str,
+    terminal_width: usize,
+) -> Result<()> {
+    writeln!(output, "📦 {}", input_name.bright_blue().bold()).into_diagnostic()?;
+    writeln!(output, " └─❌ {}", "Evaluation failed".red().bold()).into_diagnostic()?;
+    for line in error.trim().lines() {
+        writeln!(
+            output,
+            "{}",
+            wrap_with_prefix(line, "     ", terminal_width).bright_black()
+        )
+        .into_diagnostic()?;
+    }
     Ok(())
 }
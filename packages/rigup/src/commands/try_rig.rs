@@ -0,0 +1,184 @@
+use crate::commands::build::{build_installable, output_dir};
+use crate::commands::run::run_installable;
+use crate::commands::shell::develop_installable;
+use crate::error::RigupError;
+use crate::nix::{get_system, resolve_flake_path, run_nix_eval_json, RigInstallable};
+use crate::nix_expr::{AttrPath, NixExpr};
+use crate::overrides::ConfigOverrides;
+use crate::suggest::did_you_mean;
+use itertools::Itertools;
+use miette::{IntoDiagnostic, Result};
+use std::collections::BTreeMap;
+
+/// The name given to the temporary rig
+const TRY_RIG_NAME: &str = "try";
+
+/// What to do with a temporary rig
+#[derive(Clone, Copy, Debug)]
+pub enum TryAction {
+    Run,
+    Shell,
+    Build,
+}
+
+/// Parse a reference to a riglet or a rig of an input: `<input>.<name>`, or just `<name>` for one
+/// of the flake's own
+pub fn parse_input_ref(input_ref: &str) -> (&str, &str) {
+    input_ref.split_once('.').unwrap_or(("self", input_ref))
+}
+
+/// Name of the folder a temporary rig is built in, made from its riglets so that trying other
+/// riglets does not replace it, e.g. `try-self.coreutils+rigup.claude-code`
+fn build_dir_name(riglets: &[(&str, &str)]) -> String {
+    let riglets = riglets
+        .iter()
+        .map(|(input, riglet)| AttrPath::new([*input, *riglet]).to_file_name())
+        .join("+");
+    format!("{}-{}", TRY_RIG_NAME, riglets)
+}
+
+/// Check that the riglets exist in the flake or its inputs before evaluating a rig with them, so
+/// that misspelled names get suggestions
+fn check_riglet_refs(flake_expr: &str, riglets: &[(&str, &str)]) -> Result<()> {
+    let eval_expr = format!(
+        r#"
+            let flake = {flake};
+            in builtins.mapAttrs (_: input: builtins.attrNames (input.riglets or {{ }}))
+                (flake.inputs // {{ self = flake; }})
+        "#,
+        flake = NixExpr::get_flake(flake_expr),
+    );
+    let known: BTreeMap<String, Vec<String>> =
+        serde_json::from_value(run_nix_eval_json(flake_expr, &eval_expr)?).into_diagnostic()?;
+
+    for (input, riglet) in riglets {
+        let Some(input_riglets) = known.get(*input).filter(|names| !names.is_empty()) else {
+            let inputs_with_riglets = known
+                .iter()
+                .filter(|(_, names)| !names.is_empty())
+                .map(|(input, _)| input);
+            return Err(RigupError::UnknownName {
+                kind: "input with riglets",
+                name: input.to_string(),
+                scope: format!("flake `{}`", flake_expr),
+                did_you_mean: did_you_mean(input, inputs_with_riglets),
+            }
+            .into());
+        };
+        if !input_riglets.iter().any(|name| name == riglet) {
+            return Err(RigupError::UnknownName {
+                kind: "riglet",
+                name: riglet.to_string(),
+                scope: format!("input `{}` of flake `{}`", input, flake_expr),
+                did_you_mean: did_you_mean(riglet, input_riglets),
+            }
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Nix expression of a rig built from riglets of a flake and its inputs. `buildRig` is taken from
+/// the flake itself if it is rigup, or else from the input that provides it
+fn temporary_rig_expr(flake_expr: &str, riglets: &[(&str, &str)]) -> NixExpr {
+    let modules = NixExpr::list(riglets.iter().map(|(input, riglet)| {
+        NixExpr::raw("inputs").select(&AttrPath::new([*input, "riglets", *riglet]))
+    }));
+    NixExpr::raw(format!(
+        r#"(let
+                flake = {flake};
+                inputs = flake.inputs // {{ self = flake; }};
+                rigups = builtins.filter (input: input ? lib.buildRig) ([ flake ] ++ builtins.attrValues flake.inputs);
+                rigup =
+                    if rigups == [ ] then throw ("Flake " + {flake_name} + " does not have rigup as an input")
+                    else builtins.head rigups;
+            in rigup.lib.buildRig {{
+                name = {name};
+                pkgs = import (flake.inputs.nixpkgs or rigup.inputs.nixpkgs) {{ system = {system}; }};
+                modules = {modules};
+            }})"#,
+        flake = NixExpr::get_flake(flake_expr),
+        flake_name = NixExpr::string(flake_expr),
+        name = NixExpr::string(TRY_RIG_NAME),
+        system = NixExpr::string(&get_system()),
+        modules = modules,
+    ))
+}
+
+/// Combine riglets into a temporary rig, without defining it in rigup.toml, then run its
+/// entrypoint, open its shell or build its home like `rigup run`, `rigup shell` and `rigup build`
+/// do for named rigs. `args` are forwarded to the entrypoint, or run as a command in the shell
+pub fn try_riglets(
+    flake: Option<String>,
+    riglet_refs: Vec<String>,
+    action: TryAction,
+    args: &[String],
+    no_stage: bool,
+    overrides: &ConfigOverrides,
+) -> Result<()> {
+    let flake_path = flake.unwrap_or_else(|| ".".to_string());
+    let resolved_path = resolve_flake_path(&flake_path, no_stage)?;
+    let riglets: Vec<(&str, &str)> = riglet_refs.iter().map(|r| parse_input_ref(r)).collect();
+    check_riglet_refs(&resolved_path, &riglets)?;
+
+    let mut rig = temporary_rig_expr(&resolved_path, &riglets);
+    if !overrides.is_empty() {
+        rig = overrides.apply(&resolved_path, &rig, TRY_RIG_NAME)?;
+    }
+    let label = format!(
+        "temporary rig with {}",
+        riglets
+            .iter()
+            .map(|(input, riglet)| format!("{}.{}", input, riglet))
+            .join(", ")
+    );
+
+    match action {
+        TryAction::Run => {
+            let has_entrypoint =
+                run_nix_eval_json(&resolved_path, &format!("{} ? entrypoint", rig))?;
+            if has_entrypoint != serde_json::Value::Bool(true) {
+                return Err(RigupError::NoEntrypoint {
+                    rig: TRY_RIG_NAME.to_string(),
+                }
+                .into());
+            }
+            let entrypoint = rig.select(&AttrPath::new(["entrypoint"]));
+            run_installable(
+                &RigInstallable::from_expr(&resolved_path, label, &entrypoint),
+                args,
+            )
+        }
+        TryAction::Shell => {
+            let shell = rig.select(&AttrPath::new(["shell"]));
+            develop_installable(
+                &RigInstallable::from_expr(&resolved_path, label, &shell),
+                args,
+            )
+        }
+        TryAction::Build => {
+            let home = rig.select(&AttrPath::new(["home"]));
+            let output_path = output_dir(&flake_path)?.join(build_dir_name(&riglets));
+            build_installable(
+                &RigInstallable::from_expr(&resolved_path, label, &home),
+                &output_path,
+            )?;
+            eprintln!("> Rig built at: {}", output_path.display());
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_dir_name() {
+        assert_eq!(
+            build_dir_name(&[("self", "coreutils"), ("rigup", "claude-code")]),
+            "try-self.coreutils+rigup.claude-code"
+        );
+        assert_eq!(build_dir_name(&[("self", "../x")]), "try-self.___x");
+    }
+}
@@ -0,0 +1,423 @@
+use crate::error::RigupError;
+use crate::rigup_toml::{project_riglet_names, TomlFile};
+use crate::suggest::did_you_mean;
+use itertools::Itertools;
+use miette::{IntoDiagnostic, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml_edit::{Item, Key, TableLike};
+
+/// The TOML files that resolveProject.nix loads, in order
+pub const TOML_FILES: [&str; 2] = ["rigup.toml", "rigup.local.toml"];
+
+/// The keys a rig definition can have
+const RIG_KEYS: [&str; 3] = ["extends", "riglets", "config"];
+
+/// Find the root of the current project without Nix: the closest folder containing a flake.nix
+pub fn find_project_root() -> Result<PathBuf> {
+    let cwd = std::env::current_dir().into_diagnostic()?;
+    cwd.ancestors()
+        .find(|dir| dir.join("flake.nix").exists())
+        .map(Path::to_path_buf)
+        .ok_or_else(|| miette::miette!("No flake.nix found in {} or its parents", cwd.display()))
+}
+
+/// The names of the flake's inputs, as locked in flake.lock. None if there is no lock file yet
+fn locked_input_names(flake_root: &Path) -> Option<Vec<String>> {
+    let contents = std::fs::read_to_string(flake_root.join("flake.lock")).ok()?;
+    let lock: Value = serde_json::from_str(&contents).ok()?;
+    let root = lock.get("root")?.as_str()?;
+    let inputs = lock.get("nodes")?.get(root)?.get("inputs")?.as_object()?;
+    Some(inputs.keys().cloned().collect())
+}
+
+fn span_of(key: &Key, item: &Item) -> Option<Range<usize>> {
+    key.span().or_else(|| item.span())
+}
+
+/// A `self` rig extending another, with the location of the base rig's name
+struct ExtendsEdge {
+    file: usize,
+    base: String,
+    span: Option<Range<usize>>,
+}
+
+/// What the project defines, as needed to check its TOML files
+struct Project<'a> {
+    files: &'a [TomlFile],
+    /// The riglets of the `riglets` folder
+    riglets: &'a [String],
+    /// The flake's inputs, if known
+    inputs: Option<&'a [String]>,
+}
+
+impl Project<'_> {
+    /// Check the `extends` or `riglets` section of a rig: a table of input name -> name or list
+    /// of names, naming inputs, riglets and rigs that exist
+    fn check_section(
+        &self,
+        file: &TomlFile,
+        rig: &str,
+        section: &str,
+        table: &dyn TableLike,
+        rig_names: &[&String],
+        problems: &mut Vec<RigupError>,
+    ) {
+        let (what, verb) = if section == "extends" {
+            ("rig", "extends")
+        } else {
+            ("riglet", "uses")
+        };
+        for (input, _) in table.iter() {
+            let Some((key, item)) = table.get_key_value(input) else {
+                continue;
+            };
+            if let Some(inputs) = self.inputs.filter(|_| input != "self") {
+                if !inputs.iter().any(|name| name == input) {
+                    problems.push(file.diagnostic(
+                        format!(
+                            "Rig `{}` uses input `{}`, which the flake does not have",
+                            rig, input
+                        ),
+                        key.span(),
+                        "unknown input",
+                        did_you_mean(input, inputs),
+                    ));
+                }
+            }
+            let names: Option<Vec<&str>> = match item.as_value() {
+                Some(toml_edit::Value::Array(names)) => names.iter().map(|n| n.as_str()).collect(),
+                Some(value) => value.as_str().map(|name| vec![name]),
+                None => None,
+            };
+            let Some(names) = names else {
+                problems.push(file.diagnostic(
+                    format!(
+                        "`{}.{}` of rig `{}` must be a {} name or a list of {} names",
+                        section, input, rig, what, what
+                    ),
+                    span_of(key, item),
+                    "invalid entry",
+                    None,
+                ));
+                continue;
+            };
+            if input != "self" {
+                continue;
+            }
+            for name in names {
+                let (known, candidates, label): (bool, Vec<&String>, _) = if section == "extends" {
+                    (
+                        rig_names.iter().any(|rig| *rig == name),
+                        rig_names.to_vec(),
+                        "no such rig in rigup.toml or rigup.local.toml",
+                    )
+                } else {
+                    (
+                        self.riglets.iter().any(|riglet| riglet == name),
+                        self.riglets.iter().collect(),
+                        "no such riglet in the riglets folder",
+                    )
+                };
+                if !known {
+                    let span = file.rig_entry_span(rig, section, input, name);
+                    problems.push(file.diagnostic(
+                        format!(
+                            "Rig `{}` {} `self.{}`, which does not exist",
+                            rig, verb, name
+                        ),
+                        span,
+                        label,
+                        did_you_mean(name, candidates),
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Check the structure of each rig definition, and the names it refers to. Returns the
+    /// `self` rigs that each rig extends
+    fn check_rigs(&self, problems: &mut Vec<RigupError>) -> BTreeMap<String, Vec<ExtendsEdge>> {
+        let rig_names: Vec<String> = self.files.iter().flat_map(TomlFile::rig_names).collect();
+        let rig_names: Vec<&String> = rig_names.iter().unique().collect();
+        let mut extends = BTreeMap::new();
+
+        for (index, file) in self.files.iter().enumerate() {
+            let Some((rigs_key, rigs)) = file.table().get_key_value("rigs") else {
+                continue;
+            };
+            let Some(rigs) = rigs.as_table_like() else {
+                problems.push(file.diagnostic(
+                    "`rigs` must be a table of rig definitions".to_string(),
+                    span_of(rigs_key, rigs),
+                    "not a table",
+                    None,
+                ));
+                continue;
+            };
+            for (rig, _) in rigs.iter() {
+                let Some((rig_key, item)) = rigs.get_key_value(rig) else {
+                    continue;
+                };
+                let Some(definition) = item.as_table_like() else {
+                    problems.push(file.diagnostic(
+                        format!("Rig `{}` must be a table", rig),
+                        span_of(rig_key, item),
+                        "not a table",
+                        None,
+                    ));
+                    continue;
+                };
+                for (name, _) in definition.iter() {
+                    let Some((key, item)) = definition.get_key_value(name) else {
+                        continue;
+                    };
+                    match (name, item.as_table_like()) {
+                        ("extends" | "riglets", Some(section)) => {
+                            self.check_section(file, rig, name, section, &rig_names, problems)
+                        }
+                        ("config", Some(_)) => {}
+                        ("extends" | "riglets" | "config", None) => {
+                            problems.push(file.diagnostic(
+                                format!("`{}` of rig `{}` must be a table", name, rig),
+                                span_of(key, item),
+                                "not a table",
+                                None,
+                            ));
+                        }
+                        _ => problems.push(file.diagnostic(
+                            format!("Unknown key `{}` in rig `{}`", name, rig),
+                            key.span(),
+                            "unknown key",
+                            did_you_mean(name, RIG_KEYS).or_else(|| {
+                                Some(
+                                    "A rig can only have `extends`, `riglets` and `config`"
+                                        .to_string(),
+                                )
+                            }),
+                        )),
+                    }
+                }
+
+                let edges: Vec<ExtendsEdge> = file
+                    .rig_entry_names(rig, "extends", "self")
+                    .into_iter()
+                    .map(|base| ExtendsEdge {
+                        file: index,
+                        span: file.rig_entry_span(rig, "extends", "self", &base),
+                        base,
+                    })
+                    .collect();
+                extends
+                    .entry(rig.to_string())
+                    .or_insert_with(Vec::new)
+                    .extend(edges);
+            }
+        }
+        extends
+    }
+
+    /// Check that no rig is defined in several files, which `resolveProject` refuses
+    fn check_duplicates(&self, problems: &mut Vec<RigupError>) {
+        let mut definitions: BTreeMap<String, Vec<&TomlFile>> = BTreeMap::new();
+        for file in self.files {
+            for rig in file.rig_names() {
+                definitions.entry(rig).or_default().push(file);
+            }
+        }
+        for (rig, files) in definitions.into_iter().filter(|(_, files)| files.len() > 1) {
+            let related = files[1..]
+                .iter()
+                .map(|file| {
+                    file.diagnostic(
+                        format!("Rig `{}` is also defined in {}", rig, file.name()),
+                        file.rig_span(&rig),
+                        "also defined here",
+                        None,
+                    )
+                })
+                .collect();
+            problems.push(RigupError::InvalidRigDefinition {
+                message: format!("Rig `{}` is defined in several files", rig),
+                src: files[0].named_source(),
+                span: files[0].rig_span(&rig).unwrap_or_default().into(),
+                label: "first defined here".to_string(),
+                help: Some(
+                    "To override some rig's config in another TOML file, define a new rig that extends it"
+                        .to_string(),
+                ),
+                related,
+            });
+        }
+    }
+
+    /// Check that no rig ends up extending itself, which Nix would report as an infinite recursion
+    fn check_cycles(
+        &self,
+        extends: &BTreeMap<String, Vec<ExtendsEdge>>,
+        problems: &mut Vec<RigupError>,
+    ) {
+        #[derive(Clone, Copy, PartialEq)]
+        enum State {
+            InProgress,
+            Done,
+        }
+
+        fn visit<'a>(
+            project: &Project,
+            extends: &'a BTreeMap<String, Vec<ExtendsEdge>>,
+            rig: &'a str,
+            states: &mut BTreeMap<&'a str, State>,
+            stack: &mut Vec<&'a str>,
+            problems: &mut Vec<RigupError>,
+        ) {
+            states.insert(rig, State::InProgress);
+            stack.push(rig);
+            for edge in extends.get(rig).into_iter().flatten() {
+                match states.get(edge.base.as_str()) {
+                    Some(State::InProgress) => {
+                        let start = stack.iter().position(|r| *r == edge.base).unwrap_or(0);
+                        let cycle = stack[start..]
+                            .iter()
+                            .chain([&edge.base.as_str()])
+                            .map(|r| format!("`{}`", r))
+                            .join(" → ");
+                        let file = &project.files[edge.file];
+                        problems.push(file.diagnostic(
+                            format!("Rigs extend each other in a cycle: {}", cycle),
+                            edge.span.clone(),
+                            "closes the cycle",
+                            None,
+                        ));
+                    }
+                    Some(State::Done) => {}
+                    None => visit(project, extends, &edge.base, states, stack, problems),
+                }
+            }
+            stack.pop();
+            states.insert(rig, State::Done);
+        }
+
+        let mut states = BTreeMap::new();
+        for rig in extends.keys() {
+            if !states.contains_key(rig.as_str()) {
+                visit(self, extends, rig, &mut states, &mut Vec::new(), problems);
+            }
+        }
+    }
+
+    fn check(&self) -> Vec<RigupError> {
+        let mut problems = Vec::new();
+        let extends = self.check_rigs(&mut problems);
+        self.check_duplicates(&mut problems);
+        self.check_cycles(&extends, &mut problems);
+        problems
+    }
+}
+
+/// Check the project's TOML files without evaluating anything with Nix: their syntax and
+/// structure, that the riglets and rigs they refer to exist, that no rig is defined twice and
+/// that rigs do not extend each other in a cycle
+pub fn validate_project() -> Result<()> {
+    let flake_root = find_project_root()?;
+    let mut problems = Vec::new();
+    let files: Vec<TomlFile> = TOML_FILES
+        .iter()
+        .filter_map(|name| TomlFile::load_checked(&flake_root, name))
+        .filter_map(|file| file.map_err(|problem| problems.push(problem)).ok())
+        .collect();
+    let riglets = project_riglet_names(&flake_root);
+    let inputs = locked_input_names(&flake_root);
+    let project = Project {
+        files: &files,
+        riglets: &riglets,
+        inputs: inputs.as_deref(),
+    };
+    problems.extend(project.check());
+
+    if !problems.is_empty() {
+        return Err(RigupError::ValidationFailed { problems }.into());
+    }
+    let rig_count: usize = files.iter().map(|file| file.rig_names().len()).sum();
+    eprintln!(
+        "> {} valid ({} rigs)",
+        files.iter().map(TomlFile::name).join(" and "),
+        rig_count
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(toml: &str, local_toml: &str) -> Vec<String> {
+        let files = [
+            TomlFile::parse("rigup.toml", toml.to_string()).unwrap(),
+            TomlFile::parse("rigup.local.toml", local_toml.to_string()).unwrap(),
+        ];
+        let riglets = ["coreutils".to_string(), "git-setup".to_string()];
+        let inputs = ["nixpkgs".to_string(), "rigup".to_string()];
+        let project = Project {
+            files: &files,
+            riglets: &riglets,
+            inputs: Some(&inputs),
+        };
+        project.check().iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_valid_project() {
+        let toml = r#"
+[rigs.base.riglets]
+self = ["coreutils", "git-setup"]
+rigup = "claude-code"
+
+[rigs.base.config]
+agent.identity.name = "Bot"
+
+[rigs.default]
+extends.self = "base"
+"#;
+        let local_toml = r#"
+[rigs.mine]
+extends = { self = ["default"], rigup = "example-rig" }
+"#;
+        assert!(problems(toml, local_toml).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_project() {
+        let toml = r#"
+[rigs.base]
+riglets.self = ["coreutil", 42]
+riglet.self = ["git-setup"]
+config = "oops"
+
+[rigs.a]
+extends.self = "b"
+riglets.rigupp = ["claude-code"]
+
+[rigs.b]
+extends.self = ["a", "missing"]
+"#;
+        let local_toml = r#"
+[rigs.base.riglets]
+self = ["coreutils"]
+"#;
+        assert_eq!(
+            problems(toml, local_toml),
+            [
+                "`riglets.self` of rig `base` must be a riglet name or a list of riglet names",
+                "Unknown key `riglet` in rig `base`",
+                "`config` of rig `base` must be a table",
+                "Rig `a` uses input `rigupp`, which the flake does not have",
+                "Rig `b` extends `self.missing`, which does not exist",
+                "Rig `base` is defined in several files",
+                "Rigs extend each other in a cycle: `a` → `b` → `a`",
+            ]
+        );
+    }
+}
@@ -1,11 +1,42 @@
 use crate::types::RigletMeta;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::ValueEnum;
 use itertools::Itertools;
 use miette::{IntoDiagnostic, Result};
 use owo_colors::OwoColorize;
+use serde::Serialize;
+use serde_json::Value;
 use std::io::{self, IsTerminal, Write};
 use std::process::{Command, Stdio};
 use textwrap::{wrap, Options};
 
+/// How commands that report on flakes and rigs should render their results
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Colored tree, paged through less
+    Tree,
+    /// Pretty-printed JSON on stdout, without colors or paging
+    Json,
+    /// Markdown tables, e.g. to be pasted in a pull request description
+    Markdown,
+}
+
+impl OutputFormat {
+    /// Parser for `--format` in commands that have no markdown rendering
+    pub fn tree_or_json() -> impl TypedValueParser<Value = OutputFormat> {
+        PossibleValuesParser::new(["tree", "json"])
+            .map(|format| OutputFormat::from_str(&format, false).expect("a possible value"))
+    }
+}
+
+/// Write a serializable value as pretty-printed JSON to stdout
+pub fn write_json<T: Serialize>(value: &T) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, value).into_diagnostic()?;
+    writeln!(stdout).into_diagnostic()?;
+    Ok(())
+}
+
 /// Execute a closure that writes to output, piping through less if stdout is a TTY
 pub fn with_output<F>(no_pager: bool, write_fn: F) -> Result<()>
 where
@@ -53,6 +84,35 @@ pub fn wrap_with_prefix(text: &str, prefix: &str, terminal_width: usize) -> Stri
         .join("\n")
 }
 
+/// Format a JSON value for display
+pub fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("\"{}\"", s),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Null => "null".to_string(),
+        Value::Array(arr) => {
+            if arr.len() <= 3 {
+                format!("[{}]", arr.iter().map(format_value).join(" "))
+            } else {
+                format!("[...{} items...]", arr.len())
+            }
+        }
+        Value::Object(obj) => {
+            if obj.len() <= 3 {
+                format!(
+                    "{{ {}}}",
+                    obj.iter()
+                        .map(|(k, v)| format!("{} = {}; ", k, format_value(v)))
+                        .join("")
+                )
+            } else {
+                format!("{{...{} items...}}", obj.len())
+            }
+        }
+    }
+}
+
 /// Display a riglet's metadata with tree formatting
 pub fn display_riglet(
     output: &mut dyn Write,
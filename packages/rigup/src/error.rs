@@ -1,4 +1,4 @@
-use miette::Diagnostic;
+use miette::{Diagnostic, NamedSource, SourceSpan};
 use std::fmt;
 
 #[derive(Debug, Diagnostic)]
@@ -9,8 +9,102 @@ pub enum RigupError {
         stderr: String,
     },
 
+    /// A problem in a rig definition, located in the project's TOML files. Found by Nix, or by
+    /// `rigup validate`
+    InvalidRigDefinition {
+        message: String,
+        #[source_code]
+        src: NamedSource<String>,
+        #[label("{label}")]
+        span: SourceSpan,
+        label: String,
+        #[help]
+        help: Option<String>,
+        /// Other locations involved in the error
+        #[related]
+        related: Vec<RigupError>,
+    },
+
+    /// A rig, or a riglet or rig to extend it with, that does not exist
+    UnknownName {
+        /// What the name refers to: "rig", "riglet", "option"...
+        kind: &'static str,
+        name: String,
+        /// Where the name was looked up
+        scope: String,
+        #[help]
+        did_you_mean: Option<String>,
+    },
+
+    /// A config override whose value does not fit the option
+    InvalidOptionValue {
+        option: String,
+        value: String,
+        expected: String,
+    },
+
+    #[diagnostic(help(
+        "Only rigs containing a riglet that defines `entrypoint` can be run. \
+         Use `rigup shell` or `rigup build` to use the rig's tools and docs directly"
+    ))]
+    NoEntrypoint { rig: String },
+
     #[diagnostic(help("The JSON output from nix eval may be malformed"))]
     MetadataParseError { source: serde_json::Error },
+
+    /// The problems found by `rigup validate`
+    ValidationFailed {
+        #[related]
+        problems: Vec<RigupError>,
+    },
+
+    /// An MCP server of a rig that failed the handshake of `rigup mcp check`
+    McpServerFailed {
+        server: String,
+        /// The riglet that declares the server, if any
+        riglet: Option<String>,
+        reason: String,
+        #[help]
+        help: Option<String>,
+    },
+
+    /// The MCP servers that failed `rigup mcp check`
+    McpCheckFailed {
+        total: usize,
+        #[related]
+        failures: Vec<RigupError>,
+    },
+
+    /// A command that `rigup permissions check` found to match one of the rig's deny rules
+    #[diagnostic(help(
+        "Entrypoints deny the commands that start with the tool then the pattern of a deny rule, \
+         even if the blanket allow over the rig's executables covers them"
+    ))]
+    CommandDenied {
+        command: String,
+        /// The rule, as `<tool> <pattern>`
+        rule: String,
+        /// The riglet that sets the rule, if known
+        riglet: Option<String>,
+    },
+
+    /// A command line that `rigup permissions check` can't split into its commands
+    #[diagnostic(help(
+        "Commands separated by `;`, `&&`, `||`, `|`, `&` or newlines are checked one by one, but \
+         what runs in command substitutions and subshells can't be checked"
+    ))]
+    CommandUncheckable { command: String, reason: String },
+
+    /// A finding of `rigup audit` whose check was given to `--fail-on`
+    AuditFinding { check: String, message: String },
+
+    /// The findings that failed `rigup audit`
+    #[diagnostic(help("Only the checks given to `--fail-on` fail the audit"))]
+    AuditFailed {
+        rig: String,
+        #[related]
+        findings: Vec<RigupError>,
+    },
 }
 
 impl fmt::Display for RigupError {
@@ -19,9 +113,73 @@ impl fmt::Display for RigupError {
             RigupError::NixCommandFailed { code, stderr } => {
                 write!(f, "Nix command failed with exit code {}:\n{}", code, stderr)
             }
+            RigupError::InvalidRigDefinition { message, .. } => write!(f, "{}", message),
+            RigupError::UnknownName {
+                kind, name, scope, ..
+            } => write!(f, "No {} `{}` in {}", kind, name, scope),
+            RigupError::InvalidOptionValue {
+                option,
+                value,
+                expected,
+            } => write!(
+                f,
+                "Invalid value {} for option `{}`: expected {}",
+                value, option, expected
+            ),
+            RigupError::NoEntrypoint { rig } => {
+                write!(f, "Rig `{}` does not provide an entrypoint", rig)
+            }
             RigupError::MetadataParseError { .. } => {
                 write!(f, "Failed to parse riglet metadata")
             }
+            RigupError::ValidationFailed { problems } => {
+                write!(
+                    f,
+                    "Found {} problem(s) in the project's TOML files",
+                    problems.len()
+                )
+            }
+            RigupError::McpServerFailed {
+                server,
+                riglet,
+                reason,
+                ..
+            } => match riglet {
+                Some(riglet) => write!(
+                    f,
+                    "MCP server `{}` (declared by riglet `{}`) failed: {}",
+                    server, riglet, reason
+                ),
+                None => write!(f, "MCP server `{}` failed: {}", server, reason),
+            },
+            RigupError::McpCheckFailed { total, failures } => write!(
+                f,
+                "{} of the rig's {} MCP server(s) failed",
+                failures.len(),
+                total
+            ),
+            RigupError::CommandDenied {
+                command,
+                rule,
+                riglet,
+            } => match riglet {
+                Some(riglet) => write!(
+                    f,
+                    "`{}` is denied by rule `{}` of riglet `{}`",
+                    command, rule, riglet
+                ),
+                None => write!(f, "`{}` is denied by rule `{}`", command, rule),
+            },
+            RigupError::CommandUncheckable { command, reason } => {
+                write!(f, "`{}` is denied, as {}", command, reason)
+            }
+            RigupError::AuditFinding { check, message } => write!(f, "[{}] {}", check, message),
+            RigupError::AuditFailed { rig, findings } => write!(
+                f,
+                "Audit of rig `{}` failed with {} finding(s)",
+                rig,
+                findings.len()
+            ),
         }
     }
 }
@@ -30,7 +188,18 @@ impl std::error::Error for RigupError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             RigupError::NixCommandFailed { .. } => None,
+            RigupError::InvalidRigDefinition { .. } => None,
+            RigupError::UnknownName { .. } => None,
+            RigupError::InvalidOptionValue { .. } => None,
+            RigupError::NoEntrypoint { .. } => None,
             RigupError::MetadataParseError { source } => Some(source),
+            RigupError::ValidationFailed { .. } => None,
+            RigupError::McpServerFailed { .. } => None,
+            RigupError::McpCheckFailed { .. } => None,
+            RigupError::CommandDenied { .. } => None,
+            RigupError::CommandUncheckable { .. } => None,
+            RigupError::AuditFinding { .. } => None,
+            RigupError::AuditFailed { .. } => None,
         }
     }
 }
@@ -0,0 +1,28 @@
+use crate::nix_expr::AttrPath;
+use clap::Args;
+
+/// Riglets and rigs to add to a rig on the fly, via the quick extension attrs that
+/// resolveProject.nix adds to every rig: `<rig>._with.<riglet>` and `<rig>._ext.<other-rig>`
+#[derive(Args, Debug, Default)]
+pub struct RigExtensions {
+    /// Add a riglet to the rig, e.g. `--with claude-code` to run any rig under a given harness
+    ///
+    /// The riglet can come from the flake or from any of its inputs
+    #[arg(long = "with", value_name = "RIGLET")]
+    pub with: Vec<String>,
+    /// Add the riglets and config of another rig to the rig
+    ///
+    /// The rig can come from the flake or from any of its inputs
+    #[arg(long = "ext", value_name = "RIG")]
+    pub ext: Vec<String>,
+}
+
+impl RigExtensions {
+    /// The attribute path of a rig once extended, e.g. `<rig>._with.<riglet>._ext.<other-rig>`.
+    /// Riglets are added first, then rigs, each in the order they were given
+    pub fn apply(&self, rig: AttrPath) -> AttrPath {
+        let with = self.with.iter().map(|riglet| ["_with", riglet.as_str()]);
+        let ext = self.ext.iter().map(|other| ["_ext", other.as_str()]);
+        with.chain(ext).fold(rig, |rig, names| rig.join(names))
+    }
+}
@@ -0,0 +1,121 @@
+use crate::types::{InputData, RigletMeta};
+use clap::Args;
+use std::collections::BTreeMap;
+
+const INTENTS: [&str; 5] = ["base", "sourcebook", "toolbox", "cookbook", "playbook"];
+const STATUSES: [&str; 5] = ["stable", "experimental", "draft", "deprecated", "example"];
+const DISCLOSURES: [&str; 5] = ["none", "lazy", "shallow-toc", "deep-toc", "eager"];
+
+/// Filters on riglet metadata. All given filters must match for a riglet to be kept
+#[derive(Args, Debug)]
+pub struct RigletFilter {
+    /// Only keep riglets with one of these intents
+    #[arg(long, value_delimiter = ',', value_parser = INTENTS)]
+    pub intent: Vec<String>,
+    /// Only keep riglets with one of these statuses
+    #[arg(long, value_delimiter = ',', value_parser = STATUSES)]
+    pub status: Vec<String>,
+    /// Hide riglets with one of these statuses
+    #[arg(long, value_delimiter = ',', value_parser = STATUSES)]
+    pub exclude_status: Vec<String>,
+    /// Only keep riglets with one of these disclosures
+    #[arg(long, value_delimiter = ',', value_parser = DISCLOSURES)]
+    pub disclosure: Vec<String>,
+    /// Only keep riglets that are marked as broken
+    #[arg(long)]
+    pub broken: bool,
+    /// Only keep riglets (and rigs) that provide an entrypoint
+    #[arg(long)]
+    pub has_entrypoint: bool,
+    /// Only keep riglets that have all these keywords (case-insensitive)
+    #[arg(long, value_delimiter = ',')]
+    pub keyword: Vec<String>,
+}
+
+impl RigletFilter {
+    /// Whether no filter was given at all
+    pub fn is_empty(&self) -> bool {
+        self.intent.is_empty()
+            && self.status.is_empty()
+            && self.exclude_status.is_empty()
+            && self.disclosure.is_empty()
+            && !self.broken
+            && !self.has_entrypoint
+            && self.keyword.is_empty()
+    }
+
+    /// Check the filters that only depend on the riglet's own metadata
+    fn matches_meta(&self, meta: &RigletMeta) -> bool {
+        (self.intent.is_empty() || self.intent.contains(&meta.intent))
+            && (self.status.is_empty() || self.status.contains(&meta.status))
+            && !self.exclude_status.contains(&meta.status)
+            && (self.disclosure.is_empty() || self.disclosure.contains(&meta.disclosure))
+            && (!self.broken || meta.broken)
+            && self
+                .keyword
+                .iter()
+                .all(|wanted| meta.keywords.iter().any(|k| k.eq_ignore_ascii_case(wanted)))
+    }
+
+    /// Check whether a standalone riglet passes all the filters
+    pub fn matches(&self, meta: &RigletMeta) -> bool {
+        self.matches_meta(meta) && (!self.has_entrypoint || meta.entrypoint.is_some())
+    }
+
+    /// Filter the riglets and rigs of an input.
+    ///
+    /// A rig is kept if at least one of its riglets matches, and only its matching riglets are
+    /// kept. NOTE: riglets' metadata inside a rig do not tell which one provides the entrypoint,
+    /// so `--has-entrypoint` is checked against the rig itself.
+    /// Returns None if the input is left with neither riglets nor rigs (and has no error)
+    pub fn apply_input(&self, mut data: InputData) -> Option<InputData> {
+        if !self.is_empty() {
+            data.riglets.retain(|_, meta| self.matches(meta));
+            data.rigs.retain(|_, rig| {
+                rig.riglets.retain(|_, meta| self.matches_meta(meta));
+                !rig.riglets.is_empty() && (!self.has_entrypoint || rig.entrypoint.is_some())
+            });
+        }
+        (!data.riglets.is_empty() || !data.rigs.is_empty() || data.error.is_some()).then_some(data)
+    }
+
+    /// Filter the riglets and rigs of every input (see `apply_input`)
+    pub fn apply(&self, all_data: BTreeMap<String, InputData>) -> BTreeMap<String, InputData> {
+        all_data
+            .into_iter()
+            .filter_map(|(input_name, data)| Some((input_name, self.apply_input(data)?)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use serde_json::json;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        filter: RigletFilter,
+    }
+
+    fn riglet(intent: &str, disclosure: &str) -> RigletMeta {
+        serde_json::from_value(json!({ "intent": intent, "disclosure": disclosure })).unwrap()
+    }
+
+    #[test]
+    fn test_disclosure_filter() {
+        let filter = Cli::parse_from(["rigup", "--disclosure", "eager,deep-toc"]).filter;
+        assert!(!filter.is_empty());
+        assert!(filter.matches(&riglet("sourcebook", "eager")));
+        assert!(filter.matches(&riglet("cookbook", "deep-toc")));
+        assert!(!filter.matches(&riglet("cookbook", "lazy")));
+
+        let filter = Cli::parse_from(["rigup", "--disclosure", "none", "--intent", "base"]).filter;
+        assert!(filter.matches(&riglet("base", "none")));
+        assert!(!filter.matches(&riglet("toolbox", "none")));
+
+        assert!(Cli::try_parse_from(["rigup", "--disclosure", "hidden"]).is_err());
+    }
+}
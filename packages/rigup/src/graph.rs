@@ -0,0 +1,291 @@
+use clap::ValueEnum;
+use miette::{IntoDiagnostic, Result};
+use owo_colors::OwoColorize;
+use std::collections::BTreeSet;
+use std::io::Write;
+
+/// How commands that draw graphs (of rigs, of riglets) render them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum GraphFormat {
+    /// Colored tree, paged through less
+    Tree,
+    /// Graphviz DOT, e.g. to pipe into `dot -Tsvg`
+    Dot,
+    /// Mermaid flowchart, e.g. to paste in Markdown documents
+    Mermaid,
+}
+
+pub struct GraphNode {
+    pub label: String,
+    /// Shown after the label in trees, e.g. where the node comes from
+    pub note: Option<String>,
+    /// Extra lines describing the node
+    pub details: Vec<String>,
+    /// Drawn dimmed (or dashed): for nodes that are only referred to, e.g. from other flakes
+    pub external: bool,
+}
+
+pub struct GraphEdge {
+    pub from: usize,
+    pub to: usize,
+    /// Drawn dashed: for indirect relations
+    pub dashed: bool,
+}
+
+/// A directed graph, drawn with its edges going down: a tree shows each node's targets below it
+pub struct Graph {
+    pub icon: &'static str,
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+/// Escape a label for a DOT double-quoted string
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escape a label for a Mermaid double-quoted label
+fn mermaid_escape(text: &str) -> String {
+    text.replace('"', "#quot;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+}
+
+impl Graph {
+    pub fn new(icon: &'static str) -> Self {
+        Graph {
+            icon,
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        }
+    }
+
+    /// Add a node, returning its index
+    pub fn add_node(&mut self, node: GraphNode) -> usize {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+    }
+
+    pub fn add_edge(&mut self, from: usize, to: usize, dashed: bool) {
+        self.edges.push(GraphEdge { from, to, dashed });
+    }
+
+    fn targets(&self, node: usize) -> Vec<&GraphEdge> {
+        self.edges.iter().filter(|edge| edge.from == node).collect()
+    }
+
+    /// Nodes that no edge leads to, i.e. the tops of the trees. If every node of a cycle has
+    /// an incoming edge, its first node is taken as a top too, so that it is still shown
+    fn roots(&self) -> Vec<usize> {
+        let mut roots: Vec<usize> = (0..self.nodes.len())
+            .filter(|node| !self.edges.iter().any(|edge| edge.to == *node))
+            .collect();
+        let mut reachable = BTreeSet::new();
+        let mut stack = roots.clone();
+        while let Some(node) = stack.pop() {
+            if reachable.insert(node) {
+                stack.extend(self.targets(node).iter().map(|edge| edge.to));
+            }
+        }
+        for node in 0..self.nodes.len() {
+            if !reachable.contains(&node) {
+                roots.push(node);
+                let mut stack = vec![node];
+                while let Some(node) = stack.pop() {
+                    if reachable.insert(node) {
+                        stack.extend(self.targets(node).iter().map(|edge| edge.to));
+                    }
+                }
+            }
+        }
+        roots
+    }
+
+    fn tree_label(&self, node: usize, dashed: bool, seen: bool) -> String {
+        let node_data = &self.nodes[node];
+        let label = if node_data.external || dashed {
+            node_data.label.bright_black().to_string()
+        } else {
+            node_data.label.cyan().bold().to_string()
+        };
+        let mut line = format!("{} {}", self.icon, label);
+        if let Some(note) = &node_data.note {
+            line.push_str(&format!(" {}", format!("({})", note).bright_black()));
+        }
+        if seen {
+            line.push_str(&format!(" {}", "(see above)".italic()));
+        }
+        line
+    }
+
+    /// Write the details and the subtree of a node whose own line has been written
+    fn write_subtree(
+        &self,
+        output: &mut dyn Write,
+        node: usize,
+        prefix: &str,
+        visited: &mut BTreeSet<usize>,
+    ) -> Result<()> {
+        let targets = self.targets(node);
+        let details_prefix = if targets.is_empty() { "   " } else { " │ " };
+        for detail in &self.nodes[node].details {
+            writeln!(
+                output,
+                "{}{} {}",
+                prefix,
+                details_prefix,
+                detail.bright_black()
+            )
+            .into_diagnostic()?;
+        }
+        for (idx, edge) in targets.iter().enumerate() {
+            let is_last = idx == targets.len() - 1;
+            let branch = if is_last { "└─" } else { "├─" };
+            let continuation = if is_last { "   " } else { " │ " };
+            let first_visit = visited.insert(edge.to);
+            writeln!(
+                output,
+                "{} {} {}",
+                prefix,
+                branch,
+                self.tree_label(edge.to, edge.dashed, !first_visit)
+            )
+            .into_diagnostic()?;
+            if first_visit {
+                let prefix = format!("{}{}", prefix, continuation);
+                self.write_subtree(output, edge.to, &prefix, visited)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the graph as trees. A node reachable in several ways is detailed only the first time
+    pub fn write_tree(&self, output: &mut dyn Write) -> Result<()> {
+        let mut visited = BTreeSet::new();
+        for root in self.roots() {
+            if !visited.insert(root) {
+                continue;
+            }
+            writeln!(output, "{}", self.tree_label(root, false, false)).into_diagnostic()?;
+            self.write_subtree(output, root, "", &mut visited)?;
+        }
+        Ok(())
+    }
+
+    pub fn to_dot(&self, name: &str) -> String {
+        let mut lines = vec![
+            format!("digraph \"{}\" {{", dot_escape(name)),
+            "  node [shape=box];".to_string(),
+        ];
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let label = std::iter::once(node.label.as_str())
+                .chain(node.details.iter().map(String::as_str))
+                .map(dot_escape)
+                .collect::<Vec<_>>()
+                .join("\\n");
+            let style = if node.external { ", style=dashed" } else { "" };
+            lines.push(format!("  n{} [label=\"{}\"{}];", idx, label, style));
+        }
+        for edge in &self.edges {
+            let style = if edge.dashed { " [style=dashed]" } else { "" };
+            lines.push(format!("  n{} -> n{}{};", edge.from, edge.to, style));
+        }
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let mut lines = vec!["flowchart TD".to_string()];
+        for (idx, node) in self.nodes.iter().enumerate() {
+            let label = std::iter::once(node.label.as_str())
+                .chain(node.details.iter().map(String::as_str))
+                .map(mermaid_escape)
+                .collect::<Vec<_>>()
+                .join("<br/>");
+            let class = if node.external { ":::external" } else { "" };
+            lines.push(format!("  n{}[\"{}\"]{}", idx, label, class));
+        }
+        for edge in &self.edges {
+            let arrow = if edge.dashed { "-.->" } else { "-->" };
+            lines.push(format!("  n{} {} n{}", edge.from, arrow, edge.to));
+        }
+        if self.nodes.iter().any(|node| node.external) {
+            lines.push("  classDef external stroke-dasharray: 5 5".to_string());
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph() -> Graph {
+        let node = |label: &str, external| GraphNode {
+            label: label.to_string(),
+            note: None,
+            details: vec![format!("+ {}", label)],
+            external,
+        };
+        let mut graph = Graph::new("*");
+        let base = graph.add_node(node("base", false));
+        let other = graph.add_node(node("input.other", true));
+        let derived = graph.add_node(node("derived \"quoted\"", false));
+        graph.add_edge(base, derived, false);
+        graph.add_edge(other, derived, true);
+        graph
+    }
+
+    #[test]
+    fn test_tree() {
+        let mut output = Vec::new();
+        graph().write_tree(&mut output).unwrap();
+        // Drop the colors' escape sequences
+        let output = String::from_utf8(output).unwrap();
+        let mut plain = String::new();
+        let mut chars = output.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|c| *c == 'm');
+            } else {
+                plain.push(c);
+            }
+        }
+        assert_eq!(
+            plain,
+            r#"* base
+ │  + base
+ └─ * derived "quoted"
+       + derived "quoted"
+* input.other
+ │  + input.other
+ └─ * derived "quoted" (see above)
+"#
+        );
+    }
+
+    #[test]
+    fn test_dot_and_mermaid() {
+        assert_eq!(
+            graph().to_dot("rigs"),
+            r#"digraph "rigs" {
+  node [shape=box];
+  n0 [label="base\n+ base"];
+  n1 [label="input.other\n+ input.other", style=dashed];
+  n2 [label="derived \"quoted\"\n+ derived \"quoted\""];
+  n0 -> n2;
+  n1 -> n2 [style=dashed];
+}"#
+        );
+        assert_eq!(
+            graph().to_mermaid(),
+            r#"flowchart TD
+  n0["base<br/>+ base"]
+  n1["input.other<br/>+ input.other"]:::external
+  n2["derived #quot;quoted#quot;<br/>+ derived #quot;quoted#quot;"]
+  n0 --> n2
+  n1 -.-> n2
+  classDef external stroke-dasharray: 5 5"#
+        );
+    }
+}
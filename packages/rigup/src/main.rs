@@ -1,17 +1,43 @@
+mod audit;
+mod cache;
 mod commands;
 mod display;
 mod error;
+mod extensions;
+mod filter;
+mod graph;
+mod mcp;
+mod mcp_client;
 mod nix;
+mod nix_error;
+mod nix_expr;
+mod overrides;
+mod permissions;
+mod prompt_template;
+mod rigup_toml;
+mod suggest;
 mod types;
 
+use audit::AuditCheck;
 use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use clap_complete::{generate, Shell};
 use clap_complete_nushell::Nushell;
+use commands::try_rig::TryAction;
 use commands::{
-    browse_rig_docs, build_rig, enter_shell, inspect_rig, new_project, run_entrypoint, show_flake,
+    add_riglets, audit_rig, browse_rig_docs, build_rig, check_mcp_servers, check_permission,
+    config_get, config_set, config_unset, create_rig, delete_rig, diff_rigs, enter_shell,
+    inspect_rig, list_permissions, list_prompts, list_rigs, new_project, remove_riglets,
+    rename_rig, render_prompt, run_entrypoint, search_riglets, serve_rig, show_flake,
+    show_riglet_graph, try_riglets, validate_project,
 };
+use display::OutputFormat;
+use extensions::RigExtensions;
+use filter::RigletFilter;
+use graph::GraphFormat;
 use miette::Result;
+use overrides::ConfigOverrides;
 use std::io;
+use std::time::Duration;
 
 #[derive(Parser)]
 #[command(name = "rigup")]
@@ -38,6 +64,12 @@ struct RunArgs {
     /// Disable auto-staging of rigup.local.toml
     #[arg(long)]
     no_stage: bool,
+
+    #[command(flatten)]
+    extensions: RigExtensions,
+
+    #[command(flatten)]
+    overrides: ConfigOverrides,
 }
 
 #[derive(Subcommand)]
@@ -61,6 +93,10 @@ enum Commands {
         /// Disable auto-staging of rigup.local.toml
         #[arg(long)]
         no_stage: bool,
+        #[command(flatten)]
+        extensions: RigExtensions,
+        #[command(flatten)]
+        overrides: ConfigOverrides,
     },
     /// Enter a development shell for a rig
     Shell {
@@ -74,6 +110,153 @@ enum Commands {
         /// Disable auto-staging of rigup.local.toml
         #[arg(long)]
         no_stage: bool,
+        #[command(flatten)]
+        extensions: RigExtensions,
+        #[command(flatten)]
+        overrides: ConfigOverrides,
+    },
+    /// Try riglets together in a temporary rig, without defining it in rigup.toml
+    ///
+    /// Runs the rig's entrypoint by default, like `rigup run`
+    Try {
+        /// Riglets to combine, as `<input>.<riglet>`, or just `<riglet>` for the flake's own riglets
+        ///
+        /// Examples: `self.coreutils`, `rigup.claude-code`
+        #[arg(required = true)]
+        riglets: Vec<String>,
+        /// Flake to take the riglets from, along with its inputs (defaults to `.`)
+        ///
+        /// Examples: `.`, `github:user/repo[/branch]`, `git+file:/abs/path/to/local/clone`
+        #[arg(long)]
+        flake: Option<String>,
+        /// Run the rig's entrypoint (the default)
+        #[arg(long, conflicts_with_all = ["shell", "build"])]
+        run: bool,
+        /// Enter a development shell for the rig, like `rigup shell`
+        #[arg(long, conflicts_with = "build")]
+        shell: bool,
+        /// Build the rig's home directory, like `rigup build`. It is built in the output folder
+        /// as `try-` followed by its riglets, e.g. `try-self.coreutils+rigup.claude-code`
+        #[arg(long)]
+        build: bool,
+        /// Arguments to forward to the entrypoint, or with `--shell`, command to run in the shell
+        #[arg(last = true)]
+        args: Vec<String>,
+        /// Disable auto-staging of rigup.local.toml
+        #[arg(long)]
+        no_stage: bool,
+        #[command(flatten)]
+        overrides: ConfigOverrides,
+    },
+    /// Add riglets to a rig of the current project, in rigup.toml
+    ///
+    /// The file's formatting and comments are kept. The rig is created if it does not exist
+    Add {
+        /// Rig to add the riglets to
+        rig: String,
+        /// Riglets to add, as `<input>.<riglet>`, or just `<riglet>` for the project's own riglets
+        ///
+        /// Examples: `self.coreutils`, `rigup.claude-code`
+        #[arg(required = true)]
+        riglets: Vec<String>,
+        /// Edit rigup.local.toml instead of rigup.toml
+        #[arg(long)]
+        local: bool,
+        /// Disable auto-staging of rigup.local.toml
+        #[arg(long)]
+        no_stage: bool,
+    },
+    /// Remove riglets from a rig of the current project, in rigup.toml
+    ///
+    /// The file's formatting and comments are kept
+    Remove {
+        /// Rig to remove the riglets from
+        rig: String,
+        /// Riglets to remove, as `<input>.<riglet>`, or just `<riglet>` for the project's own riglets
+        #[arg(required = true)]
+        riglets: Vec<String>,
+        /// Edit rigup.local.toml instead of rigup.toml
+        #[arg(long)]
+        local: bool,
+    },
+    /// Create, rename or delete rigs of the current project
+    Rig {
+        #[command(subcommand)]
+        command: RigCommands,
+    },
+    /// Check rigup.toml and rigup.local.toml without evaluating them with Nix
+    ///
+    /// Checks their syntax and structure, that the riglets of the `riglets` folder and the rigs
+    /// they refer to exist, that the inputs they use are in flake.lock, that no rig is defined in
+    /// both files and that rigs do not extend each other in a cycle. Fast enough for a pre-commit hook
+    Validate,
+    /// List the rigs of the current project, or show how they extend each other
+    ///
+    /// Reads rigup.toml and rigup.local.toml without evaluating them with Nix
+    Rigs {
+        /// Show the rigs as a tree of the rigs that extend them, with the riglets and config each
+        /// rig adds to its base rigs
+        #[arg(long)]
+        tree: bool,
+        /// Print the `extends` graph in this format. `dot` (Graphviz) and `mermaid` print it
+        /// without colors nor paging
+        #[arg(long, value_enum, conflicts_with = "tree")]
+        format: Option<GraphFormat>,
+        /// Disable paging through less
+        #[arg(short = 'P', long)]
+        no_pager: bool,
+    },
+    /// Read or change the config of a rig of the current project
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Serve rigs over the Model Context Protocol (MCP), or check their MCP servers
+    Mcp {
+        #[command(subcommand)]
+        command: McpCommands,
+    },
+    /// List a rig's prompt commands, or render one to pipe it into any agent or script
+    Prompt {
+        #[command(subcommand)]
+        command: PromptCommands,
+    },
+    /// Report what a rig exposes, for reviewers to approve it
+    ///
+    /// Lists every executable and the riglet that provides it, whether each is wrapped (unwrapped
+    /// tools see the user's real XDG_CONFIG_HOME), the MCP servers with their commands, URLs and
+    /// header names, the deny rules, and the riglets marked broken, draft or deprecated
+    Audit {
+        /// Flake reference in the form `<flake>#<rig>`, e.g. `.#default`
+        flake_ref: String,
+        /// Checks whose findings make the audit fail, e.g. `--fail-on remote-mcp-without-tls,unwrapped-tool`
+        ///
+        /// Findings of other checks are only reported
+        #[arg(long, value_enum, value_delimiter = ',')]
+        fail_on: Vec<AuditCheck>,
+        /// Output format
+        ///
+        /// `json` prints the report without colors nor paging, for use in scripts
+        #[arg(long, default_value = "tree", value_parser = OutputFormat::tree_or_json())]
+        format: OutputFormat,
+        /// Disable paging through less
+        #[arg(short = 'P', long)]
+        no_pager: bool,
+        /// Disable auto-staging of rigup.local.toml
+        #[arg(long)]
+        no_stage: bool,
+        /// Re-evaluate the flake instead of reusing a cached evaluation
+        #[arg(long)]
+        refresh: bool,
+        #[command(flatten)]
+        extensions: RigExtensions,
+        #[command(flatten)]
+        overrides: ConfigOverrides,
+    },
+    /// Check what the entrypoints of a rig let an agent run without asking
+    Permissions {
+        #[command(subcommand)]
+        command: PermissionsCommands,
     },
     /// Show all riglets and rigs from a flake and its inputs
     Show {
@@ -86,6 +269,9 @@ enum Commands {
         /// By default only `self`, the given flake, is shown. This will also list the rigs and riglets provided by its own input flakes
         #[arg(short = 'i', long)]
         with_inputs: bool,
+        /// Maximum number of inputs to evaluate concurrently with --with-inputs
+        #[arg(short, long, default_value_t = 4)]
+        jobs: usize,
         /// Show all details
         ///
         /// Adds keywords, tools, when-to-use for riglets and riglet list for rigs
@@ -100,6 +286,46 @@ enum Commands {
         /// Disable auto-staging of rigup.local.toml
         #[arg(long)]
         no_stage: bool,
+        /// Re-evaluate the flake instead of reusing a cached evaluation
+        ///
+        /// Evaluations are cached in `$XDG_CACHE_HOME/rigup`, keyed by the flake's locked contents
+        #[arg(long)]
+        refresh: bool,
+        #[command(flatten)]
+        filter: RigletFilter,
+        /// Output format
+        ///
+        /// `json` prints the evaluated metadata without colors nor paging, for use in scripts
+        #[arg(long, default_value = "tree", value_parser = OutputFormat::tree_or_json())]
+        format: OutputFormat,
+    },
+    /// Search riglets from a flake and all its inputs by metadata
+    ///
+    /// Riglets are ranked by how well their name, keywords, command names, description and
+    /// when-to-use match the query
+    Search {
+        /// Words to search for
+        #[arg(required = true)]
+        query: Vec<String>,
+        /// Flake to search in, along with its inputs (defaults to `.`)
+        ///
+        /// Examples: `.`, `github:user/repo[/branch]`, `git+file:/abs/path/to/local/clone`
+        #[arg(long)]
+        flake: Option<String>,
+        /// Maximum number of results to show
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+        /// Disable paging through less
+        #[arg(short = 'P', long)]
+        no_pager: bool,
+        /// Disable auto-staging of rigup.local.toml
+        #[arg(long)]
+        no_stage: bool,
+        /// Re-evaluate the flake instead of reusing a cached evaluation
+        ///
+        /// Evaluations are cached in `$XDG_CACHE_HOME/rigup`, keyed by the flake's locked contents
+        #[arg(long)]
+        refresh: bool,
     },
     /// Inspect a specific rig's structure and configuration
     Inspect {
@@ -121,6 +347,80 @@ enum Commands {
         /// Disable auto-staging of rigup.local.toml
         #[arg(long)]
         no_stage: bool,
+        /// Re-evaluate the flake instead of reusing a cached evaluation
+        ///
+        /// Evaluations are cached in `$XDG_CACHE_HOME/rigup`, keyed by the flake's locked contents
+        #[arg(long)]
+        refresh: bool,
+        /// Output format
+        ///
+        /// `json` prints the evaluated metadata without colors nor paging, for use in scripts
+        #[arg(long, default_value = "tree", value_parser = OutputFormat::tree_or_json())]
+        format: OutputFormat,
+        #[command(flatten)]
+        extensions: RigExtensions,
+        #[command(flatten)]
+        overrides: ConfigOverrides,
+    },
+    /// Show how the riglets of a rig import each other
+    ///
+    /// Tells which riglets are listed explicitly (by the rig or the rigs it extends) and which
+    /// were pulled in by the imports of other riglets, and flags the riglets listed although
+    /// another listed riglet already imports them
+    Graph {
+        /// Flake reference in the form `<flake>#<rig>` (defaults to `.#default`)
+        ///
+        /// Current repo must use `.#` prefix. Examples: `.#myrig`, `github:user/repo`, `github:user/repo#myrig`
+        flake_ref: Option<String>,
+        /// Output format
+        ///
+        /// `dot` (Graphviz) and `mermaid` print the graph without colors nor paging
+        #[arg(long, value_enum, default_value_t = GraphFormat::Tree)]
+        format: GraphFormat,
+        /// Disable paging through less
+        #[arg(short = 'P', long)]
+        no_pager: bool,
+        /// Disable auto-staging of rigup.local.toml
+        #[arg(long)]
+        no_stage: bool,
+        /// Re-evaluate the flake instead of reusing a cached evaluation
+        ///
+        /// Evaluations are cached in `$XDG_CACHE_HOME/rigup`, keyed by the flake's locked contents
+        #[arg(long)]
+        refresh: bool,
+        #[command(flatten)]
+        extensions: RigExtensions,
+    },
+    /// Compare two rigs: riglets added or removed, changed riglet metadata and option values
+    Diff {
+        /// Flake reference of the rig to compare from, in the form `<flake>#<rig>`
+        ///
+        /// Current repo must use `.#` prefix. Examples: `.#myrig`, `github:user/repo`, `github:user/repo#myrig`
+        old_ref: String,
+        /// Flake reference of the rig to compare to, in the form `<flake>#<rig>`
+        #[arg(required_unless_present = "rev")]
+        new_ref: Option<String>,
+        /// Compare the rig in the working tree with the same rig at this git revision instead
+        ///
+        /// Only for rigs of the current repo (`.#<rig>`). Examples: `HEAD`, `main`, `a1b2c3d`
+        #[arg(long, conflicts_with = "new_ref")]
+        rev: Option<String>,
+        /// Disable paging through less
+        #[arg(short = 'P', long)]
+        no_pager: bool,
+        /// Disable auto-staging of rigup.local.toml
+        #[arg(long)]
+        no_stage: bool,
+        /// Re-evaluate the flake instead of reusing a cached evaluation
+        ///
+        /// Evaluations are cached in `$XDG_CACHE_HOME/rigup`, keyed by the flake's locked contents
+        #[arg(long)]
+        refresh: bool,
+        /// Output format
+        ///
+        /// `json` and `markdown` print the differences without colors nor paging. `markdown` is meant to be pasted in pull requests
+        #[arg(long, value_enum, default_value_t = OutputFormat::Tree)]
+        format: OutputFormat,
     },
     /// Browse a rig's documentation with $EDITOR (or specified program)
     Browse {
@@ -129,11 +429,17 @@ enum Commands {
         /// Current repo must use `.#` prefix. Examples: `.#myrig`, `github:user/repo`, `github:user/repo#myrig`
         flake_ref: Option<String>,
         /// Program to open documentation with (defaults to $EDITOR)
-        #[arg(short, long)]
-        with_: Option<String>,
+        #[arg(short = 'w', long, visible_alias = "with")]
+        open_with: Option<String>,
         /// Disable auto-staging of rigup.local.toml
         #[arg(long)]
         no_stage: bool,
+        /// Add the riglets and config of another rig to the rig
+        ///
+        /// The rig can come from the flake or from any of its inputs. Riglets cannot be added on
+        /// the fly here, as `--with` is the program to open documentation with
+        #[arg(long = "ext", value_name = "RIG")]
+        ext: Vec<String>,
     },
     /// Generate shell completions
     Completions {
@@ -143,6 +449,219 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+enum RigCommands {
+    /// Define a new rig in rigup.toml
+    Create {
+        /// Name of the rig
+        name: String,
+        /// Rig to extend, as `<input>.<rig>`, or just `<rig>` for the project's own rigs. Can be
+        /// given several times
+        #[arg(long, value_name = "RIG")]
+        extends: Vec<String>,
+        /// Riglets to use, as `<input>.<riglet>`, or just `<riglet>` for the project's own riglets
+        #[arg(long, num_args = 1.., value_name = "RIGLET")]
+        riglets: Vec<String>,
+        /// Define the rig in rigup.local.toml instead of rigup.toml
+        #[arg(long)]
+        local: bool,
+        /// Disable auto-staging of rigup.local.toml
+        #[arg(long)]
+        no_stage: bool,
+    },
+    /// Rename a rig, and update the rigs that extend it
+    Rename {
+        /// Current name of the rig
+        old: String,
+        /// New name of the rig
+        new: String,
+    },
+    /// Delete a rig. Refused if other rigs extend it
+    Delete {
+        /// Name of the rig
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print the value of a config option of a rig as JSON (or of all the options in a group)
+    Get {
+        /// Rig to read the config of
+        rig: String,
+        /// Option path, e.g. `agent.identity.name`
+        option: String,
+        /// Disable auto-staging of rigup.local.toml
+        #[arg(long)]
+        no_stage: bool,
+    },
+    /// Set a config option of a rig, in the TOML file that defines the rig
+    ///
+    /// The value is checked against the option's type before the file is edited. Its formatting
+    /// and comments are kept
+    Set {
+        /// Rig to configure
+        rig: String,
+        /// Option path, e.g. `agent.personality.alignment.lawfulness`
+        option: String,
+        /// The value, parsed as TOML (`42`, `true`, `["a", "b"]`, `{ x = 1 }`...), or taken as a
+        /// plain string if it is not valid TOML
+        #[arg(allow_hyphen_values = true)]
+        value: String,
+        /// Disable auto-staging of rigup.local.toml
+        #[arg(long)]
+        no_stage: bool,
+    },
+    /// Remove a config option from a rig's config, in the TOML file that defines the rig
+    Unset {
+        /// Rig to configure
+        rig: String,
+        /// Option path, e.g. `agent.identity.name`
+        option: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum McpCommands {
+    /// Serve a rig as an MCP server over stdio, for harnesses without an entrypoint riglet
+    ///
+    /// The rig's RIG.md manifest and docs are served as resources (`rig://<rig>/RIG.md`,
+    /// `rig://<rig>/docs/...`), its prompt commands as prompts, and the executables of its tools
+    /// as tools, run with the rig's config folder as XDG_CONFIG_HOME
+    Serve {
+        /// Flake reference in the form `<flake>#<rig>` (defaults to `.#default`)
+        ///
+        /// Current repo must use `.#` prefix. Examples: `.#myrig`, `github:user/repo`, `github:user/repo#myrig`
+        flake_ref: Option<String>,
+        /// Seconds a tool may run before it is killed and its call reported as failed
+        #[arg(long, default_value_t = 120)]
+        tool_timeout: u64,
+        /// Disable auto-staging of rigup.local.toml
+        #[arg(long)]
+        no_stage: bool,
+        #[command(flatten)]
+        extensions: RigExtensions,
+        #[command(flatten)]
+        overrides: ConfigOverrides,
+    },
+    /// Check that the MCP servers of a rig start and answer
+    ///
+    /// Local servers are built and started, remote ones are reached at their URL (with their
+    /// headers). Each one goes through the MCP handshake (`initialize` then `tools/list`)
+    Check {
+        /// Flake reference in the form `<flake>#<rig>` (defaults to `.#default`)
+        ///
+        /// Current repo must use `.#` prefix. Examples: `.#myrig`, `github:user/repo`, `github:user/repo#myrig`
+        flake_ref: Option<String>,
+        /// Seconds to wait for each server to go through the handshake
+        #[arg(long, default_value_t = 10)]
+        timeout: u64,
+        /// Disable auto-staging of rigup.local.toml
+        #[arg(long)]
+        no_stage: bool,
+        #[command(flatten)]
+        extensions: RigExtensions,
+        #[command(flatten)]
+        overrides: ConfigOverrides,
+    },
+}
+
+#[derive(Subcommand)]
+enum PromptCommands {
+    /// List the prompt commands of a rig, by `riglet:command` name, with the arguments they use
+    List {
+        /// Flake reference in the form `<flake>#<rig>` (defaults to `.#default`)
+        ///
+        /// Current repo must use `.#` prefix. Examples: `.#myrig`, `github:user/repo`, `github:user/repo#myrig`
+        flake_ref: Option<String>,
+        /// Disable paging through less
+        #[arg(short = 'P', long)]
+        no_pager: bool,
+        /// Disable auto-staging of rigup.local.toml
+        #[arg(long)]
+        no_stage: bool,
+        /// Re-evaluate the flake instead of reusing a cached evaluation
+        #[arg(long)]
+        refresh: bool,
+        #[command(flatten)]
+        extensions: RigExtensions,
+        #[command(flatten)]
+        overrides: ConfigOverrides,
+    },
+    /// Print a prompt command of a rig with its arguments filled in, as a harness would send it
+    ///
+    /// `$ARGUMENTS` is replaced by all the arguments joined with spaces, `$1`, `$2`... by each
+    /// of them. The instruction to read the riglet's docs first is included when the command has
+    /// one
+    Render {
+        /// Flake reference in the form `<flake>#<rig>`, e.g. `.#default`
+        flake_ref: String,
+        /// Prompt command, as `riglet:command`
+        name: String,
+        /// Arguments of the prompt command
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+        /// Disable auto-staging of rigup.local.toml
+        #[arg(long)]
+        no_stage: bool,
+        /// Re-evaluate the flake instead of reusing a cached evaluation
+        #[arg(long)]
+        refresh: bool,
+        #[command(flatten)]
+        extensions: RigExtensions,
+        #[command(flatten)]
+        overrides: ConfigOverrides,
+    },
+}
+
+#[derive(Subcommand)]
+enum PermissionsCommands {
+    /// Tell whether a shell command would be allowed by the rig's blanket allow over its
+    /// executables, or denied by one of its deny rules, and by which riglet
+    ///
+    /// A command is denied when it starts with the tool then the words of a deny rule's
+    /// pattern. Command lines are split like a shell would at `;`, `&&`, `||`, `|`, `&` and
+    /// newlines (outside quotes), and each command is checked. Lines with command substitutions,
+    /// subshells or unbalanced quotes are denied. Exits with an error if the command is denied
+    Check {
+        /// Flake reference in the form `<flake>#<rig>`, e.g. `.#default`
+        flake_ref: String,
+        /// The command to check, e.g. `-- git push origin main`
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+        /// Disable auto-staging of rigup.local.toml
+        #[arg(long)]
+        no_stage: bool,
+        /// Re-evaluate the flake instead of reusing a cached evaluation
+        #[arg(long)]
+        refresh: bool,
+        #[command(flatten)]
+        extensions: RigExtensions,
+        #[command(flatten)]
+        overrides: ConfigOverrides,
+    },
+    /// Print the effective allow and deny rules of a rig
+    List {
+        /// Flake reference in the form `<flake>#<rig>` (defaults to `.#default`)
+        ///
+        /// Current repo must use `.#` prefix. Examples: `.#myrig`, `github:user/repo`, `github:user/repo#myrig`
+        flake_ref: Option<String>,
+        /// Disable paging through less
+        #[arg(short = 'P', long)]
+        no_pager: bool,
+        /// Disable auto-staging of rigup.local.toml
+        #[arg(long)]
+        no_stage: bool,
+        /// Re-evaluate the flake instead of reusing a cached evaluation
+        #[arg(long)]
+        refresh: bool,
+        #[command(flatten)]
+        extensions: RigExtensions,
+        #[command(flatten)]
+        overrides: ConfigOverrides,
+    },
+}
+
 #[derive(Clone, Debug)]
 enum SupportedShell {
     Standard(Shell),
@@ -175,10 +694,15 @@ fn main() -> Result<()> {
     match cli.command {
         Some(Commands::Browse {
             flake_ref,
-            with_,
+            open_with,
             no_stage,
+            ext,
         }) => {
-            browse_rig_docs(with_, flake_ref, no_stage)?;
+            let extensions = RigExtensions {
+                with: Vec::new(),
+                ext,
+            };
+            browse_rig_docs(open_with, flake_ref, no_stage, &extensions)?;
         }
         Some(Commands::New {
             directory,
@@ -189,44 +713,289 @@ fn main() -> Result<()> {
         Some(Commands::Build {
             flake_ref,
             no_stage,
+            extensions,
+            overrides,
         }) => {
-            build_rig(flake_ref, no_stage)?;
+            build_rig(flake_ref, no_stage, &extensions, &overrides)?;
         }
         Some(Commands::Shell {
             flake_ref,
             command,
             no_stage,
+            extensions,
+            overrides,
+        }) => {
+            enter_shell(flake_ref, command, no_stage, &extensions, &overrides)?;
+        }
+        Some(Commands::Try {
+            riglets,
+            flake,
+            run: _,
+            shell,
+            build,
+            args,
+            no_stage,
+            overrides,
+        }) => {
+            let action = if shell {
+                TryAction::Shell
+            } else if build {
+                TryAction::Build
+            } else {
+                TryAction::Run
+            };
+            try_riglets(flake, riglets, action, &args, no_stage, &overrides)?;
+        }
+        Some(Commands::Add {
+            rig,
+            riglets,
+            local,
+            no_stage,
+        }) => {
+            add_riglets(rig, riglets, local, no_stage)?;
+        }
+        Some(Commands::Remove {
+            rig,
+            riglets,
+            local,
+        }) => {
+            remove_riglets(rig, riglets, local)?;
+        }
+        Some(Commands::Rig { command }) => match command {
+            RigCommands::Create {
+                name,
+                extends,
+                riglets,
+                local,
+                no_stage,
+            } => create_rig(name, extends, riglets, local, no_stage)?,
+            RigCommands::Rename { old, new } => rename_rig(old, new)?,
+            RigCommands::Delete { name } => delete_rig(name)?,
+        },
+        Some(Commands::Validate) => {
+            validate_project()?;
+        }
+        Some(Commands::Rigs {
+            tree,
+            format,
+            no_pager,
         }) => {
-            enter_shell(flake_ref, command, no_stage)?;
+            list_rigs(format.or(tree.then_some(GraphFormat::Tree)), no_pager)?;
         }
+        Some(Commands::Config { command }) => match command {
+            ConfigCommands::Get {
+                rig,
+                option,
+                no_stage,
+            } => config_get(rig, option, no_stage)?,
+            ConfigCommands::Set {
+                rig,
+                option,
+                value,
+                no_stage,
+            } => config_set(rig, option, value, no_stage)?,
+            ConfigCommands::Unset { rig, option } => config_unset(rig, option)?,
+        },
         Some(Commands::Show {
             flake,
             with_inputs,
+            jobs,
             no_pager,
             detailed,
             no_descriptions,
             no_stage,
+            refresh,
+            filter,
+            format,
         }) => {
             show_flake(
                 flake,
                 with_inputs,
+                jobs,
                 no_pager,
                 detailed,
                 no_descriptions,
                 no_stage,
+                refresh,
+                &filter,
+                format,
             )?;
         }
+        Some(Commands::Search {
+            query,
+            flake,
+            limit,
+            no_pager,
+            no_stage,
+            refresh,
+        }) => {
+            search_riglets(query, flake, limit, no_pager, no_stage, refresh)?;
+        }
         Some(Commands::Inspect {
             flake_ref,
             no_pager,
             detailed,
             no_descriptions,
             no_stage,
+            refresh,
+            format,
+            extensions,
+            overrides,
         }) => {
-            inspect_rig(flake_ref, no_pager, detailed, no_descriptions, no_stage)?;
+            inspect_rig(
+                flake_ref,
+                no_pager,
+                detailed,
+                no_descriptions,
+                no_stage,
+                refresh,
+                format,
+                &extensions,
+                &overrides,
+            )?;
+        }
+        Some(Commands::Mcp { command }) => match command {
+            McpCommands::Serve {
+                flake_ref,
+                tool_timeout,
+                no_stage,
+                extensions,
+                overrides,
+            } => serve_rig(
+                flake_ref,
+                Duration::from_secs(tool_timeout),
+                no_stage,
+                &extensions,
+                &overrides,
+            )?,
+            McpCommands::Check {
+                flake_ref,
+                timeout,
+                no_stage,
+                extensions,
+                overrides,
+            } => check_mcp_servers(
+                flake_ref,
+                Duration::from_secs(timeout),
+                no_stage,
+                &extensions,
+                &overrides,
+            )?,
+        },
+        Some(Commands::Prompt { command }) => match command {
+            PromptCommands::List {
+                flake_ref,
+                no_pager,
+                no_stage,
+                refresh,
+                extensions,
+                overrides,
+            } => list_prompts(
+                flake_ref,
+                no_pager,
+                no_stage,
+                refresh,
+                &extensions,
+                &overrides,
+            )?,
+            PromptCommands::Render {
+                flake_ref,
+                name,
+                args,
+                no_stage,
+                refresh,
+                extensions,
+                overrides,
+            } => render_prompt(
+                flake_ref,
+                name,
+                args,
+                no_stage,
+                refresh,
+                &extensions,
+                &overrides,
+            )?,
+        },
+        Some(Commands::Audit {
+            flake_ref,
+            fail_on,
+            format,
+            no_pager,
+            no_stage,
+            refresh,
+            extensions,
+            overrides,
+        }) => audit_rig(
+            flake_ref,
+            fail_on,
+            format,
+            no_pager,
+            no_stage,
+            refresh,
+            &extensions,
+            &overrides,
+        )?,
+        Some(Commands::Permissions { command }) => match command {
+            PermissionsCommands::Check {
+                flake_ref,
+                command,
+                no_stage,
+                refresh,
+                extensions,
+                overrides,
+            } => check_permission(
+                flake_ref,
+                command,
+                no_stage,
+                refresh,
+                &extensions,
+                &overrides,
+            )?,
+            PermissionsCommands::List {
+                flake_ref,
+                no_pager,
+                no_stage,
+                refresh,
+                extensions,
+                overrides,
+            } => list_permissions(
+                flake_ref,
+                no_pager,
+                no_stage,
+                refresh,
+                &extensions,
+                &overrides,
+            )?,
+        },
+        Some(Commands::Graph {
+            flake_ref,
+            format,
+            no_pager,
+            no_stage,
+            refresh,
+            extensions,
+        }) => {
+            show_riglet_graph(flake_ref, format, no_pager, no_stage, refresh, &extensions)?;
+        }
+        Some(Commands::Diff {
+            old_ref,
+            new_ref,
+            rev,
+            no_pager,
+            no_stage,
+            refresh,
+            format,
+        }) => {
+            diff_rigs(old_ref, new_ref, rev, no_pager, no_stage, refresh, format)?;
         }
         Some(Commands::Run(run_args)) => {
-            run_entrypoint(run_args.flake_ref, &run_args.args, run_args.no_stage)?;
+            run_entrypoint(
+                run_args.flake_ref,
+                &run_args.args,
+                run_args.no_stage,
+                &run_args.extensions,
+                &run_args.overrides,
+            )?;
         }
         Some(Commands::Completions { shell }) => {
             let mut cmd = Cli::command();
@@ -243,6 +1012,8 @@ fn main() -> Result<()> {
                 cli.run_args.flake_ref,
                 &cli.run_args.args,
                 cli.run_args.no_stage,
+                &cli.run_args.extensions,
+                &cli.run_args.overrides,
             )?;
         }
     }
@@ -0,0 +1,634 @@
+use crate::prompt_template::{self, PromptCommand};
+use miette::{IntoDiagnostic, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{BufRead, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+/// MCP protocol versions that the server speaks, latest first
+pub const PROTOCOL_VERSIONS: [&str; 3] = ["2025-06-18", "2025-03-26", "2024-11-05"];
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const RESOURCE_NOT_FOUND: i64 = -32002;
+
+/// A riglet's metadata, as far as describing its tools goes
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RigletTools {
+    pub description: String,
+    pub command_names: Vec<String>,
+}
+
+/// An error to answer a JSON-RPC request with
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid_params(message: impl Into<String>) -> Self {
+        RpcError {
+            code: INVALID_PARAMS,
+            message: message.into(),
+        }
+    }
+}
+
+/// Serves a built rig over MCP: its manifest and docs as resources, its prompt commands as
+/// prompts and its executables as tools
+pub struct RigServer {
+    /// Name of the rig, used in resource URIs
+    pub rig: String,
+    /// The rig's home, as built by `rigup build`
+    pub home: PathBuf,
+    pub prompts: BTreeMap<String, PromptCommand>,
+    /// Metadata of the rig's riglets
+    pub riglets: BTreeMap<String, RigletTools>,
+    /// How long a tool may run before it is killed
+    pub tool_timeout: Duration,
+}
+
+/// What a tool printed, and how it exited (`None` if it was killed for running too long)
+struct ToolOutput {
+    status: Option<ExitStatus>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+/// Read a child's output pipe to the end in a thread, so that no pipe fills up while the others
+/// are written to or read
+fn read_in_background(pipe: Option<impl Read + Send + 'static>) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+        let _ = sender.send(bytes);
+    });
+    receiver
+}
+
+/// Run a tool with the given standard input, killing it once `timeout` has passed
+fn run_tool(
+    command: &mut Command,
+    stdin: String,
+    timeout: Duration,
+) -> std::io::Result<ToolOutput> {
+    let deadline = Instant::now() + timeout;
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut child_stdin) = child.stdin.take() {
+        std::thread::spawn(move || {
+            // The command may exit without reading its input
+            let _ = child_stdin.write_all(stdin.as_bytes());
+        });
+    }
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            break None;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    };
+    // Processes that the tool left in the background may hold its output open: don't wait for them
+    let grace = Duration::from_secs(1);
+    Ok(ToolOutput {
+        status,
+        stdout: stdout.recv_timeout(grace).unwrap_or_default(),
+        stderr: stderr.recv_timeout(grace).unwrap_or_default(),
+    })
+}
+
+fn mime_type(path: &Path, is_text: bool) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("md") => "text/markdown",
+        Some("json") => "application/json",
+        Some("html") => "text/html",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("svg") => "image/svg+xml",
+        Some("pdf") => "application/pdf",
+        _ if is_text => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (idx, byte)| n | (*byte as u32) << (16 - 8 * idx));
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * idx) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// All the files under a folder, following symlinks (docRoot is made of symlinks to the
+/// riglets' docs), as paths relative to it
+fn files_under(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut stack = vec![PathBuf::new()];
+    while let Some(rel_dir) = stack.pop() {
+        let Ok(entries) = std::fs::read_dir(dir.join(&rel_dir)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let rel_path = rel_dir.join(entry.file_name());
+            match std::fs::metadata(entry.path()) {
+                Ok(metadata) if metadata.is_dir() => stack.push(rel_path),
+                Ok(_) => files.push(rel_path),
+                Err(_) => {}
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+fn string_param<'a>(params: &'a Value, name: &str) -> Result<&'a str, RpcError> {
+    params
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::invalid_params(format!("Missing string parameter `{}`", name)))
+}
+
+impl RigServer {
+    fn uri_prefix(&self) -> String {
+        format!("rig://{}/", self.rig)
+    }
+
+    fn manifest_uri(&self) -> String {
+        format!("{}RIG.md", self.uri_prefix())
+    }
+
+    fn initialize(&self, params: &Value) -> Value {
+        // Answer with the version the client asked for if we speak it, else with our latest
+        let requested = params.get("protocolVersion").and_then(Value::as_str);
+        let version = PROTOCOL_VERSIONS
+            .into_iter()
+            .find(|version| Some(*version) == requested)
+            .unwrap_or(PROTOCOL_VERSIONS[0]);
+        json!({
+            "protocolVersion": version,
+            "capabilities": { "resources": {}, "prompts": {}, "tools": {} },
+            "serverInfo": { "name": "rigup", "version": env!("CARGO_PKG_VERSION") },
+            "instructions": format!(
+                "This server exposes the `{}` rig. Read resource {} first and foremost: it tells \
+                 which docs (other resources) and tools the rig provides, and when to use them",
+                self.rig,
+                self.manifest_uri()
+            ),
+        })
+    }
+
+    fn list_resources(&self) -> Value {
+        let mut resources = vec![json!({
+            "uri": self.manifest_uri(),
+            "name": "RIG.md",
+            "description": format!("Manifest of rig `{}`: read it first", self.rig),
+            "mimeType": "text/markdown",
+        })];
+        for rel_path in files_under(&self.home.join("docs")) {
+            let rel_path = Path::new("docs").join(rel_path);
+            resources.push(json!({
+                "uri": format!("{}{}", self.uri_prefix(), rel_path.display()),
+                "name": rel_path.display().to_string(),
+                "mimeType": mime_type(&rel_path, true),
+            }));
+        }
+        json!({ "resources": resources })
+    }
+
+    fn read_resource(&self, params: &Value) -> Result<Value, RpcError> {
+        let uri = string_param(params, "uri")?;
+        let not_found = || RpcError {
+            code: RESOURCE_NOT_FOUND,
+            message: format!("Resource not found: {}", uri),
+        };
+        // Only RIG.md and the files of docs/ are served, and never outside of the rig's home
+        let rel_path = Path::new(uri.strip_prefix(&self.uri_prefix()).ok_or_else(not_found)?);
+        let is_served = rel_path == Path::new("RIG.md")
+            || (rel_path.starts_with("docs")
+                && rel_path
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_))));
+        if !is_served {
+            return Err(not_found());
+        }
+        let bytes = std::fs::read(self.home.join(rel_path)).map_err(|_| not_found())?;
+        let content = match String::from_utf8(bytes) {
+            Ok(text) => json!({ "uri": uri, "mimeType": mime_type(rel_path, true), "text": text }),
+            Err(error) => json!({
+                "uri": uri,
+                "mimeType": mime_type(rel_path, false),
+                "blob": base64(error.as_bytes()),
+            }),
+        };
+        Ok(json!({ "contents": [content] }))
+    }
+
+    fn list_prompts(&self) -> Value {
+        let prompts: Vec<Value> = self
+            .prompts
+            .iter()
+            .map(|(name, prompt)| {
+                let mut arguments = Vec::new();
+                if prompt_template::uses_all_arguments(&prompt.template) {
+                    arguments.push(json!({
+                        "name": "arguments",
+                        "description": "All the arguments, separated by spaces ($ARGUMENTS)",
+                        "required": false,
+                    }));
+                }
+                for position in 1..=prompt_template::positional_count(&prompt.template) {
+                    arguments.push(json!({
+                        "name": format!("arg{}", position),
+                        "description": format!("Argument ${}", position),
+                        "required": false,
+                    }));
+                }
+                json!({
+                    "name": name,
+                    "description": prompt.description,
+                    "arguments": arguments,
+                })
+            })
+            .collect();
+        json!({ "prompts": prompts })
+    }
+
+    fn get_prompt(&self, params: &Value) -> Result<Value, RpcError> {
+        let name = string_param(params, "name")?;
+        let prompt = self
+            .prompts
+            .get(name)
+            .ok_or_else(|| RpcError::invalid_params(format!("Unknown prompt: {}", name)))?;
+        let arguments = params.get("arguments");
+        let argument = |name: &str| {
+            arguments
+                .and_then(|arguments| arguments.get(name))
+                .and_then(Value::as_str)
+        };
+        // Single arguments can be given one by one, or else are taken from all the arguments
+        let all_arguments = argument("arguments");
+        let count = prompt_template::positional_count(&prompt.template);
+        let mut positional: Vec<String> = (1..=count)
+            .map(|position| {
+                argument(&format!("arg{}", position))
+                    .unwrap_or_default()
+                    .to_string()
+            })
+            .collect();
+        if positional.iter().all(String::is_empty) {
+            positional = all_arguments
+                .unwrap_or_default()
+                .split_whitespace()
+                .map(str::to_string)
+                .collect();
+        }
+        let all_arguments = all_arguments
+            .map(str::to_string)
+            .unwrap_or_else(|| positional.join(" "));
+        let text = prompt_template::render(&prompt.template, &all_arguments, &positional);
+        Ok(json!({
+            "description": prompt.description,
+            "messages": [{ "role": "user", "content": { "type": "text", "text": text } }],
+        }))
+    }
+
+    fn tool_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(self.home.join(".local/bin"))
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn list_tools(&self) -> Value {
+        let tools: Vec<Value> = self
+            .tool_names()
+            .into_iter()
+            .map(|name| {
+                let description = self
+                    .riglets
+                    .iter()
+                    .find(|(_, riglet)| riglet.command_names.contains(&name))
+                    .map(|(riglet_name, riglet)| {
+                        format!(
+                            "Run `{}`, from riglet {}: {}",
+                            name, riglet_name, riglet.description
+                        )
+                    })
+                    .unwrap_or_else(|| format!("Run `{}`", name));
+                json!({
+                    "name": name,
+                    "description": description,
+                    "inputSchema": {
+                        "type": "object",
+                        "properties": {
+                            "args": {
+                                "type": "array",
+                                "items": { "type": "string" },
+                                "description": "Command-line arguments",
+                            },
+                            "stdin": {
+                                "type": "string",
+                                "description": "Text to send to the command's standard input",
+                            },
+                        },
+                    },
+                })
+            })
+            .collect();
+        json!({ "tools": tools })
+    }
+
+    /// Run one of the rig's executables with the rig's tools in PATH and its config folder as
+    /// XDG_CONFIG_HOME, like in `rigup shell`. Tools that outrun `tool_timeout` are killed
+    fn call_tool(&self, params: &Value) -> Result<Value, RpcError> {
+        let name = string_param(params, "name")?;
+        if !self.tool_names().iter().any(|tool| tool == name) {
+            return Err(RpcError::invalid_params(format!("Unknown tool: {}", name)));
+        }
+        let arguments = params.get("arguments");
+        let args: Vec<&str> = arguments
+            .and_then(|arguments| arguments.get("args"))
+            .and_then(Value::as_array)
+            .map(|args| args.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+        let stdin = arguments
+            .and_then(|arguments| arguments.get("stdin"))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+
+        let bin_dir = self.home.join(".local/bin");
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        let path = std::env::join_paths(
+            std::iter::once(bin_dir.clone()).chain(std::env::split_paths(&path)),
+        )
+        .unwrap_or(path);
+        let output = run_tool(
+            Command::new(bin_dir.join(name))
+                .args(&args)
+                .env("PATH", path)
+                .env("XDG_CONFIG_HOME", self.home.join(".config")),
+            stdin.to_string(),
+            self.tool_timeout,
+        );
+
+        let (text, is_error) = match output {
+            Ok(output) => {
+                let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+                if !output.stderr.is_empty() {
+                    text.push_str(&format!(
+                        "\n[stderr]\n{}",
+                        String::from_utf8_lossy(&output.stderr)
+                    ));
+                }
+                match output.status {
+                    Some(status) if status.success() => (text, false),
+                    Some(status) => {
+                        text.push_str(&format!("\n[{}]", status));
+                        (text, true)
+                    }
+                    None => {
+                        text.push_str(&format!(
+                            "\n[killed after {}s]",
+                            self.tool_timeout.as_secs_f64()
+                        ));
+                        (text, true)
+                    }
+                }
+            }
+            Err(error) => (format!("Failed to run `{}`: {}", name, error), true),
+        };
+        Ok(json!({
+            "content": [{ "type": "text", "text": text }],
+            "isError": is_error,
+        }))
+    }
+
+    /// Answer a JSON-RPC message. Notifications get no answer
+    pub fn handle_message(&self, message: &Value) -> Option<Value> {
+        let id = message.get("id")?.clone();
+        let method = message.get("method").and_then(Value::as_str).unwrap_or("");
+        let params = message.get("params").cloned().unwrap_or(json!({}));
+        let result = match method {
+            "initialize" => Ok(self.initialize(&params)),
+            "ping" => Ok(json!({})),
+            "resources/list" => Ok(self.list_resources()),
+            "resources/read" => self.read_resource(&params),
+            "prompts/list" => Ok(self.list_prompts()),
+            "prompts/get" => self.get_prompt(&params),
+            "tools/list" => Ok(self.list_tools()),
+            "tools/call" => self.call_tool(&params),
+            _ => Err(RpcError {
+                code: METHOD_NOT_FOUND,
+                message: format!("Method not found: {}", method),
+            }),
+        };
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": error.code, "message": error.message },
+            }),
+        })
+    }
+
+    /// Serve newline-delimited JSON-RPC messages (the MCP stdio transport) until `input` ends
+    pub fn serve(&self, input: impl BufRead, mut output: impl Write) -> Result<()> {
+        for line in input.lines() {
+            let line = line.into_diagnostic()?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match serde_json::from_str::<Value>(&line) {
+                Ok(message) => self.handle_message(&message),
+                Err(error) => Some(json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": { "code": PARSE_ERROR, "message": error.to_string() },
+                })),
+            };
+            if let Some(response) = response {
+                writeln!(output, "{}", response).into_diagnostic()?;
+                output.flush().into_diagnostic()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob\xff"), "Zm9vYv8=");
+    }
+
+    /// Drive a server with a scripted session, like an MCP client would
+    #[test]
+    fn test_scripted_session() {
+        let home = std::env::temp_dir().join(format!("rigup-mcp-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&home);
+        std::fs::create_dir_all(home.join("docs/git-setup")).unwrap();
+        std::fs::create_dir_all(home.join(".local/bin")).unwrap();
+        std::fs::create_dir_all(home.join(".config")).unwrap();
+        std::fs::write(home.join("RIG.md"), "# Rig\n").unwrap();
+        std::fs::write(home.join("docs/git-setup/SKILL.md"), "Use git\n").unwrap();
+        let tool = home.join(".local/bin/greet");
+        std::fs::write(
+            &tool,
+            "#!/bin/sh\necho \"hello $1 from $XDG_CONFIG_HOME\"\ncat\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let server = RigServer {
+            rig: "default".to_string(),
+            home: home.clone(),
+            prompts: BTreeMap::from([(
+                "git-setup:review".to_string(),
+                PromptCommand {
+                    description: "Review a branch".to_string(),
+                    template: "Review $1 ($ARGUMENTS)".to_string(),
+                    use_sub_agent: false,
+                },
+            )]),
+            riglets: BTreeMap::from([(
+                "greeter".to_string(),
+                RigletTools {
+                    description: "Greetings".to_string(),
+                    command_names: vec!["greet".to_string()],
+                },
+            )]),
+            tool_timeout: Duration::from_secs(10),
+        };
+        let script = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"protocolVersion":"2024-11-05","capabilities":{},"clientInfo":{"name":"test","version":"0"}}}"#,
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"resources/list"}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"resources/read","params":{"uri":"rig://default/docs/git-setup/SKILL.md"}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"resources/read","params":{"uri":"rig://default/docs/../../etc/passwd"}}"#,
+            r#"{"jsonrpc":"2.0","id":5,"method":"prompts/get","params":{"name":"git-setup:review","arguments":{"arguments":"main --strict"}}}"#,
+            r#"{"jsonrpc":"2.0","id":6,"method":"tools/list"}"#,
+            r#"{"jsonrpc":"2.0","id":7,"method":"tools/call","params":{"name":"greet","arguments":{"args":["you"],"stdin":"bye"}}}"#,
+            r#"{"jsonrpc":"2.0","id":8,"method":"unknown"}"#,
+        ]
+        .join("\n");
+        let mut output = Vec::new();
+        server.serve(script.as_bytes(), &mut output).unwrap();
+        let responses: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(responses.len(), 8, "notifications get no response");
+        assert_eq!(responses[0]["result"]["protocolVersion"], "2024-11-05");
+        let uris: Vec<&str> = responses[1]["result"]["resources"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|resource| resource["uri"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            uris,
+            [
+                "rig://default/RIG.md",
+                "rig://default/docs/git-setup/SKILL.md"
+            ]
+        );
+        assert_eq!(responses[2]["result"]["contents"][0]["text"], "Use git\n");
+        assert_eq!(responses[3]["error"]["code"], RESOURCE_NOT_FOUND);
+        assert_eq!(
+            responses[4]["result"]["messages"][0]["content"]["text"],
+            "Review main (main --strict)"
+        );
+        assert_eq!(
+            responses[5]["result"]["tools"][0]["description"],
+            "Run `greet`, from riglet greeter: Greetings"
+        );
+        assert_eq!(
+            responses[6]["result"]["content"][0]["text"],
+            format!("hello you from {}\nbye", home.join(".config").display())
+        );
+        assert_eq!(responses[6]["result"]["isError"], false);
+        assert_eq!(responses[7]["error"]["code"], METHOD_NOT_FOUND);
+        std::fs::remove_dir_all(&home).unwrap();
+    }
+
+    /// Tools get input larger than a pipe holds while they print as much, and are killed once
+    /// they outrun the timeout
+    #[test]
+    fn test_call_tool() {
+        let home = std::env::temp_dir().join(format!("rigup-mcp-tool-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&home);
+        std::fs::create_dir_all(home.join(".local/bin")).unwrap();
+        for (name, script) in [("echo-input", "cat\n"), ("hang", "sleep 30\n")] {
+            let tool = home.join(".local/bin").join(name);
+            std::fs::write(&tool, format!("#!/bin/sh\n{}", script)).unwrap();
+            std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let server = RigServer {
+            rig: "default".to_string(),
+            home: home.clone(),
+            prompts: BTreeMap::new(),
+            riglets: BTreeMap::new(),
+            tool_timeout: Duration::from_millis(500),
+        };
+
+        let input = "x".repeat(1 << 20);
+        let result = server
+            .call_tool(&json!({ "name": "echo-input", "arguments": { "stdin": input } }))
+            .unwrap_or_else(|_| panic!("echo-input should run"));
+        assert_eq!(result["isError"], false);
+        assert_eq!(result["content"][0]["text"], input.as_str());
+
+        let started = Instant::now();
+        let result = server
+            .call_tool(&json!({ "name": "hang" }))
+            .unwrap_or_else(|_| panic!("hang should run"));
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(result["isError"], true);
+        assert_eq!(result["content"][0]["text"], "\n[killed after 0.5s]");
+        std::fs::remove_dir_all(&home).unwrap();
+    }
+}
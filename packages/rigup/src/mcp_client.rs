@@ -0,0 +1,898 @@
+use crate::mcp::PROTOCOL_VERSIONS;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How to reach an MCP server (see `mcpServer` in rigletSchema.nix)
+pub enum ServerEndpoint {
+    /// A local server, started as this executable and spoken to over stdio
+    Command(PathBuf),
+    /// A remote server, spoken to over streamable HTTP, or with `sse` over the older HTTP+SSE
+    /// transport
+    Http {
+        url: String,
+        headers: BTreeMap<String, String>,
+        sse: bool,
+    },
+}
+
+impl ServerEndpoint {
+    pub fn transport_name(&self) -> &'static str {
+        match self {
+            ServerEndpoint::Command(_) => "stdio",
+            ServerEndpoint::Http { sse: false, .. } => "http",
+            ServerEndpoint::Http { sse: true, .. } => "sse",
+        }
+    }
+}
+
+/// What a server told about itself during the handshake
+#[derive(Debug)]
+pub struct ServerReport {
+    pub name: String,
+    pub version: String,
+    pub protocol_version: String,
+    /// None if the server does not have the `tools` capability
+    pub tool_count: Option<usize>,
+}
+
+/// Why a handshake failed, with what the server printed on stderr if it is local
+#[derive(Debug)]
+pub struct HandshakeFailure {
+    pub reason: String,
+    pub stderr: Option<String>,
+}
+
+/// Exchanges JSON-RPC messages with a server
+trait Transport {
+    fn send(&mut self, message: &Value, deadline: Instant) -> Result<(), String>;
+    /// The next message from the server
+    fn receive(&mut self, deadline: Instant) -> Result<Value, String>;
+    /// What the server printed on stderr so far, for local servers
+    fn stderr(&self) -> Option<String> {
+        None
+    }
+}
+
+fn remaining(deadline: Instant) -> Duration {
+    deadline.saturating_duration_since(Instant::now())
+}
+
+fn timed_out(timeout: Duration) -> String {
+    format!(
+        "The server did not answer within {}s",
+        timeout.as_secs_f64()
+    )
+}
+
+/// A local server, started as a child process
+struct StdioTransport {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    stderr: Arc<Mutex<String>>,
+    /// Disconnected once all of the server's stderr has been read
+    stderr_read: Receiver<()>,
+    timeout: Duration,
+}
+
+impl StdioTransport {
+    fn spawn(command: &PathBuf, timeout: Duration) -> Result<Self, String> {
+        let mut child = Command::new(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|error| format!("Failed to start {}: {}", command.display(), error))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let mut child_stderr = child.stderr.take().expect("stderr is piped");
+
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(|line| line.ok()) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let stderr = Arc::new(Mutex::new(String::new()));
+        let stderr_writer = Arc::clone(&stderr);
+        let (stderr_done, stderr_read) = mpsc::channel();
+        std::thread::spawn(move || {
+            let _stderr_done = stderr_done;
+            let mut buffer = [0; 4096];
+            while let Ok(read @ 1..) = child_stderr.read(&mut buffer) {
+                if let Ok(mut stderr) = stderr_writer.lock() {
+                    stderr.push_str(&String::from_utf8_lossy(&buffer[..read]));
+                }
+            }
+        });
+        Ok(StdioTransport {
+            child,
+            stdin,
+            lines,
+            stderr,
+            stderr_read,
+            timeout,
+        })
+    }
+
+    /// The failure of a server that exited, once what it printed on stderr has been read (unless
+    /// it left a process behind that still holds its stderr)
+    fn exited(&mut self, status: String, deadline: Instant) -> String {
+        let _ = self.stderr_read.recv_timeout(remaining(deadline));
+        format!("The server exited before answering ({})", status)
+    }
+}
+
+impl Transport for StdioTransport {
+    fn send(&mut self, message: &Value, deadline: Instant) -> Result<(), String> {
+        writeln!(self.stdin, "{}", message)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|error| {
+                // Writing fails once the server exited, which is what to report then, like `receive` does
+                while Instant::now() < deadline {
+                    if let Ok(Some(status)) = self.child.try_wait() {
+                        return self.exited(status.to_string(), deadline);
+                    }
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                format!("Failed to write to the server: {}", error)
+            })
+    }
+
+    fn receive(&mut self, deadline: Instant) -> Result<Value, String> {
+        loop {
+            match self.lines.recv_timeout(remaining(deadline)) {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => {
+                    return serde_json::from_str(&line).map_err(|_| {
+                        format!("The server wrote something else than JSON-RPC: {}", line)
+                    })
+                }
+                Err(RecvTimeoutError::Timeout) => return Err(timed_out(self.timeout)),
+                Err(RecvTimeoutError::Disconnected) => {
+                    let status = self
+                        .child
+                        .wait()
+                        .map(|status| status.to_string())
+                        .unwrap_or_else(|error| error.to_string());
+                    return Err(self.exited(status, deadline));
+                }
+            }
+        }
+    }
+
+    fn stderr(&self) -> Option<String> {
+        let stderr = self.stderr.lock().ok()?;
+        (!stderr.trim().is_empty()).then(|| stderr.trim_end().to_string())
+    }
+}
+
+impl Drop for StdioTransport {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Quote a value for a curl config file
+fn curl_quote(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+            .replace('\r', "\\r")
+            .replace('\t', "\\t")
+    )
+}
+
+/// A curl config for a request to a server. Giving it on stdin keeps the headers, which may hold
+/// secrets, out of the command line
+fn curl_config(url: &str, headers: &[(String, String)], body: Option<&str>) -> String {
+    let mut config = vec![format!("url = {}", curl_quote(url))];
+    for (name, value) in headers {
+        config.push(format!(
+            "header = {}",
+            curl_quote(&format!("{}: {}", name, value))
+        ));
+    }
+    if let Some(body) = body {
+        config.push(format!("data-binary = {}", curl_quote(body)));
+    }
+    config.join("\n") + "\n"
+}
+
+fn spawn_curl(args: &[&str], config: &str) -> Result<Child, String> {
+    let mut child = Command::new("curl")
+        .args(["--silent", "--show-error", "--config", "-"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| format!("Failed to run curl: {}", error))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    stdin
+        .write_all(config.as_bytes())
+        .map_err(|error| format!("Failed to run curl: {}", error))?;
+    Ok(child)
+}
+
+/// An HTTP response, as `curl --include` prints it
+struct HttpResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl HttpResponse {
+    fn parse(output: &str) -> Option<Self> {
+        // Skip interim responses like `100 Continue`
+        let mut rest = output;
+        loop {
+            let (head, body) = rest.split_once("\r\n\r\n").unwrap_or((rest, ""));
+            let mut lines = head.lines();
+            let status: u16 = lines.next()?.split_whitespace().nth(1)?.parse().ok()?;
+            if (100..200).contains(&status) {
+                rest = body;
+                continue;
+            }
+            let headers = lines
+                .filter_map(|line| {
+                    let (name, value) = line.split_once(':')?;
+                    Some((name.trim().to_lowercase(), value.trim().to_string()))
+                })
+                .collect();
+            return Some(HttpResponse {
+                status,
+                headers,
+                body: body.to_string(),
+            });
+        }
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// POST a message to a server and return its response
+fn post(
+    url: &str,
+    headers: &[(String, String)],
+    message: &Value,
+    deadline: Instant,
+    timeout: Duration,
+) -> Result<HttpResponse, String> {
+    let mut headers = headers.to_vec();
+    headers.push(("Content-Type".to_string(), "application/json".to_string()));
+    headers.push((
+        "Accept".to_string(),
+        "application/json, text/event-stream".to_string(),
+    ));
+    let config = curl_config(url, &headers, Some(&message.to_string()));
+    let seconds = remaining(deadline).as_secs_f64().max(0.1).to_string();
+    let output = spawn_curl(&["--include", "--max-time", &seconds], &config)?
+        .wait_with_output()
+        .map_err(|error| format!("Failed to run curl: {}", error))?;
+    if !output.status.success() {
+        // Exit code 28 is curl's timeout
+        return Err(if output.status.code() == Some(28) {
+            timed_out(timeout)
+        } else {
+            String::from_utf8_lossy(&output.stderr).trim().to_string()
+        });
+    }
+    let response = HttpResponse::parse(&String::from_utf8_lossy(&output.stdout))
+        .ok_or_else(|| "The server sent an invalid HTTP response".to_string())?;
+    if !(200..300).contains(&response.status) {
+        return Err(format!(
+            "The server answered with HTTP status {}: {}",
+            response.status,
+            response.body.trim()
+        ));
+    }
+    Ok(response)
+}
+
+/// The `data` of the events of a `text/event-stream`, with their type
+fn parse_sse_events(stream: &str) -> Vec<(String, String)> {
+    let mut events = Vec::new();
+    let (mut event, mut data) = (String::from("message"), Vec::new());
+    for line in stream.lines().chain([""]) {
+        if line.is_empty() {
+            if !data.is_empty() {
+                events.push((event, data.join("\n")));
+            }
+            (event, data) = (String::from("message"), Vec::new());
+        } else if let Some(value) = line.strip_prefix("event:") {
+            event = value.trim().to_string();
+        } else if let Some(value) = line.strip_prefix("data:") {
+            data.push(value.strip_prefix(' ').unwrap_or(value).to_string());
+        }
+    }
+    events
+}
+
+/// A remote server speaking the streamable HTTP transport: each message is POSTed, and the
+/// response carries the answers, as JSON or as an event stream
+struct HttpTransport {
+    url: String,
+    headers: Vec<(String, String)>,
+    pending: VecDeque<Value>,
+    timeout: Duration,
+}
+
+impl Transport for HttpTransport {
+    fn send(&mut self, message: &Value, deadline: Instant) -> Result<(), String> {
+        let response = post(&self.url, &self.headers, message, deadline, self.timeout)?;
+        if let Some(session_id) = response.header("mcp-session-id") {
+            self.headers
+                .retain(|(name, _)| !name.eq_ignore_ascii_case("Mcp-Session-Id"));
+            self.headers
+                .push(("Mcp-Session-Id".to_string(), session_id.to_string()));
+        }
+        let is_stream = response
+            .header("content-type")
+            .is_some_and(|content_type| content_type.starts_with("text/event-stream"));
+        let bodies = if is_stream {
+            parse_sse_events(&response.body)
+                .into_iter()
+                .map(|(_, data)| data)
+                .collect()
+        } else {
+            vec![response.body]
+        };
+        for body in bodies.iter().filter(|body| !body.trim().is_empty()) {
+            match serde_json::from_str(body) {
+                Ok(Value::Array(messages)) => self.pending.extend(messages),
+                Ok(message) => self.pending.push_back(message),
+                Err(_) => {
+                    return Err(format!(
+                        "The server sent something else than JSON: {}",
+                        body
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self, _deadline: Instant) -> Result<Value, String> {
+        self.pending
+            .pop_front()
+            .ok_or_else(|| "The server sent no answer".to_string())
+    }
+}
+
+/// A remote server speaking the older HTTP+SSE transport: answers come from an event stream,
+/// which first tells where to POST messages
+struct SseTransport {
+    stream: Child,
+    events: Receiver<(String, String)>,
+    headers: Vec<(String, String)>,
+    endpoint: Option<String>,
+    url: String,
+    timeout: Duration,
+}
+
+impl SseTransport {
+    fn connect(
+        url: &str,
+        headers: Vec<(String, String)>,
+        timeout: Duration,
+    ) -> Result<Self, String> {
+        let mut stream_headers = headers.clone();
+        stream_headers.push(("Accept".to_string(), "text/event-stream".to_string()));
+        let mut stream = spawn_curl(
+            &["--no-buffer", "--fail"],
+            &curl_config(url, &stream_headers, None),
+        )?;
+        let stdout = stream.stdout.take().expect("stdout is piped");
+        let (sender, events) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut event = String::new();
+            for line in BufReader::new(stdout).lines().map_while(|line| line.ok()) {
+                event.push_str(&line);
+                event.push('\n');
+                if line.is_empty() {
+                    for parsed in parse_sse_events(&event) {
+                        if sender.send(parsed).is_err() {
+                            return;
+                        }
+                    }
+                    event.clear();
+                }
+            }
+        });
+        Ok(SseTransport {
+            stream,
+            events,
+            headers,
+            endpoint: None,
+            url: url.to_string(),
+            timeout,
+        })
+    }
+
+    fn next_event(&mut self, deadline: Instant) -> Result<(String, String), String> {
+        self.events
+            .recv_timeout(remaining(deadline))
+            .map_err(|error| match error {
+                RecvTimeoutError::Timeout => timed_out(self.timeout),
+                RecvTimeoutError::Disconnected => "The server closed the event stream".to_string(),
+            })
+    }
+
+    /// Where to POST messages, as told by the first `endpoint` event, resolved against the URL
+    /// of the stream
+    fn endpoint(&mut self, deadline: Instant) -> Result<String, String> {
+        while self.endpoint.is_none() {
+            let (event, data) = self.next_event(deadline)?;
+            if event == "endpoint" {
+                let endpoint = if data.contains("://") {
+                    data
+                } else {
+                    let after_scheme = self.url.find("://").map_or(0, |idx| idx + 3);
+                    let origin_end = self.url[after_scheme..]
+                        .find('/')
+                        .map_or(self.url.len(), |idx| after_scheme + idx);
+                    format!(
+                        "{}/{}",
+                        &self.url[..origin_end],
+                        data.trim_start_matches('/')
+                    )
+                };
+                self.endpoint = Some(endpoint);
+            }
+        }
+        Ok(self.endpoint.clone().unwrap_or_default())
+    }
+}
+
+impl Transport for SseTransport {
+    fn send(&mut self, message: &Value, deadline: Instant) -> Result<(), String> {
+        let endpoint = self.endpoint(deadline)?;
+        post(&endpoint, &self.headers, message, deadline, self.timeout).map(|_| ())
+    }
+
+    fn receive(&mut self, deadline: Instant) -> Result<Value, String> {
+        loop {
+            let (event, data) = self.next_event(deadline)?;
+            if event == "message" {
+                return serde_json::from_str(&data)
+                    .map_err(|_| format!("The server sent something else than JSON: {}", data));
+            }
+        }
+    }
+}
+
+impl Drop for SseTransport {
+    fn drop(&mut self) {
+        let _ = self.stream.kill();
+        let _ = self.stream.wait();
+    }
+}
+
+/// Send a request and wait for its response, skipping the notifications and requests that the
+/// server may send meanwhile
+fn request(
+    transport: &mut dyn Transport,
+    id: u64,
+    method: &str,
+    params: Value,
+    deadline: Instant,
+) -> Result<Value, String> {
+    let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+    transport.send(&message, deadline)?;
+    loop {
+        let response = transport.receive(deadline)?;
+        if response.get("id") != Some(&json!(id)) || response.get("method").is_some() {
+            continue;
+        }
+        if let Some(error) = response.get("error") {
+            return Err(format!(
+                "`{}` failed: {} (code {})",
+                method,
+                error.get("message").and_then(Value::as_str).unwrap_or(""),
+                error.get("code").unwrap_or(&Value::Null)
+            ));
+        }
+        return response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| format!("The response to `{}` has no result", method));
+    }
+}
+
+/// `initialize`, then `tools/list` (following its pages)
+fn handshake(transport: &mut dyn Transport, deadline: Instant) -> Result<ServerReport, String> {
+    let initialized = request(
+        transport,
+        1,
+        "initialize",
+        json!({
+            "protocolVersion": PROTOCOL_VERSIONS[0],
+            "capabilities": {},
+            "clientInfo": { "name": "rigup", "version": env!("CARGO_PKG_VERSION") },
+        }),
+        deadline,
+    )?;
+    let info = |field: &str| {
+        initialized
+            .pointer(&format!("/serverInfo/{}", field))
+            .and_then(Value::as_str)
+            .unwrap_or("?")
+            .to_string()
+    };
+    let report = ServerReport {
+        name: info("name"),
+        version: info("version"),
+        protocol_version: initialized
+            .get("protocolVersion")
+            .and_then(Value::as_str)
+            .ok_or("The response to `initialize` has no protocolVersion")?
+            .to_string(),
+        tool_count: None,
+    };
+    transport.send(
+        &json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+        deadline,
+    )?;
+    if initialized.pointer("/capabilities/tools").is_none() {
+        return Ok(report);
+    }
+
+    let mut tool_count = 0;
+    let mut cursor: Option<String> = None;
+    for id in 2.. {
+        let params = match &cursor {
+            Some(cursor) => json!({ "cursor": cursor }),
+            None => json!({}),
+        };
+        let page = request(transport, id, "tools/list", params, deadline)?;
+        tool_count += page
+            .get("tools")
+            .and_then(Value::as_array)
+            .ok_or("The response to `tools/list` has no tools")?
+            .len();
+        cursor = page
+            .get("nextCursor")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        if cursor.is_none() {
+            break;
+        }
+    }
+    Ok(ServerReport {
+        tool_count: Some(tool_count),
+        ..report
+    })
+}
+
+/// Connect to a server and go through the MCP handshake, within `timeout`
+pub fn check_server(
+    endpoint: &ServerEndpoint,
+    timeout: Duration,
+) -> Result<ServerReport, HandshakeFailure> {
+    let deadline = Instant::now() + timeout;
+    let failure = |reason| HandshakeFailure {
+        reason,
+        stderr: None,
+    };
+    let mut transport: Box<dyn Transport> = match endpoint {
+        ServerEndpoint::Command(command) => {
+            Box::new(StdioTransport::spawn(command, timeout).map_err(failure)?)
+        }
+        ServerEndpoint::Http { url, headers, sse } => {
+            let headers = headers.clone().into_iter().collect();
+            if *sse {
+                Box::new(SseTransport::connect(url, headers, timeout).map_err(failure)?)
+            } else {
+                Box::new(HttpTransport {
+                    url: url.clone(),
+                    headers,
+                    pending: VecDeque::new(),
+                    timeout,
+                })
+            }
+        }
+    };
+    handshake(transport.as_mut(), deadline).map_err(|reason| HandshakeFailure {
+        reason,
+        stderr: transport.stderr(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+    use std::os::unix::fs::PermissionsExt;
+    use std::thread::JoinHandle;
+
+    fn script_server(name: &str, script: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rigup-{}-{}", name, std::process::id()));
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_stdio_handshake() {
+        // Answers by request id, with a log notification and a second page of tools in between
+        let server = script_server(
+            "mcp-ok",
+            r#"#!/bin/sh
+while read -r line; do
+  case "$line" in
+    *'"id":1'*) echo '{"jsonrpc":"2.0","method":"notifications/message","params":{}}'
+                echo '{"jsonrpc":"2.0","id":1,"result":{"protocolVersion":"2025-03-26","capabilities":{"tools":{}},"serverInfo":{"name":"fake","version":"1.2"}}}' ;;
+    *'"id":2'*) echo '{"jsonrpc":"2.0","id":2,"result":{"tools":[{"name":"a"},{"name":"b"}],"nextCursor":"p2"}}' ;;
+    *'"id":3'*) echo '{"jsonrpc":"2.0","id":3,"result":{"tools":[{"name":"c"}]}}' ;;
+  esac
+done
+"#,
+        );
+        let report = check_server(
+            &ServerEndpoint::Command(server.clone()),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert_eq!(report.name, "fake");
+        assert_eq!(report.version, "1.2");
+        assert_eq!(report.protocol_version, "2025-03-26");
+        assert_eq!(report.tool_count, Some(3));
+        std::fs::remove_file(server).unwrap();
+    }
+
+    #[test]
+    fn test_stdio_failures() {
+        let crashing = script_server(
+            "mcp-crash",
+            "#!/bin/sh\necho 'missing API key' >&2\nexit 3\n",
+        );
+        let failure = check_server(
+            &ServerEndpoint::Command(crashing.clone()),
+            Duration::from_secs(5),
+        )
+        .unwrap_err();
+        assert!(failure
+            .reason
+            .starts_with("The server exited before answering"));
+        assert_eq!(failure.stderr.as_deref(), Some("missing API key"));
+
+        let silent = script_server("mcp-silent", "#!/bin/sh\nsleep 5\n");
+        let failure = check_server(
+            &ServerEndpoint::Command(silent.clone()),
+            Duration::from_millis(200),
+        )
+        .unwrap_err();
+        assert_eq!(failure.reason, "The server did not answer within 0.2s");
+        std::fs::remove_file(crashing).unwrap();
+        std::fs::remove_file(silent).unwrap();
+    }
+
+    #[test]
+    fn test_http_parsing() {
+        let response = HttpResponse::parse(
+            "HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nMcp-Session-Id: abc\r\n\r\nevent: message\ndata: {\"id\":1}\n\n",
+        )
+        .unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("mcp-session-id"), Some("abc"));
+        assert_eq!(
+            parse_sse_events(&response.body),
+            [("message".to_string(), "{\"id\":1}".to_string())]
+        );
+        assert_eq!(
+            curl_config("http://x", &[("A".into(), "b \"c\"".into())], None),
+            "url = \"http://x\"\nheader = \"A: b \\\"c\\\"\"\n"
+        );
+    }
+
+    /// A request that a stand-in server received
+    struct Received {
+        request_line: String,
+        /// By lowercase name
+        headers: BTreeMap<String, String>,
+        /// Null for requests without a JSON body
+        body: Value,
+    }
+
+    fn read_request(stream: &TcpStream) -> Received {
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut headers = BTreeMap::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let Some((name, value)) = line.trim_end().split_once(':') else {
+                break;
+            };
+            headers.insert(name.to_lowercase(), value.trim().to_string());
+        }
+        let length = headers
+            .get("content-length")
+            .map_or(0, |length| length.parse().unwrap());
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        Received {
+            request_line: request_line.trim_end().to_string(),
+            headers,
+            body: serde_json::from_slice(&body).unwrap_or(Value::Null),
+        }
+    }
+
+    /// What a stand-in server answers to a message, if it is a request
+    fn answer(message: &Value) -> Option<Value> {
+        let result = match message.get("method")?.as_str()? {
+            "initialize" => json!({
+                "protocolVersion": "2025-06-18",
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "stand-in", "version": "0.1" },
+            }),
+            "tools/list" => json!({ "tools": [{ "name": "a" }, { "name": "b" }] }),
+            _ => return None,
+        };
+        Some(json!({ "jsonrpc": "2.0", "id": message.get("id")?, "result": result }))
+    }
+
+    const ACCEPTED: &str =
+        "HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+    /// Serve the handshake over streamable HTTP on a local port, answering as JSON or as event
+    /// streams (with a notification first), and give the session id in answer to `initialize`
+    fn http_stand_in(event_stream: bool) -> (String, JoinHandle<Vec<Received>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut received = Vec::new();
+            for stream in listener.incoming().take(3) {
+                let mut stream = stream.unwrap();
+                let request = read_request(&stream);
+                let response = match answer(&request.body) {
+                    None => ACCEPTED.to_string(),
+                    Some(answer) => {
+                        let (content_type, body) = if event_stream {
+                            let notification =
+                                json!({ "jsonrpc": "2.0", "method": "notifications/message" });
+                            let body = format!(
+                                "event: message\ndata: {}\n\nevent: message\ndata: {}\n\n",
+                                notification, answer
+                            );
+                            ("text/event-stream", body)
+                        } else {
+                            ("application/json", answer.to_string())
+                        };
+                        let session_id = if request.body["method"] == "initialize" {
+                            "Mcp-Session-Id: session-1\r\n"
+                        } else {
+                            ""
+                        };
+                        format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                            content_type,
+                            session_id,
+                            body.len(),
+                            body
+                        )
+                    }
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+                received.push(request);
+            }
+            received
+        });
+        (url, server)
+    }
+
+    /// Serve the handshake over the older HTTP+SSE transport on a local port: the event stream
+    /// tells where to POST, and carries the answers
+    fn sse_stand_in() -> (String, JoinHandle<Vec<Received>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/sse", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut events, _) = listener.accept().unwrap();
+            let mut received = vec![read_request(&events)];
+            events
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\nevent: endpoint\ndata: /messages?session=1\n\n")
+                .unwrap();
+            for stream in listener.incoming().take(3) {
+                let mut stream = stream.unwrap();
+                let request = read_request(&stream);
+                stream.write_all(ACCEPTED.as_bytes()).unwrap();
+                if let Some(answer) = answer(&request.body) {
+                    write!(events, "event: message\ndata: {}\n\n", answer).unwrap();
+                }
+                received.push(request);
+            }
+            received
+        });
+        (url, server)
+    }
+
+    fn methods(received: &[Received]) -> Vec<&str> {
+        received
+            .iter()
+            .filter_map(|request| request.body["method"].as_str())
+            .collect()
+    }
+
+    /// Headers that need quoting in curl's config
+    fn tricky_headers() -> BTreeMap<String, String> {
+        BTreeMap::from([("Authorization".to_string(), r#"Bearer "a\b" c"#.to_string())])
+    }
+
+    #[test]
+    fn test_http_handshake() {
+        let headers = tricky_headers();
+        for event_stream in [false, true] {
+            let (url, server) = http_stand_in(event_stream);
+            let endpoint = ServerEndpoint::Http {
+                url,
+                headers: headers.clone(),
+                sse: false,
+            };
+            let report = check_server(&endpoint, Duration::from_secs(5)).unwrap();
+            assert_eq!(report.name, "stand-in");
+            assert_eq!(report.protocol_version, "2025-06-18");
+            assert_eq!(report.tool_count, Some(2));
+
+            let received = server.join().unwrap();
+            assert_eq!(
+                methods(&received),
+                ["initialize", "notifications/initialized", "tools/list"]
+            );
+            let session_ids: Vec<Option<&str>> = received
+                .iter()
+                .map(|request| request.headers.get("mcp-session-id").map(String::as_str))
+                .collect();
+            assert_eq!(session_ids, [None, Some("session-1"), Some("session-1")]);
+            for request in &received {
+                assert_eq!(request.headers["authorization"], headers["Authorization"]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sse_handshake() {
+        let headers = tricky_headers();
+        let (url, server) = sse_stand_in();
+        let endpoint = ServerEndpoint::Http {
+            url,
+            headers: headers.clone(),
+            sse: true,
+        };
+        let report = check_server(&endpoint, Duration::from_secs(5)).unwrap();
+        assert_eq!(report.name, "stand-in");
+        assert_eq!(report.tool_count, Some(2));
+
+        let received = server.join().unwrap();
+        assert_eq!(received[0].request_line, "GET /sse HTTP/1.1");
+        assert_eq!(received[0].headers["accept"], "text/event-stream");
+        assert_eq!(
+            methods(&received),
+            ["initialize", "notifications/initialized", "tools/list"]
+        );
+        for request in &received[1..] {
+            assert_eq!(request.request_line, "POST /messages?session=1 HTTP/1.1");
+        }
+        for request in &received {
+            assert_eq!(request.headers["authorization"], headers["Authorization"]);
+        }
+    }
+}
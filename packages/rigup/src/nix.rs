@@ -1,10 +1,16 @@
 use crate::error::RigupError;
+use crate::nix_error::{diagnose_nix_error, nix_command_failed};
+use crate::nix_expr::{AttrPath, NixExpr};
+use crate::overrides::ConfigOverrides;
+use crate::suggest::did_you_mean;
+use itertools::Itertools;
 use miette::{IntoDiagnostic, Result};
 use owo_colors::OwoColorize;
+use serde::Deserialize;
 use serde_json::Value;
-use std::io::Read;
+use std::io::{BufRead, BufReader, IsTerminal};
 use std::path::PathBuf;
-use std::process::{Command, Stdio};
+use std::process::{ChildStderr, Command, Stdio};
 
 /// Detect the current system in Nix format (e.g., "x86_64-linux", "aarch64-darwin")
 pub fn get_system() -> String {
@@ -50,33 +56,36 @@ pub fn get_flake_root() -> Result<PathBuf> {
 }
 
 /// Parse a flake reference like "<flake>#<rig>"
-/// Returns (flake_path, rig_name)
+/// Returns (flake_path, rig_attrpath)
 ///
 /// To avoid ambiguity (following nix flake conventions):
 /// - If no `#` is present, treat as flake reference with default rig
 /// - Current repo MUST use `.#` prefix explicitly
 ///
+/// The rig part is an attribute path (under `rigs.<system>`), so it can use the quick extension
+/// attrs, like `myrig._with.<riglet>`. A rig name containing dots must be quoted: `.#"my.rig"`
+///
 /// Examples:
 /// - None -> ".#default"
 /// - ".#myrig" -> current repo, myrig
 /// - "github:foo/bar" -> "github:foo/bar#default"
 /// - "github:foo/bar#myrig" -> "github:foo/bar#myrig"
 /// - "example-rig" -> "example-rig#default" (flake reference, NOT rig name!)
-pub fn parse_flake_ref(flake_ref: Option<&str>) -> Result<(String, String)> {
+pub fn parse_flake_ref(flake_ref: Option<&str>) -> Result<(String, AttrPath)> {
     let ref_str = flake_ref.unwrap_or(".#default");
 
     match ref_str.split_once('#') {
         Some((flake, "")) => {
             // Explicit flake#rig format
-            Ok((flake.to_string(), "default".to_string()))
+            Ok((flake.to_string(), AttrPath::new(["default"])))
         }
         Some((flake, rig)) => {
             // Explicit flake#rig format
-            Ok((flake.to_string(), rig.to_string()))
+            Ok((flake.to_string(), AttrPath::parse(rig)?))
         }
         None => {
             // No # means it's a flake reference, default to #default
-            Ok((ref_str.to_string(), "default".to_string()))
+            Ok((ref_str.to_string(), AttrPath::new(["default"])))
         }
     }
 }
@@ -116,28 +125,243 @@ pub fn resolve_flake_path(flake_path: &str, no_stage: bool) -> Result<String> {
     }
 }
 
+/// Build a flake reference to the current repo as it was at a given git revision
+///
+/// Nix requires a full commit hash for `rev`, so branches, tags and such are resolved via git first
+pub fn resolve_flake_path_at_rev(rev: &str) -> Result<String> {
+    let flake_root = get_flake_root()?;
+    let output = Command::new("git")
+        .args(["rev-parse", "--verify", &format!("{}^{{commit}}", rev)])
+        .current_dir(&flake_root)
+        .output()
+        .into_diagnostic()?;
+
+    if !output.status.success() {
+        return Err(miette::miette!(
+            "Cannot resolve git revision '{}': {}",
+            rev,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let commit = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(format!("git+file:{}?rev={}", flake_root.display(), commit))
+}
+
+/// Names that a rig reference is checked against (see `check_rig_ref`)
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RigRefNames {
+    rigs: Vec<String>,
+    /// The riglets of the flake and of its inputs, that `._with.<riglet>` can add
+    #[serde(default)]
+    riglets: Vec<String>,
+    /// The rigs of the flake and of its inputs, that `._ext.<rig>` can add
+    #[serde(default)]
+    input_rigs: Vec<String>,
+    has_entrypoint: Option<bool>,
+}
+
+/// Find what is wrong with a rig reference: a rig that does not exist, or a riglet or rig added
+/// via `._with.<riglet>` or `._ext.<rig>` that does not exist. With `require_entrypoint`, also
+/// check that the rig has an entrypoint (which requires evaluating the rig). The evaluation is
+/// silent, and None is returned if it fails
+fn rig_ref_problem(
+    flake_expr: &str,
+    rig: &AttrPath,
+    require_entrypoint: bool,
+) -> Option<RigupError> {
+    let names = rig.names();
+    let uses = |attr: &str| names.iter().any(|name| name == attr);
+    let rigs_of = |input: &str| {
+        format!(
+            "{} or {{ }}",
+            NixExpr::raw(input).select(&AttrPath::new(["rigs", &get_system()]))
+        )
+    };
+
+    let mut fields = vec![format!("rigs = builtins.attrNames ({});", rigs_of("flake"))];
+    if uses("_with") {
+        fields.push(
+            "riglets = builtins.concatMap (input: builtins.attrNames (input.riglets or { })) inputs;"
+                .to_string(),
+        );
+    }
+    if uses("_ext") {
+        fields.push(format!(
+            "inputRigs = builtins.concatMap (input: builtins.attrNames ({})) inputs;",
+            rigs_of("input")
+        ));
+    }
+    if require_entrypoint {
+        fields.push(format!(
+            "hasEntrypoint = ({} or {{ }}) ? entrypoint;",
+            NixExpr::raw("flake")
+                .select(&AttrPath::new(["rigs", &get_system()]).join(names.iter().cloned()))
+        ));
+    }
+    let eval_expr = format!(
+        r#"
+            let
+                flake = {flake};
+                inputs = [ flake ] ++ builtins.attrValues flake.inputs;
+            in {{ {fields} }}
+        "#,
+        flake = NixExpr::get_flake(flake_expr),
+        fields = fields.join(" "),
+    );
+    let known: RigRefNames =
+        serde_json::from_value(run_nix_eval_json_captured(flake_expr, &eval_expr).ok()?).ok()?;
+
+    let unknown =
+        |kind, name: &str, scope: String, candidates: &[String]| RigupError::UnknownName {
+            kind,
+            name: name.to_string(),
+            scope,
+            did_you_mean: did_you_mean(name, candidates),
+        };
+    if !known.rigs.contains(&names[0]) {
+        let scope = format!("flake `{}`", flake_expr);
+        return Some(unknown("rig", &names[0], scope, &known.rigs));
+    }
+    for (attr, name) in names.iter().tuple_windows() {
+        let scope = format!("flake `{}` or its inputs", flake_expr);
+        if attr == "_with" && !known.riglets.contains(name) {
+            return Some(unknown("riglet", name, scope, &known.riglets));
+        }
+        if attr == "_ext" && !known.input_rigs.contains(name) {
+            return Some(unknown("rig", name, scope, &known.input_rigs));
+        }
+    }
+    (known.has_entrypoint == Some(false)).then(|| RigupError::NoEntrypoint {
+        rig: rig.to_string(),
+    })
+}
+
+/// When Nix failed on a rig, check the rig reference (see `rig_ref_problem`) so that a misspelled
+/// name is reported with suggestions instead of Nix's own error. Checking only on failure saves
+/// an evaluation in the common case
+pub fn check_rig_ref<T>(
+    result: Result<T>,
+    flake_expr: &str,
+    rig: &AttrPath,
+    require_entrypoint: bool,
+) -> Result<T> {
+    result.map_err(
+        |error| match rig_ref_problem(flake_expr, rig, require_entrypoint) {
+            Some(problem) => problem.into(),
+            None => error,
+        },
+    )
+}
+
 /// Build a complete flake reference for a rig component
-/// Takes parsed flake path and rig name, returns full reference
+/// Takes parsed flake path and rig attrpath, and returns full reference. Nix failing on it should
+/// go through `check_rig_ref`
 pub fn build_flake_ref(
     flake_path: &str,
-    rig: &str,
+    rig: &AttrPath,
     system: &str,
     component: Option<&str>,
     no_stage: bool,
 ) -> Result<String> {
     let resolved_path = resolve_flake_path(flake_path, no_stage)?;
+    let attr_path = AttrPath::new(["rigs", system])
+        .join(rig.names().iter().cloned())
+        .join(component);
     Ok(format!(
-        "{}#rigs.{}.{}{}",
+        "{}#{}",
         resolved_path,
-        system,
-        rig,
-        match component {
-            Some(c) => format!(".{}", c),
-            None => String::new(),
-        }
+        attr_path.to_flake_fragment()?
     ))
 }
 
+/// Nix expression of a rig of a flake, for the current system
+pub fn rig_expr(flake_expr: &str, rig: &AttrPath) -> NixExpr {
+    NixExpr::get_flake(flake_expr)
+        .select(&AttrPath::new(["rigs", &get_system()]).join(rig.names().iter().cloned()))
+}
+
+/// How to designate a rig component to `nix build`, `nix path-info` or `nix develop`
+pub struct RigInstallable {
+    /// Shown in progress messages
+    pub label: String,
+    /// The (resolved) flake the component comes from
+    pub flake: String,
+    pub args: Vec<String>,
+    /// The rig and its component, when given by name, to check if Nix fails on them
+    pub rig_component: Option<(AttrPath, String)>,
+}
+
+impl RigInstallable {
+    /// A rig component of a flake given by a Nix expression instead of a flake reference
+    pub fn from_expr(flake: &str, label: String, expr: &NixExpr) -> Self {
+        RigInstallable {
+            label,
+            flake: flake.to_string(),
+            args: vec![
+                "--impure".to_string(),
+                "--expr".to_string(),
+                expr.to_string(),
+            ],
+            rig_component: None,
+        }
+    }
+
+    /// Check the rig reference if Nix failed on this installable (see `check_rig_ref`)
+    pub fn check_failure<T>(&self, result: Result<T>) -> Result<T> {
+        match &self.rig_component {
+            Some((rig, component)) => {
+                check_rig_ref(result, &self.flake, rig, component == "entrypoint")
+            }
+            None => result,
+        }
+    }
+}
+
+/// Build the installable of a rig component: its flake reference (see `build_flake_ref`), or if
+/// there are config overrides, an expression that extends the rig with them
+pub fn rig_installable(
+    flake_path: &str,
+    rig: &AttrPath,
+    system: &str,
+    component: &str,
+    no_stage: bool,
+    overrides: &ConfigOverrides,
+) -> Result<RigInstallable> {
+    let flake_ref = build_flake_ref(flake_path, rig, system, Some(component), no_stage)?;
+    let (resolved_path, _) = flake_ref
+        .split_once('#')
+        .expect("flake refs built by build_flake_ref have a fragment");
+    if overrides.is_empty() {
+        return Ok(RigInstallable {
+            label: flake_ref.clone(),
+            flake: resolved_path.to_string(),
+            args: vec![flake_ref],
+            rig_component: Some((rig.clone(), component.to_string())),
+        });
+    }
+    let rig_with_overrides = check_rig_ref(
+        overrides.apply(
+            resolved_path,
+            &rig_expr(resolved_path, rig),
+            &rig.to_string(),
+        ),
+        resolved_path,
+        rig,
+        false,
+    )?;
+    let expr = rig_with_overrides.select(&AttrPath::new([component]));
+    Ok(RigInstallable {
+        rig_component: Some((rig.clone(), component.to_string())),
+        ..RigInstallable::from_expr(
+            resolved_path,
+            format!("{} (with config overrides)", flake_ref),
+            &expr,
+        )
+    })
+}
+
 /// Run a command interactively, inheriting stdin/stdout/stderr
 pub fn run_command_inherit(cmd: &str, args: Vec<&str>) -> Result<()> {
     let status = Command::new(cmd)
@@ -160,37 +384,88 @@ pub fn run_command_inherit(cmd: &str, args: Vec<&str>) -> Result<()> {
     Ok(())
 }
 
-/// Run a nix eval command that returns JSON, capturing stdout but showing stderr
-/// This is useful for commands that output JSON while showing build progress
-pub fn run_nix_eval_json(eval_expr: &str) -> Result<Value> {
+/// Forward the error output of a Nix command to ours as it comes (so that progress stays
+/// visible), and return the error that Nix reports, if any, so that it can be turned into a
+/// diagnostic. With `tee`, that error is printed as well, else it is only returned
+fn forward_stderr(stderr: ChildStderr, tee: bool) -> String {
+    let mut error = String::new();
+    for line in BufReader::new(stderr).lines().map_while(|line| line.ok()) {
+        let in_error = !error.is_empty() || line.starts_with("error:");
+        if tee || !in_error {
+            eprintln!("{}", line);
+        }
+        if in_error {
+            error.push_str(&line);
+            error.push('\n');
+        }
+    }
+    error
+}
+
+/// Run a nix command on a flake, showing its messages on stderr, and return its stdout (if
+/// `stdout` is piped). On failure, errors in the TOML files of the flake (if it is a local one)
+/// are reported as diagnostics
+pub fn run_nix(flake: &str, args: &[&str], stdout: Stdio) -> Result<Vec<u8>> {
+    // On a terminal, Nix's error is shown as it prints it, and is only followed by a diagnostic
+    // if it can be pointed at in the TOML files
+    let tee = std::io::stderr().is_terminal();
     let mut child = Command::new("nix")
-        .args(&["eval", "--impure", "--expr", eval_expr, "--json"])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::inherit())
+        .args(args)
+        .stdout(stdout)
+        .stderr(Stdio::piped())
         .spawn()
         .into_diagnostic()?;
 
-    // Read stdout (contains JSON result)
-    let mut stdout = Vec::new();
-    child
-        .stdout
-        .take()
-        .expect("Failed to capture stdout")
-        .read_to_end(&mut stdout)
-        .into_diagnostic()?;
+    let stderr = child.stderr.take().expect("Failed to capture stderr");
+    let forwarder = std::thread::spawn(move || forward_stderr(stderr, tee));
 
-    // Wait for process to complete
-    let status = child.wait().into_diagnostic()?;
+    // Wait for process to complete, reading stdout meanwhile
+    let output = child.wait_with_output().into_diagnostic()?;
+    let error = forwarder.join().unwrap_or_default();
 
-    if !status.success() {
-        let code = status.code().unwrap_or(1);
-        return Err(RigupError::NixCommandFailed {
-            code,
-            stderr: "See error output above".to_string(),
+    if !output.status.success() {
+        let code = output.status.code().unwrap_or(1);
+        if tee {
+            return Err(diagnose_nix_error(flake, &error)
+                .unwrap_or(RigupError::NixCommandFailed {
+                    code,
+                    stderr: "nix command failed".to_string(),
+                })
+                .into());
         }
-        .into());
+        return Err(nix_command_failed(flake, code, error).into());
     }
 
+    Ok(output.stdout)
+}
+
+/// Run a nix eval command (of an expression using `flake`) that returns JSON, capturing stdout
+/// but showing stderr. This is useful for commands that output JSON while showing build progress
+pub fn run_nix_eval_json(flake: &str, eval_expr: &str) -> Result<Value> {
+    let stdout = run_nix(
+        flake,
+        &["eval", "--impure", "--expr", eval_expr, "--json"],
+        Stdio::piped(),
+    )?;
+
     // Parse JSON result
     serde_json::from_slice(&stdout).into_diagnostic()
 }
+
+/// Run a nix eval command that returns JSON, capturing both stdout and stderr
+/// This is useful when several evaluations run concurrently, as their progress output would
+/// otherwise be interleaved
+pub fn run_nix_eval_json_captured(flake: &str, eval_expr: &str) -> Result<Value> {
+    let output = Command::new("nix")
+        .args(&["eval", "--impure", "--expr", eval_expr, "--json"])
+        .output()
+        .into_diagnostic()?;
+
+    if !output.status.success() {
+        let code = output.status.code().unwrap_or(1);
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        return Err(nix_command_failed(flake, code, stderr).into());
+    }
+
+    serde_json::from_slice(&output.stdout).into_diagnostic()
+}
@@ -0,0 +1,351 @@
+use crate::error::RigupError;
+use crate::nix::get_system;
+use crate::nix_expr::{AttrPath, NixExpr};
+use crate::rigup_toml::{project_riglet_names, TomlFile};
+use crate::suggest::did_you_mean;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// What a Nix error says about a rig defined in a rigup TOML file
+#[derive(Debug, PartialEq)]
+enum RigDefIssue {
+    /// `uses `<input>.riglets.<riglet>` which does not exist`
+    MissingRiglet {
+        input: String,
+        riglet: String,
+    },
+    /// `extends `<input>.rigs.<system>.<rig>` which does not exist`
+    MissingBaseRig {
+        input: String,
+        rig: String,
+    },
+    /// A module system error about an option set in the rig's config
+    ConfigOption {
+        path: Vec<String>,
+    },
+    Other,
+}
+
+#[derive(Debug, PartialEq)]
+struct RigDefError {
+    /// The TOML file, as printed by Nix: `<projectUri>/<path relative to the flake root>`
+    file: String,
+    rig: String,
+    message: String,
+    issue: RigDefIssue,
+}
+
+/// Get the text between `start` and `end` in `s`
+fn between<'a>(s: &'a str, start: &str, end: &[char]) -> Option<&'a str> {
+    let (_, after) = s.split_once(start)?;
+    Some(&after[..after.find(end)?])
+}
+
+/// The innermost error message of some Nix error output (Nix prints the evaluation trace first),
+/// up to its first line break
+fn innermost_message(stderr: &str) -> Option<&str> {
+    let (_, message) = stderr.rsplit_once("error: ")?;
+    message.lines().next().map(str::trim)
+}
+
+/// Parse the errors located in a rig definition, i.e. the ones mentioning
+/// `<projectUri>/<file>::[rigs.<rig>]` (see `rigDefError` in resolveProject.nix), and the module
+/// system errors whose definitions come from `<projectUri>/<file>::[rigs.<rig>.config]`
+fn parse_rig_def_error(stderr: &str) -> Option<RigDefError> {
+    const MARKER: &str = "::[rigs.";
+    let index = stderr.find(MARKER)?;
+    let file_start = stderr[..index]
+        .rfind(|c: char| c.is_whitespace() || c == '`')
+        .map_or(0, |i| i + 1);
+    let file = stderr[file_start..index].to_string();
+    let (section, after) = stderr[index + MARKER.len()..].split_once(']')?;
+
+    if let Some(rig) = section.strip_suffix(".config") {
+        let message = innermost_message(stderr)?;
+        let path = between(message, "option `", &['\'', '`'])
+            .and_then(|path| AttrPath::parse(path).ok())
+            .map(|path| path.names().to_vec())
+            .unwrap_or_default();
+        return Some(RigDefError {
+            file,
+            rig: rig.to_string(),
+            message: message
+                .trim_end_matches("Definition values:")
+                .trim_end()
+                .to_string(),
+            issue: RigDefIssue::ConfigOption { path },
+        });
+    }
+
+    let message = after.lines().next().unwrap_or_default().trim();
+    let quoted = between(message, "`", &['`']).unwrap_or_default();
+    let issue = if message.starts_with("uses ") {
+        quoted
+            .split_once(".riglets.")
+            .map(|(input, riglet)| RigDefIssue::MissingRiglet {
+                input: input.to_string(),
+                riglet: riglet.to_string(),
+            })
+    } else if message.starts_with("extends ") {
+        quoted
+            .split_once(".rigs.")
+            .and_then(|(input, rest)| Some((input, rest.split_once('.')?.1)))
+            .map(|(input, rig)| RigDefIssue::MissingBaseRig {
+                input: input.to_string(),
+                rig: rig.to_string(),
+            })
+    } else {
+        None
+    };
+    Some(RigDefError {
+        file,
+        rig: section.to_string(),
+        message: format!("Rig `{}` {}", section, message),
+        issue: issue.unwrap_or(RigDefIssue::Other),
+    })
+}
+
+/// Parse the error of a rig being defined in several TOML files. Returns the rig and the files
+fn parse_duplicate_rig(stderr: &str) -> Option<(String, Vec<String>)> {
+    let (_, after) = stderr.split_once(": rig `")?;
+    let (rig, after) = after.split_once("` is defined in several files:")?;
+    let files = after
+        .lines()
+        .map(str::trim)
+        .skip_while(|line| line.is_empty())
+        .map_while(|line| line.strip_prefix("- "))
+        // Each line is `<relative path> (<store path>)`
+        .map(|line| line.split(" (").next().unwrap_or(line).to_string())
+        .collect();
+    Some((rig.to_string(), files))
+}
+
+/// Find which file of the project a file printed by Nix refers to. As the project URI may contain
+/// slashes, the longest suffix of the printed path that exists under the flake root is used
+fn local_file(flake_root: &Path, printed: &str) -> Option<TomlFile> {
+    let parts: Vec<&str> = printed.split('/').collect();
+    (0..parts.len()).find_map(|start| TomlFile::load(flake_root, &parts[start..].join("/")))
+}
+
+/// The names of the riglets (or with `rigs`, of the rigs) that an input of the local flake
+/// provides, to suggest the closest ones to a missing name. Those of `self` are found without
+/// evaluating the flake (which is the one that just failed to evaluate)
+fn input_names(flake_root: &Path, input: &str, rigs: bool) -> Vec<String> {
+    match (input, rigs) {
+        ("self", false) => project_riglet_names(flake_root),
+        ("self", true) => ["rigup.toml", "rigup.local.toml"]
+            .iter()
+            .filter_map(|file| TomlFile::load(flake_root, file))
+            .flat_map(|file| file.rig_names())
+            .collect(),
+        _ => {
+            let attr = if rigs {
+                AttrPath::new(["inputs", input, "rigs", &get_system()])
+            } else {
+                AttrPath::new(["inputs", input, "riglets"])
+            };
+            let eval_expr = format!(
+                "builtins.attrNames ({} or {{ }})",
+                NixExpr::get_flake(&format!("git+file:{}", flake_root.display())).select(&attr)
+            );
+            // Suggestions are best-effort, so this evaluation is silent
+            Command::new("nix")
+                .args(["eval", "--impure", "--json", "--expr", &eval_expr])
+                .stderr(Stdio::null())
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .and_then(|output| serde_json::from_slice(&output.stdout).ok())
+                .unwrap_or_default()
+        }
+    }
+}
+
+/// Turn a Nix error into a diagnostic pointing into the project's TOML files, if it is about a
+/// rig definition
+fn diagnose(flake_root: &Path, stderr: &str) -> Option<RigupError> {
+    if let Some((rig, files)) = parse_duplicate_rig(stderr) {
+        let mut locations = files.iter().filter_map(|printed| {
+            let file = local_file(flake_root, printed)?;
+            Some((file.rig_span(&rig)?, file))
+        });
+        let (span, file) = locations.next()?;
+        let related = locations
+            .map(|(span, file)| RigupError::InvalidRigDefinition {
+                message: format!("Rig `{}` is also defined in {}", rig, file.name()),
+                src: file.named_source(),
+                span: span.into(),
+                label: "also defined here".to_string(),
+                help: None,
+                related: Vec::new(),
+            })
+            .collect();
+        return Some(RigupError::InvalidRigDefinition {
+            message: format!("Rig `{}` is defined in several files", rig),
+            src: file.named_source(),
+            span: span.into(),
+            label: "first defined here".to_string(),
+            help: Some(
+                "To override some rig's config in another TOML file, define a new rig that extends it"
+                    .to_string(),
+            ),
+            related,
+        });
+    }
+
+    let error = parse_rig_def_error(stderr)?;
+    let file = local_file(flake_root, &error.file)?;
+    let (span, label, help) = match &error.issue {
+        RigDefIssue::MissingRiglet { input, riglet } => (
+            file.rig_entry_span(&error.rig, "riglets", input, riglet),
+            format!("input `{}` has no riglet `{}`", input, riglet),
+            did_you_mean(riglet, &input_names(flake_root, input, false)).or_else(|| {
+                Some("`rigup show --with-inputs` lists the available riglets".to_string())
+            }),
+        ),
+        RigDefIssue::MissingBaseRig { input, rig } => (
+            file.rig_entry_span(&error.rig, "extends", input, rig),
+            format!("input `{}` has no rig `{}`", input, rig),
+            did_you_mean(rig, &input_names(flake_root, input, true)).or_else(|| {
+                Some("`rigup show --with-inputs` lists the available rigs".to_string())
+            }),
+        ),
+        RigDefIssue::ConfigOption { path } => (
+            file.config_span(&error.rig, path),
+            "defined here".to_string(),
+            None,
+        ),
+        RigDefIssue::Other => (None, "in this rig".to_string(), None),
+    };
+    let span = span.or_else(|| file.rig_span(&error.rig))?;
+    Some(RigupError::InvalidRigDefinition {
+        message: error.message,
+        src: file.named_source(),
+        span: span.into(),
+        label,
+        help,
+        related: Vec::new(),
+    })
+}
+
+/// The folder of a flake whose files are those of a local working tree (which spans can then
+/// point into), i.e. a `git+file:` flake not pinned to some revision
+fn local_flake_root(flake: &str) -> Option<PathBuf> {
+    let path = flake.strip_prefix("git+file:")?;
+    (!path.contains('?')).then(|| PathBuf::from(path.strip_prefix("//").unwrap_or(path)))
+}
+
+/// A diagnostic pointing into the rigup.toml (or rigup.local.toml) of `flake`, when the error
+/// output of a failed Nix command on it comes from one of its rig definitions
+pub fn diagnose_nix_error(flake: &str, stderr: &str) -> Option<RigupError> {
+    diagnose(&local_flake_root(flake)?, stderr)
+}
+
+/// The error to report when a Nix command on `flake` fails, given the error output it printed:
+/// a diagnostic from `diagnose_nix_error`, or else Nix's own error output
+pub fn nix_command_failed(flake: &str, code: i32, stderr: String) -> RigupError {
+    diagnose_nix_error(flake, &stderr).unwrap_or(RigupError::NixCommandFailed { code, stderr })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_flake_root() {
+        assert_eq!(
+            local_flake_root("git+file:/home/me/proj"),
+            Some(PathBuf::from("/home/me/proj"))
+        );
+        assert_eq!(
+            local_flake_root("git+file:///home/me/proj"),
+            Some(PathBuf::from("/home/me/proj"))
+        );
+        // Another revision of the repo does not have the files of the working tree
+        assert_eq!(
+            local_flake_root("git+file:/home/me/proj?rev=0123abcd"),
+            None
+        );
+        assert_eq!(local_flake_root("github:YPares/rigup.nix"), None);
+    }
+
+    #[test]
+    fn test_parse_missing_riglet() {
+        let stderr = r#"error:
+       … while evaluating the attribute 'example-rig'
+
+       error: YPares/rigup.nix/rigup.toml::[rigs.example-rig] uses `self.riglets.coreutil` which does not exist
+       (source: /nix/store/0123456789abcdef-source/rigup.toml)
+"#;
+        assert_eq!(
+            parse_rig_def_error(stderr),
+            Some(RigDefError {
+                file: "YPares/rigup.nix/rigup.toml".to_string(),
+                rig: "example-rig".to_string(),
+                message: "Rig `example-rig` uses `self.riglets.coreutil` which does not exist"
+                    .to_string(),
+                issue: RigDefIssue::MissingRiglet {
+                    input: "self".to_string(),
+                    riglet: "coreutil".to_string()
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_missing_base_rig() {
+        let stderr = "error: me/proj/rigup.local.toml::[rigs.mine] extends `self.rigs.x86_64-linux.basic` which does not exist\n";
+        let error = parse_rig_def_error(stderr).unwrap();
+        assert_eq!(error.file, "me/proj/rigup.local.toml");
+        assert_eq!(
+            error.issue,
+            RigDefIssue::MissingBaseRig {
+                input: "self".to_string(),
+                rig: "basic".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_config_option_error() {
+        let stderr = r#"error:
+       … while evaluating the option `agent.identity.nam':
+
+       error: The option `agent.identity.nam' does not exist. Definition values:
+       - In `YPares/rigup.nix/rigup.toml::[rigs.example-rig.config] (/nix/store/0123-source/rigup.toml)':
+           "Test Agent"
+"#;
+        let error = parse_rig_def_error(stderr).unwrap();
+        assert_eq!(error.rig, "example-rig");
+        assert_eq!(
+            error.message,
+            "The option `agent.identity.nam' does not exist."
+        );
+        assert_eq!(
+            error.issue,
+            RigDefIssue::ConfigOption {
+                path: vec![
+                    "agent".to_string(),
+                    "identity".to_string(),
+                    "nam".to_string()
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_duplicate_rig() {
+        let stderr = r#"error: In me/proj: rig `mine` is defined in several files:
+        - rigup.toml (/nix/store/0123-source/rigup.toml)
+        - rigup.local.toml (/nix/store/0123-source/rigup.local.toml)
+       To override some rig's config in another TOML file, define a new rig that extends it.
+"#;
+        assert_eq!(
+            parse_duplicate_rig(stderr),
+            Some((
+                "mine".to_string(),
+                vec!["rigup.toml".to_string(), "rigup.local.toml".to_string()]
+            ))
+        );
+    }
+}
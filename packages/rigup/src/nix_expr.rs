@@ -0,0 +1,267 @@
+use miette::Result;
+use std::fmt;
+
+/// A piece of Nix code
+///
+/// User-provided values (flake URLs, rig names, etc.) must never be spliced directly into Nix
+/// code: they go through the constructors below, which quote them properly. Only trusted code,
+/// written in this crate, should use `NixExpr::raw`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NixExpr(String);
+
+impl NixExpr {
+    /// Trusted Nix code, used as is
+    pub fn raw(code: impl Into<String>) -> Self {
+        NixExpr(code.into())
+    }
+
+    /// A Nix string literal
+    pub fn string(value: &str) -> Self {
+        NixExpr(quote_string(value))
+    }
+
+    pub fn bool(value: bool) -> Self {
+        NixExpr(value.to_string())
+    }
+
+    pub fn list(items: impl IntoIterator<Item = NixExpr>) -> Self {
+        let items: Vec<String> = items.into_iter().map(|item| item.0).collect();
+        NixExpr(format!("[ {} ]", items.join(" ")))
+    }
+
+    /// `builtins.getFlake "<url>"`
+    pub fn get_flake(url: &str) -> Self {
+        NixExpr(format!("(builtins.getFlake {})", quote_string(url)))
+    }
+
+    /// Select an attribute path from this expression: `<expr>.a."b.c"`
+    pub fn select(&self, path: &AttrPath) -> Self {
+        NixExpr(format!("{}.{}", self.0, path))
+    }
+}
+
+impl fmt::Display for NixExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Quote a string as a Nix string literal, escaping `"`, `\`, `${` and control chars
+fn quote_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    let mut chars = value.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '$' if chars.peek() == Some(&'{') => quoted.push_str("\\$"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+const NIX_KEYWORDS: [&str; 11] = [
+    "assert", "else", "if", "in", "inherit", "let", "or", "rec", "then", "with", "__curPos",
+];
+
+/// Whether an attribute name can be written without quotes
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '\'' | '-'))
+        && !NIX_KEYWORDS.contains(&name)
+}
+
+/// An attribute path, like `rigs.x86_64-linux."my.rig"`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttrPath(Vec<String>);
+
+impl AttrPath {
+    pub fn new<S: Into<String>>(names: impl IntoIterator<Item = S>) -> Self {
+        AttrPath(names.into_iter().map(Into::into).collect())
+    }
+
+    /// Parse an attribute path as given on the command line, e.g. `my-rig._with.some-riglet`.
+    /// Names containing dots must be double-quoted, as in Nix: `"my.rig"`
+    pub fn parse(path: &str) -> Result<Self> {
+        let mut names = Vec::new();
+        let mut current = String::new();
+        let mut in_quotes = false;
+        for c in path.chars() {
+            match c {
+                '"' => in_quotes = !in_quotes,
+                '.' if !in_quotes => names.push(std::mem::take(&mut current)),
+                _ => current.push(c),
+            }
+        }
+        if in_quotes {
+            return Err(miette::miette!(
+                "Unterminated quote in attribute path `{}`",
+                path
+            ));
+        }
+        names.push(current);
+        if names.iter().any(String::is_empty) {
+            return Err(miette::miette!("Empty attribute name in `{}`", path));
+        }
+        Ok(AttrPath(names))
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.0
+    }
+
+    /// This path followed by other attribute names
+    pub fn join<S: Into<String>>(&self, names: impl IntoIterator<Item = S>) -> Self {
+        AttrPath(
+            self.0
+                .iter()
+                .cloned()
+                .chain(names.into_iter().map(Into::into))
+                .collect(),
+        )
+    }
+
+    /// Render as the fragment of a flake reference (the part after `#`), in which Nix
+    /// percent-decodes the fragment, then splits it on dots outside of double quotes.
+    ///
+    /// Fails for names containing double quotes, as fragments have no way to escape them
+    pub fn to_flake_fragment(&self) -> Result<String> {
+        if let Some(name) = self.0.iter().find(|name| name.contains('"')) {
+            return Err(miette::miette!(
+                "Attribute name `{}` contains a double quote, which flake references cannot express",
+                name
+            ));
+        }
+        let path = self
+            .0
+            .iter()
+            .map(|name| {
+                if is_identifier(name) {
+                    name.clone()
+                } else {
+                    format!("\"{}\"", name)
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(".");
+        let mut fragment = String::with_capacity(path.len());
+        for byte in path.bytes() {
+            if byte.is_ascii_alphanumeric() || b"-._~\"".contains(&byte) {
+                fragment.push(byte as char);
+            } else {
+                fragment.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        Ok(fragment)
+    }
+
+    /// Render as a file name, e.g. for the folder a rig is built in. Characters that are not
+    /// safe in file names (like `/`, or dots in `..`) are replaced by `_`
+    pub fn to_file_name(&self) -> String {
+        self.0
+            .iter()
+            .map(|name| {
+                name.chars()
+                    .map(|c| {
+                        if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                            c
+                        } else {
+                            '_'
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+impl fmt::Display for AttrPath {
+    /// Render as Nix code
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<String> = self
+            .0
+            .iter()
+            .map(|name| {
+                if is_identifier(name) {
+                    name.clone()
+                } else {
+                    quote_string(name)
+                }
+            })
+            .collect();
+        f.write_str(&names.join("."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_string() {
+        assert_eq!(NixExpr::string("plain").to_string(), r#""plain""#);
+        assert_eq!(
+            NixExpr::string(r#"a "quoted" \ path"#).to_string(),
+            r#""a \"quoted\" \\ path""#
+        );
+        assert_eq!(
+            NixExpr::string("${builtins.abort 1}").to_string(),
+            r#""\${builtins.abort 1}""#
+        );
+        // A lone $ is not an interpolation
+        assert_eq!(NixExpr::string("$HOME").to_string(), r#""$HOME""#);
+    }
+
+    #[test]
+    fn test_attr_path_parse() {
+        assert_eq!(
+            AttrPath::parse("example-rig._with.claude-code").unwrap(),
+            AttrPath::new(["example-rig", "_with", "claude-code"])
+        );
+        assert_eq!(
+            AttrPath::parse(r#""my.rig".entrypoint"#).unwrap(),
+            AttrPath::new(["my.rig", "entrypoint"])
+        );
+        assert!(AttrPath::parse("a..b").is_err());
+        assert!(AttrPath::parse(r#""unterminated"#).is_err());
+    }
+
+    #[test]
+    fn test_attr_path_display() {
+        let path = AttrPath::new(["rigs", "x86_64-linux", "my.rig", "if", "${x}"]);
+        assert_eq!(
+            path.to_string(),
+            r#"rigs.x86_64-linux."my.rig"."if"."\${x}""#
+        );
+        assert_eq!(
+            path.to_flake_fragment().unwrap(),
+            r#"rigs.x86_64-linux."my.rig"."if"."%24%7Bx%7D""#
+        );
+        assert_eq!(
+            AttrPath::new(["it's", "a b"]).to_flake_fragment().unwrap(),
+            r#"it%27s."a%20b""#
+        );
+        assert!(AttrPath::new(["say \"hi\""]).to_flake_fragment().is_err());
+    }
+
+    #[test]
+    fn test_attr_path_file_name() {
+        assert_eq!(
+            AttrPath::new(["example-rig", "_with"]).to_file_name(),
+            "example-rig._with"
+        );
+        assert_eq!(AttrPath::new([".."]).to_file_name(), "__");
+        assert_eq!(
+            AttrPath::new(["../../etc", "my.rig"]).to_file_name(),
+            "______etc.my_rig"
+        );
+    }
+}
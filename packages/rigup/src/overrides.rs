@@ -0,0 +1,393 @@
+use crate::error::RigupError;
+use crate::nix::{rig_expr, run_nix_eval_json};
+use crate::nix_expr::{AttrPath, NixExpr};
+use crate::suggest::did_you_mean;
+use crate::types::{flatten_options, ConfigOption, ConfigValue};
+use clap::Args;
+use miette::{IntoDiagnostic, Result};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use toml_edit::{DocumentMut, Item};
+
+/// Config overrides, applied to a rig for a single command without editing rigup.toml
+#[derive(Args, Debug, Default)]
+pub struct ConfigOverrides {
+    /// Set a config option of the rig, e.g. `--set claude-code.strictMcpConfig=true`
+    ///
+    /// The value is parsed as TOML (`42`, `true`, `["a", "b"]`, `{ x = 1 }`...), or taken as a
+    /// plain string if it is not valid TOML. Takes precedence over the rig's own config
+    #[arg(long = "set", value_name = "OPTION=VALUE")]
+    pub set: Vec<String>,
+    /// Set config options of the rig from a TOML file
+    ///
+    /// The file is written like the `[rigs.<rig>.config]` table of rigup.toml. `--set` flags
+    /// take precedence over it
+    #[arg(long, value_name = "FILE")]
+    pub config_file: Vec<PathBuf>,
+}
+
+/// Parse a value given on the command line as TOML (`42`, `true`, `["a", "b"]`, `{ x = 1 }`...),
+/// or take it as a plain string if it is not valid TOML
+pub fn parse_cli_value(value: &str) -> toml_edit::Value {
+    let value = value.trim();
+    value
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| toml_edit::Value::from(value))
+}
+
+/// Convert a TOML value to JSON. Datetimes become strings
+pub fn toml_to_json(value: &toml_edit::Value) -> Value {
+    match value {
+        toml_edit::Value::String(s) => Value::from(s.value().as_str()),
+        toml_edit::Value::Integer(i) => Value::from(*i.value()),
+        toml_edit::Value::Float(f) => Value::from(*f.value()),
+        toml_edit::Value::Boolean(b) => Value::from(*b.value()),
+        toml_edit::Value::Datetime(d) => Value::from(d.value().to_string()),
+        toml_edit::Value::Array(array) => array.iter().map(toml_to_json).collect(),
+        toml_edit::Value::InlineTable(table) => table
+            .iter()
+            .map(|(key, value)| (key.to_string(), toml_to_json(value)))
+            .collect(),
+    }
+}
+
+fn toml_item_to_json(item: &Item) -> Value {
+    match item {
+        Item::None => Value::Null,
+        Item::Value(value) => toml_to_json(value),
+        Item::Table(table) => table
+            .iter()
+            .map(|(key, item)| (key.to_string(), toml_item_to_json(item)))
+            .collect(),
+        Item::ArrayOfTables(tables) => tables
+            .iter()
+            .map(|table| {
+                Value::Object(
+                    table
+                        .iter()
+                        .map(|(key, item)| (key.to_string(), toml_item_to_json(item)))
+                        .collect(),
+                )
+            })
+            .collect(),
+    }
+}
+
+/// Set `value` at `path` in a JSON object, merging it with the tables already there
+fn insert_at(object: &mut Map<String, Value>, path: &[String], value: Value) {
+    let (name, rest) = path.split_first().expect("option paths are never empty");
+    if rest.is_empty() {
+        match (object.get_mut(name), value) {
+            (Some(Value::Object(existing)), Value::Object(new)) => {
+                for (key, value) in new {
+                    insert_at(existing, &[key], value);
+                }
+            }
+            (_, value) => {
+                object.insert(name.clone(), value);
+            }
+        }
+    } else {
+        let child = object
+            .entry(name.clone())
+            .and_modify(|child| {
+                if !child.is_object() {
+                    *child = Value::Object(Map::new());
+                }
+            })
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Value::Object(child) = child {
+            insert_at(child, rest, value);
+        }
+    }
+}
+
+impl ConfigOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.config_file.is_empty()
+    }
+
+    /// All the overrides, as a JSON object of option values. Config files come first, in order,
+    /// then `--set` flags
+    pub fn to_json(&self) -> Result<Map<String, Value>> {
+        let mut config = Map::new();
+        for file in &self.config_file {
+            let contents = std::fs::read_to_string(file)
+                .into_diagnostic()
+                .map_err(|e| e.wrap_err(format!("Cannot read config file {}", file.display())))?;
+            let doc: DocumentMut = contents.parse().map_err(|e| {
+                miette::miette!("Invalid TOML in config file {}: {}", file.display(), e)
+            })?;
+            if let Value::Object(values) = toml_item_to_json(doc.as_item()) {
+                for (key, value) in values {
+                    insert_at(&mut config, &[key], value);
+                }
+            }
+        }
+        for setting in &self.set {
+            let (path, value) = setting.split_once('=').ok_or_else(|| {
+                miette::miette!(
+                    help = "Write it as `--set path.to.option=value`",
+                    "Invalid --set `{}`: no `=` found",
+                    setting
+                )
+            })?;
+            let path = AttrPath::parse(path.trim())?;
+            insert_at(
+                &mut config,
+                path.names(),
+                toml_to_json(&parse_cli_value(value)),
+            );
+        }
+        Ok(config)
+    }
+
+    /// A rig of a flake, extended with these overrides if there are some. Without overrides, the
+    /// evaluation fails with a readable error if the flake does not output the rig (with some,
+    /// checking them against the rig's options already does)
+    pub fn rig_expr(&self, flake_expr: &str, rig_attrpath: &AttrPath) -> Result<NixExpr> {
+        let rig = rig_expr(flake_expr, rig_attrpath);
+        if self.is_empty() {
+            Ok(NixExpr::raw(format!(
+                "({} or (throw (\"Flake \" + {} + \" does not output \" + {})))",
+                rig,
+                NixExpr::string(flake_expr),
+                NixExpr::string(&rig_attrpath.to_string()),
+            )))
+        } else {
+            self.apply(flake_expr, &rig, &rig_attrpath.to_string())
+        }
+    }
+
+    /// A rig (of `flake_expr`) extended with these overrides, after checking them against its options
+    pub fn apply(&self, flake_expr: &str, rig_expr: &NixExpr, rig_name: &str) -> Result<NixExpr> {
+        let config = self.to_json()?;
+        validate(
+            rig_name,
+            &fetch_config_options(flake_expr, rig_expr)?,
+            &config,
+        )?;
+        Ok(extend_rig(rig_expr, &config))
+    }
+}
+
+/// Evaluate the options that a rig (of `flake_expr`) exposes (its `configOptions`)
+pub fn fetch_config_options(
+    flake_expr: &str,
+    rig_expr: &NixExpr,
+) -> Result<BTreeMap<String, ConfigValue>> {
+    serde_json::from_value(run_nix_eval_json(
+        flake_expr,
+        &format!("{}.configOptions", rig_expr),
+    )?)
+    .map_err(|source| RigupError::MetadataParseError { source }.into())
+}
+
+/// Check the value of a single option path against the options a rig exposes
+pub fn check_setting(
+    rig: &str,
+    options: &BTreeMap<String, ConfigValue>,
+    path: &AttrPath,
+    value: &Value,
+) -> Result<()> {
+    let mut config = Map::new();
+    insert_at(&mut config, path.names(), value.clone());
+    validate(rig, options, &config)
+}
+
+/// Check a value against the type of an option, as described by the module system.
+/// Returns the expected type if the value does not match. Types that cannot be checked here
+/// (packages, submodules, functions...) are left for the module system to check
+fn check_type(option_type: &str, value: &Value) -> Result<(), String> {
+    // Compound types are parenthesized when nested, e.g. `null or (list of string)`
+    let option_type = option_type
+        .strip_prefix('(')
+        .and_then(|t| t.strip_suffix(')'))
+        .unwrap_or(option_type);
+    let matches = if let Some(inner) = option_type.strip_prefix("null or ") {
+        value.is_null() || check_type(inner, value).is_ok()
+    } else if let Some(inner) = option_type.strip_prefix("list of ") {
+        match value {
+            Value::Array(items) => items.iter().all(|item| check_type(inner, item).is_ok()),
+            _ => false,
+        }
+    } else if let Some(inner) = option_type
+        .strip_prefix("attribute set of ")
+        .or_else(|| option_type.strip_prefix("lazy attribute set of "))
+    {
+        match value {
+            Value::Object(items) => items.values().all(|item| check_type(inner, item).is_ok()),
+            _ => false,
+        }
+    } else if option_type == "boolean" {
+        value.is_boolean()
+    } else if option_type.contains("integer") {
+        value.is_i64() || value.is_u64()
+    } else if option_type == "floating point number" || option_type == "number" {
+        value.is_number()
+    } else if option_type.starts_with("string")
+        || option_type.starts_with("non-empty string")
+        || option_type.starts_with("single-line string")
+        || option_type.starts_with("strings concatenated")
+    {
+        value.is_string()
+    } else {
+        true
+    };
+    if matches {
+        Ok(())
+    } else {
+        Err(option_type.to_string())
+    }
+}
+
+/// Check a value against an option's type, and its possible values if it is an enum
+fn check_option(option: &ConfigOption, value: &Value) -> Result<(), String> {
+    match (option.option_type.as_str(), &option.enum_values) {
+        ("enum", Some(values)) if !values.contains(value) => Err(format!(
+            "one of {}",
+            values
+                .iter()
+                .map(Value::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        (option_type, _) => check_type(option_type, value),
+    }
+}
+
+/// Check config overrides against the options a rig exposes (its `configOptions`), before
+/// evaluating anything with them
+fn validate(
+    rig: &str,
+    options: &BTreeMap<String, ConfigValue>,
+    config: &Map<String, Value>,
+) -> Result<()> {
+    let options = flatten_options(options);
+
+    fn go(
+        rig: &str,
+        options: &BTreeMap<String, &ConfigOption>,
+        path: &str,
+        value: &Value,
+    ) -> Result<()> {
+        if let Some(option) = options.get(path) {
+            return check_option(option, value).map_err(|expected| {
+                RigupError::InvalidOptionValue {
+                    option: path.to_string(),
+                    value: value.to_string(),
+                    expected,
+                }
+                .into()
+            });
+        }
+        // Below an option whose type has sub-attributes (attribute set, submodule, anything...),
+        // which its own type checks
+        if options
+            .keys()
+            .any(|option| path.starts_with(&format!("{}.", option)))
+        {
+            return Ok(());
+        }
+        // A group of options
+        let group_prefix = format!("{}.", path);
+        if let Value::Object(values) = value {
+            if options
+                .keys()
+                .any(|option| option.starts_with(&group_prefix))
+            {
+                for (key, value) in values {
+                    go(rig, options, &format!("{}{}", group_prefix, key), value)?;
+                }
+                return Ok(());
+            }
+        }
+        Err(RigupError::UnknownName {
+            kind: "option",
+            name: path.to_string(),
+            scope: format!("rig `{}`", rig),
+            did_you_mean: did_you_mean(path, options.keys()),
+        }
+        .into())
+    }
+
+    for (key, value) in config {
+        go(rig, &options, key, value)?;
+    }
+    Ok(())
+}
+
+/// Extend a rig with config overrides, via its `extend` function. Overrides are set with the
+/// priority of `lib.mkForce`, so that they replace the values set in rigup.toml instead of
+/// conflicting with them
+fn extend_rig(rig: &NixExpr, config: &Map<String, Value>) -> NixExpr {
+    let json = Value::Object(config.clone()).to_string();
+    NixExpr::raw(format!(
+        r#"(let
+                force = value:
+                    if builtins.isAttrs value
+                    then builtins.mapAttrs (_: force) value
+                    else {{ _type = "override"; priority = 50; content = value; }};
+            in {rig}.extend {{
+                extraModules = [ {{
+                    _file = "rigup CLI config overrides";
+                    config = force (builtins.fromJSON {json});
+                }} ];
+            }})"#,
+        rig = rig,
+        json = NixExpr::string(&json),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_json() {
+        let overrides = ConfigOverrides {
+            set: vec![
+                "claude-code.strictMcpConfig=true".to_string(),
+                "models.default.modelId = some-model".to_string(),
+                r#"coreutils.commands=["ls", "cat"]"#.to_string(),
+                r#"agent.identity={ name = "Bot" }"#.to_string(),
+                r#"agent.identity.email="bot@example.com""#.to_string(),
+            ],
+            config_file: Vec::new(),
+        };
+        assert_eq!(
+            Value::Object(overrides.to_json().unwrap()),
+            json!({
+                "claude-code": { "strictMcpConfig": true },
+                "models": { "default": { "modelId": "some-model" } },
+                "coreutils": { "commands": ["ls", "cat"] },
+                "agent": { "identity": { "name": "Bot", "email": "bot@example.com" } },
+            })
+        );
+    }
+
+    #[test]
+    fn test_check_option() {
+        let option = |option_type: &str, enum_values: Option<Vec<Value>>| ConfigOption {
+            description: None,
+            option_type: option_type.to_string(),
+            default: None,
+            value: None,
+            enum_values,
+        };
+        assert!(check_option(&option("boolean", None), &json!(true)).is_ok());
+        assert!(check_option(&option("boolean", None), &json!("true")).is_err());
+        assert!(check_option(&option("null or signed integer", None), &json!(null)).is_ok());
+        assert!(check_option(&option("list of string", None), &json!(["a", 1])).is_err());
+        assert!(check_option(&option("null or (list of string)", None), &json!(["a"])).is_ok());
+        assert!(check_option(&option("package", None), &json!("hello")).is_ok());
+        let models = option("enum", Some(vec![json!("opus"), json!("sonnet")]));
+        assert!(check_option(&models, &json!("sonnet")).is_ok());
+        assert_eq!(
+            check_option(&models, &json!("haiku")),
+            Err(r#"one of "opus", "sonnet""#.to_string())
+        );
+    }
+}
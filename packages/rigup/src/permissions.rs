@@ -0,0 +1,285 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The deny rules of a rig, as buildRig.nix gives them (`denyRules` and `rigletDenyRules`)
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RigDenyRules {
+    /// Patterns by tool, merged from all riglets
+    pub deny_rules: BTreeMap<String, Vec<String>>,
+    /// Patterns by riglet then tool. Null with rigup libs older than this field
+    pub riglet_deny_rules: Option<BTreeMap<String, BTreeMap<String, Vec<String>>>>,
+}
+
+impl RigDenyRules {
+    /// One rule per pattern, with the riglet that sets it when it is known
+    pub fn into_rules(self) -> Vec<DenyRule> {
+        let Some(riglet_deny_rules) = self.riglet_deny_rules else {
+            return self
+                .deny_rules
+                .into_iter()
+                .flat_map(|(tool, patterns)| {
+                    patterns.into_iter().map(move |pattern| DenyRule {
+                        tool: tool.clone(),
+                        pattern,
+                        riglet: None,
+                    })
+                })
+                .collect();
+        };
+        riglet_deny_rules
+            .into_iter()
+            .flat_map(|(riglet, rules)| {
+                rules.into_iter().flat_map(move |(tool, patterns)| {
+                    let riglet = riglet.clone();
+                    patterns.into_iter().map(move |pattern| DenyRule {
+                        tool: tool.clone(),
+                        pattern,
+                        riglet: Some(riglet.clone()),
+                    })
+                })
+            })
+            .collect()
+    }
+}
+
+/// A command pattern that entrypoints deny, e.g. `push` for tool `git`
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct DenyRule {
+    pub tool: String,
+    pub pattern: String,
+    /// The riglet that sets the rule. Unknown with rigup libs that only give merged deny rules
+    pub riglet: Option<String>,
+}
+
+impl DenyRule {
+    /// Whether a command (as words) starts with the rule's tool then the words of its pattern,
+    /// which is what the rules that harnesses generate (e.g. `Bash(git push:*)`) check
+    fn matches(&self, command: &[String]) -> bool {
+        let pattern: Vec<&str> = self.pattern.split_whitespace().collect();
+        command.first() == Some(&self.tool)
+            && command.len() > pattern.len()
+            && command[1..=pattern.len()] == pattern[..]
+    }
+}
+
+/// What entrypoints let an agent run without asking: every executable of the rig, except the
+/// commands that match a deny rule
+#[derive(Debug)]
+pub struct Permissions {
+    /// The rig's executables (`allExeNames` in buildRig.nix), all allowed with any arguments
+    pub allowed: BTreeSet<String>,
+    pub denied: Vec<DenyRule>,
+}
+
+/// Whether a command can be run without asking
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict<'a> {
+    /// The command only runs executables of the rig, and matches no deny rule
+    Allowed,
+    /// A command of the line (given as words) matches a deny rule. Deny rules win over the
+    /// blanket allow
+    Denied(Vec<String>, &'a DenyRule),
+    /// A command of the line (given as words) runs an executable that is not part of the rig:
+    /// the harness asks before running it, or applies its own defaults
+    NotCovered(Vec<String>),
+    /// The line can't be split into its commands (see `split_commands`), for the given reason.
+    /// It is denied, as what it runs is unknown
+    Uncheckable(&'static str),
+}
+
+/// Split a shell command line into its commands, as words without their quotes. Commands are
+/// separated by `;`, `&&`, `||`, `|`, `|&`, `&` and newlines, even when glued to words, but not
+/// within quotes. Fails on lines whose commands can't be told apart that way
+fn split_commands(command_line: &str) -> Result<Vec<Vec<String>>, &'static str> {
+    const UNBALANCED: &str = "it has an unbalanced quote";
+    const SUBSTITUTION: &str = "it substitutes the output of a command";
+    let mut commands = Vec::new();
+    let mut command = Vec::new();
+    // None between words, so that quotes can start an empty word
+    let mut word: Option<String> = None;
+    let mut chars = command_line.chars().peekable();
+    while let Some(char) = chars.next() {
+        match char {
+            ' ' | '\t' => command.extend(word.take()),
+            // Redirections like `2>&1` are part of their word
+            '&' if word.as_ref().is_some_and(|word| word.ends_with(['>', '<'])) => {
+                word.get_or_insert_with(String::new).push(char)
+            }
+            ';' | '&' | '|' | '\n' => {
+                if matches!(
+                    (char, chars.peek()),
+                    ('&', Some('&')) | ('|', Some('|' | '&'))
+                ) {
+                    chars.next();
+                }
+                command.extend(word.take());
+                if !command.is_empty() {
+                    commands.push(std::mem::take(&mut command));
+                }
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or(UNBALANCED)? {
+                        '\'' => break,
+                        char => word.push(char),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or(UNBALANCED)? {
+                        '"' => break,
+                        '`' => return Err(SUBSTITUTION),
+                        '$' if chars.peek() == Some(&'(') => return Err(SUBSTITUTION),
+                        '\\' => match chars.next().ok_or(UNBALANCED)? {
+                            '\n' => {}
+                            escaped @ ('"' | '\\' | '$' | '`') => word.push(escaped),
+                            char => {
+                                word.push('\\');
+                                word.push(char);
+                            }
+                        },
+                        char => word.push(char),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') | None => {}
+                Some(char) => word.get_or_insert_with(String::new).push(char),
+            },
+            '`' => return Err(SUBSTITUTION),
+            '$' if chars.peek() == Some(&'(') => return Err(SUBSTITUTION),
+            '(' | ')' => return Err("it runs a subshell"),
+            char => word.get_or_insert_with(String::new).push(char),
+        }
+    }
+    command.extend(word);
+    if !command.is_empty() {
+        commands.push(command);
+    }
+    Ok(commands)
+}
+
+impl Permissions {
+    /// Check a shell command line. It is split into its commands like a shell would, and each
+    /// of them must be allowed for the line to be
+    pub fn check(&self, command_line: &str) -> Verdict<'_> {
+        let commands = match split_commands(command_line) {
+            Ok(commands) => commands,
+            Err(reason) => return Verdict::Uncheckable(reason),
+        };
+        for command in &commands {
+            if let Some(rule) = self.denied.iter().find(|rule| rule.matches(command)) {
+                return Verdict::Denied(command.clone(), rule);
+            }
+        }
+        match commands
+            .into_iter()
+            .find(|command| !self.allowed.contains(&command[0]))
+        {
+            Some(command) => Verdict::NotCovered(command),
+            None => Verdict::Allowed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn test_check() {
+        let deny_rules = RigDenyRules {
+            deny_rules: BTreeMap::new(),
+            riglet_deny_rules: Some(BTreeMap::from([
+                (
+                    "git-setup".to_string(),
+                    BTreeMap::from([(
+                        "git".to_string(),
+                        vec!["push".to_string(), "commit --amend".to_string()],
+                    )]),
+                ),
+                (
+                    "jj".to_string(),
+                    BTreeMap::from([("jj".to_string(), vec!["git push".to_string()])]),
+                ),
+            ])),
+        };
+        let permissions = Permissions {
+            allowed: BTreeSet::from(["git".to_string(), "jj".to_string()]),
+            denied: deny_rules.into_rules(),
+        };
+
+        assert_eq!(permissions.check("git status"), Verdict::Allowed);
+        assert_eq!(permissions.check("git commit -m 'Fix'"), Verdict::Allowed);
+        assert_eq!(permissions.check("git pushy"), Verdict::Allowed);
+        assert_eq!(
+            permissions.check("git push origin main"),
+            Verdict::Denied(
+                words(&["git", "push", "origin", "main"]),
+                &permissions.denied[0]
+            )
+        );
+        assert_eq!(
+            permissions.check("jj log && jj  git push"),
+            Verdict::Denied(words(&["jj", "git", "push"]), &permissions.denied[2])
+        );
+        assert_eq!(
+            permissions.check("git log | less"),
+            Verdict::NotCovered(words(&["less"]))
+        );
+        assert_eq!(permissions.denied[1].riglet.as_deref(), Some("git-setup"));
+
+        // Separators glued to words
+        let push = Verdict::Denied(words(&["git", "push"]), &permissions.denied[0]);
+        for command_line in [
+            "git status; git push",
+            "git status;git push",
+            "git log|git push",
+            "git status&&git push",
+            "git status||git push",
+            "git status & git push",
+            "git status\ngit push",
+            "git \"push\"",
+        ] {
+            assert_eq!(permissions.check(command_line), push, "{}", command_line);
+        }
+        assert_eq!(
+            permissions.check("git log 2>&1|less"),
+            Verdict::NotCovered(words(&["less"]))
+        );
+        // Separators within quotes
+        assert_eq!(
+            permissions.check("git commit -m 'Fix; git push'"),
+            Verdict::Allowed
+        );
+        assert_eq!(
+            permissions.check(r#"git commit -m "a && b \" | c""#),
+            Verdict::Allowed
+        );
+        // Lines that can't be split
+        assert_eq!(
+            permissions.check("git commit -m 'Fix"),
+            Verdict::Uncheckable("it has an unbalanced quote")
+        );
+        assert_eq!(
+            permissions.check("git status $(git push)"),
+            Verdict::Uncheckable("it substitutes the output of a command")
+        );
+        assert_eq!(
+            permissions.check("git commit -m \"`git push`\""),
+            Verdict::Uncheckable("it substitutes the output of a command")
+        );
+        assert_eq!(
+            permissions.check("(git push)"),
+            Verdict::Uncheckable("it runs a subshell")
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use serde::Deserialize;
+use std::ops::Range;
+
+/// A prompt command of a rig, as given by `promptCommands` in buildRig.nix. Its template already
+/// starts with the instruction to read the riglet's docs, when buildRig adds one
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptCommand {
+    pub description: String,
+    pub template: String,
+    #[serde(default)]
+    pub use_sub_agent: bool,
+}
+
+/// A placeholder in the template of a prompt command (see `promptCommand` in rigletSchema.nix)
+#[derive(Debug, PartialEq, Eq)]
+enum Placeholder {
+    /// `$ARGUMENTS`: all the arguments
+    All,
+    /// `$1` to `$9`: one argument, counting from 1. Longer numbers are left as they are, so
+    /// that amounts like `$100` do not ask for a hundred arguments
+    Positional(usize),
+}
+
+fn placeholders(template: &str) -> Vec<(Range<usize>, Placeholder)> {
+    let mut found = Vec::new();
+    for (start, _) in template.match_indices('$') {
+        let rest = &template[start + 1..];
+        if rest.starts_with("ARGUMENTS") {
+            found.push((start..start + 1 + "ARGUMENTS".len(), Placeholder::All));
+            continue;
+        }
+        let mut digits = rest.chars().map_while(|c| c.to_digit(10));
+        if let (Some(position @ 1..=9), None) = (digits.next(), digits.next()) {
+            found.push((start..start + 2, Placeholder::Positional(position as usize)));
+        }
+    }
+    found
+}
+
+/// Whether a template uses `$ARGUMENTS`
+pub fn uses_all_arguments(template: &str) -> bool {
+    placeholders(template)
+        .iter()
+        .any(|(_, placeholder)| *placeholder == Placeholder::All)
+}
+
+/// The number of single arguments that a template uses: the highest `$1` to `$9` in it
+pub fn positional_count(template: &str) -> usize {
+    placeholders(template)
+        .iter()
+        .filter_map(|(_, placeholder)| match placeholder {
+            Placeholder::Positional(position) => Some(*position),
+            Placeholder::All => None,
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Fill in a template: `$ARGUMENTS` with `all_arguments`, and `$1` to `$9` with the n-th of
+/// `positional` (or nothing if there are fewer), like harnesses do for slash commands
+pub fn render(template: &str, all_arguments: &str, positional: &[String]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut copied_until = 0;
+    for (range, placeholder) in placeholders(template) {
+        rendered.push_str(&template[copied_until..range.start]);
+        match placeholder {
+            Placeholder::All => rendered.push_str(all_arguments),
+            Placeholder::Positional(position) => {
+                if let Some(argument) = positional.get(position - 1) {
+                    rendered.push_str(argument);
+                }
+            }
+        }
+        copied_until = range.end;
+    }
+    rendered.push_str(&template[copied_until..]);
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let template = "Review $1 against $2 ($ARGUMENTS), costs $ and $0 and $12";
+        assert!(uses_all_arguments(template));
+        assert_eq!(positional_count(template), 2);
+        let positional = ["a.rs".to_string(), "main".to_string()];
+        assert_eq!(
+            render(template, "a.rs main", &positional),
+            "Review a.rs against main (a.rs main), costs $ and $0 and $12"
+        );
+        assert_eq!(positional_count("Keep the budget under $100 for $1"), 1);
+        assert_eq!(positional_count("Keep the budget under $100"), 0);
+        assert!(!uses_all_arguments("No arguments"));
+        assert_eq!(positional_count("No arguments"), 0);
+    }
+}
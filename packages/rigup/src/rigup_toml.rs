@@ -0,0 +1,1030 @@
+use crate::error::RigupError;
+use miette::{IntoDiagnostic, NamedSource, Result};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use toml_edit::{Array, DocumentMut, ImDocument, Item, Key, Table, TableLike, Value};
+
+/// The names of the rigs defined in the top-level table of a rigup TOML file
+fn rig_names(root: &Table) -> Vec<String> {
+    root.get("rigs")
+        .and_then(Item::as_table_like)
+        .map(|rigs| rigs.iter().map(|(name, _)| name.to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// The names in an entry of the `riglets` or `extends` of a rig, which is either a single name
+/// or a list of names
+fn entry_names(item: &Item) -> Vec<String> {
+    match item.as_value() {
+        Some(Value::Array(names)) => names
+            .iter()
+            .filter_map(|name| Some(name.as_str()?.to_string()))
+            .collect(),
+        Some(value) => value.as_str().map(str::to_string).into_iter().collect(),
+        None => Vec::new(),
+    }
+}
+
+/// All the names listed in the `<section>` of a rig's table (`section` being `riglets` or
+/// `extends`), as `(<input>, <name>)` pairs
+fn section_entries(rig: &dyn TableLike, section: &str) -> Vec<(String, String)> {
+    let Some(entries) = rig.get(section).and_then(Item::as_table_like) else {
+        return Vec::new();
+    };
+    entries
+        .iter()
+        .flat_map(|(input, item)| {
+            entry_names(item)
+                .into_iter()
+                .map(move |name| (input.to_string(), name))
+        })
+        .collect()
+}
+
+/// The dotted paths of the values that the `config` of a rig's table sets, and with
+/// `with_tables`, of the tables that hold them. Inline tables are merged into the options like
+/// standard ones, so they count as tables
+fn config_paths(rig: &dyn TableLike, with_tables: bool) -> Vec<String> {
+    fn go(prefix: &str, table: &dyn TableLike, with_tables: bool, acc: &mut Vec<String>) {
+        for (key, item) in table.iter() {
+            let path = format!("{}{}", prefix, key);
+            match item.as_table_like() {
+                Some(child) => {
+                    go(&format!("{}.", path), child, with_tables, acc);
+                    if with_tables {
+                        acc.push(path);
+                    }
+                }
+                None => acc.push(path),
+            }
+        }
+    }
+    let mut acc = Vec::new();
+    if let Some(config) = rig.get("config").and_then(Item::as_table_like) {
+        go("", config, with_tables, &mut acc);
+    }
+    acc
+}
+
+/// A rigup TOML file of the current project, parsed so that the location of each of its items
+/// is known (to point at them in error messages)
+pub struct TomlFile {
+    /// The path relative to the flake root, as shown in error messages
+    name: String,
+    doc: ImDocument<String>,
+}
+
+impl TomlFile {
+    /// Load and parse a TOML file. Returns None if it does not exist or is invalid TOML
+    pub fn load(flake_root: &Path, rel_path: &str) -> Option<Self> {
+        Self::load_checked(flake_root, rel_path)?.ok()
+    }
+
+    /// Load and parse a TOML file. Returns None if it does not exist, and a diagnostic pointing
+    /// at the syntax error if it is invalid TOML
+    pub fn load_checked(flake_root: &Path, rel_path: &str) -> Option<Result<Self, RigupError>> {
+        let contents = std::fs::read_to_string(flake_root.join(rel_path)).ok()?;
+        Some(Self::parse(rel_path, contents.clone()).map_err(|e| {
+            RigupError::InvalidRigDefinition {
+                message: format!("Invalid TOML in {}", rel_path),
+                src: NamedSource::new(rel_path, contents).with_language("TOML"),
+                span: e.span().unwrap_or_default().into(),
+                label: e.message().to_string(),
+                help: None,
+                related: Vec::new(),
+            }
+        }))
+    }
+
+    pub fn parse(name: &str, contents: String) -> Result<Self, toml_edit::TomlError> {
+        Ok(TomlFile {
+            name: name.to_string(),
+            doc: ImDocument::parse(contents)?,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn named_source(&self) -> NamedSource<String> {
+        NamedSource::new(&self.name, self.doc.raw().to_string()).with_language("TOML")
+    }
+
+    /// The file's top-level table
+    pub fn table(&self) -> &Table {
+        self.doc.as_table()
+    }
+
+    /// A diagnostic pointing at some location of this file
+    pub fn diagnostic(
+        &self,
+        message: String,
+        span: Option<Range<usize>>,
+        label: &str,
+        help: Option<String>,
+    ) -> RigupError {
+        RigupError::InvalidRigDefinition {
+            message,
+            src: self.named_source(),
+            span: span.unwrap_or_default().into(),
+            label: label.to_string(),
+            help,
+            related: Vec::new(),
+        }
+    }
+
+    /// The names of the rigs defined in this file
+    pub fn rig_names(&self) -> Vec<String> {
+        rig_names(self.doc.as_table())
+    }
+
+    fn rig(&self, rig: &str) -> Option<(Option<Range<usize>>, &dyn TableLike)> {
+        let (key, item) = self
+            .doc
+            .as_table()
+            .get("rigs")?
+            .as_table_like()?
+            .get_key_value(rig)?;
+        Some((key.span().or_else(|| item.span()), item.as_table_like()?))
+    }
+
+    /// Location of the name of a rig, in its first `[rigs.<rig>...]` header
+    pub fn rig_span(&self, rig: &str) -> Option<Range<usize>> {
+        self.rig(rig)?.0
+    }
+
+    /// The names in the `<section>.<input>` entry of a rig (`section` being `riglets` or
+    /// `extends`), which is either a single name or a list of names
+    pub fn rig_entry_names(&self, rig: &str, section: &str, input: &str) -> Vec<String> {
+        self.rig(rig)
+            .and_then(|(_, table)| table.get(section)?.as_table_like()?.get(input))
+            .map(entry_names)
+            .unwrap_or_default()
+    }
+
+    /// Location of `<name>` in the `<section>.<input>` list of a rig (`section` being `riglets`
+    /// or `extends`)
+    pub fn rig_entry_span(
+        &self,
+        rig: &str,
+        section: &str,
+        input: &str,
+        name: &str,
+    ) -> Option<Range<usize>> {
+        let (_, table) = self.rig(rig)?;
+        let (key, item) = table.get(section)?.as_table_like()?.get_key_value(input)?;
+        // The value is either a single name or a list of names
+        match item.as_value()? {
+            toml_edit::Value::Array(names) => names
+                .iter()
+                .find(|value| value.as_str() == Some(name))
+                .and_then(|value| value.span()),
+            value if value.as_str() == Some(name) => value.span(),
+            _ => key.span(),
+        }
+    }
+
+    /// Location of the deepest key of `config.<path>` defined by a rig. This can be the key of
+    /// a parent table, as the error may be about an option of a submodule
+    pub fn config_span(&self, rig: &str, path: &[String]) -> Option<Range<usize>> {
+        let (_, table) = self.rig(rig)?;
+        let (config_key, mut item) = table.get_key_value("config")?;
+        let mut span = config_key.span().or_else(|| item.span());
+        for name in path {
+            let Some((key, child)) = item
+                .as_table_like()
+                .and_then(|table| table.get_key_value(name))
+            else {
+                break;
+            };
+            span = key.span().or_else(|| child.span()).or(span);
+            item = child;
+        }
+        span
+    }
+
+    /// All the names listed in the `<section>` of a rig (`section` being `riglets` or
+    /// `extends`), as `(<input>, <name>)` pairs
+    pub fn rig_entries(&self, rig: &str, section: &str) -> Vec<(String, String)> {
+        self.rig(rig)
+            .map(|(_, table)| section_entries(table, section))
+            .unwrap_or_default()
+    }
+
+    /// The dotted paths of the values that the `config` of a rig sets. Inline tables are
+    /// merged into the options like standard ones, so only their values count
+    pub fn config_value_paths(&self, rig: &str) -> Vec<String> {
+        self.rig(rig)
+            .map(|(_, table)| config_paths(table, false))
+            .unwrap_or_default()
+    }
+}
+
+/// The names of the riglets that a project defines in its `riglets` folder: `<name>.nix` files
+/// and `<name>/default.nix` folders (see `riglets` in resolveProject.nix)
+pub fn project_riglet_names(flake_root: &Path) -> Vec<String> {
+    std::fs::read_dir(flake_root.join("riglets"))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            // A folder is named after its riglet as a whole, dots included
+            let (name, is_riglet) = if path.is_dir() {
+                (path.file_name()?, path.join("default.nix").exists())
+            } else {
+                let name = path.file_stem()?;
+                (name, path.extension()? == "nix" && name != "default")
+            };
+            if !is_riglet {
+                return None;
+            }
+            name.to_str().map(str::to_string)
+        })
+        .collect()
+}
+
+/// A rigup TOML file of the current project, to be edited while keeping its formatting and
+/// comments
+pub struct TomlFileMut {
+    path: PathBuf,
+    /// The path relative to the flake root, as shown in messages
+    name: String,
+    doc: DocumentMut,
+    /// The contents of the file when it was opened (empty if it did not exist), so that it is
+    /// only written if it was edited
+    original: String,
+}
+
+/// Get the sub-table `key` of a table, creating it if it is missing. A created table is dotted
+/// (`key.x = ...`) if its parent already holds values, so that it is written right there, and
+/// gets its own `[...key]` header otherwise
+fn child_table<'a>(parent: &'a mut dyn TableLike, key: &str) -> Option<&'a mut dyn TableLike> {
+    let has_values = parent
+        .iter()
+        .any(|(_, item)| item.is_value() || item.as_table().is_some_and(Table::is_dotted));
+    child_table_with(parent, key, has_values)
+}
+
+fn child_table_with<'a>(
+    parent: &'a mut dyn TableLike,
+    key: &str,
+    dotted: bool,
+) -> Option<&'a mut dyn TableLike> {
+    if !parent.contains_key(key) {
+        let mut table = Table::new();
+        table.set_implicit(true);
+        table.set_dotted(dotted);
+        // Inserted through `TableLike`, which turns it into an inline table if the parent is one
+        parent.insert(key, Item::Table(table));
+        if parent.get(key).is_some_and(Item::is_inline_table) {
+            pass_closing_space(parent);
+        }
+    }
+    parent.get_mut(key)?.as_table_like_mut()
+}
+
+/// Whether the item at `path` in a table is in an inline table, or would be created in one
+fn in_inline_table(table: &dyn TableLike, path: &[&str]) -> bool {
+    let Some((key, rest)) = path.split_first() else {
+        return false;
+    };
+    match table.get(key) {
+        Some(item) if item.is_inline_table() => !rest.is_empty(),
+        Some(item) => item
+            .as_table_like()
+            .is_some_and(|child| in_inline_table(child, rest)),
+        None => false,
+    }
+}
+
+/// The value that ends an item of an inline table: itself, or for dotted keys (`a.b = 1`), the
+/// value at the end of their path
+fn trailing_value(value: &mut Value) -> &mut Value {
+    let dotted = value
+        .as_inline_table()
+        .is_some_and(|table| table.is_dotted() && !table.is_empty());
+    if !dotted {
+        return value;
+    }
+    let table = value.as_inline_table_mut().expect("checked above");
+    let (_, last) = table.iter_mut().last().expect("checked above");
+    trailing_value(last)
+}
+
+/// In an inline table, the space before the closing brace is held by the last value. Pass it
+/// on to a value just appended after it, so that no space is left before the comma
+fn pass_closing_space(table: &mut dyn TableLike) {
+    let mut values: Vec<&mut Value> = table
+        .iter_mut()
+        .filter_map(|(_, item)| item.as_value_mut())
+        .collect();
+    if let [.., previous, last] = values.as_mut_slice() {
+        let previous = trailing_value(previous);
+        if let Some(space) = previous.decor().suffix().cloned() {
+            previous.decor_mut().set_suffix("");
+            last.decor_mut().set_suffix(space);
+        }
+    }
+}
+
+/// The last position of a table header in a document
+fn last_position(table: &Table) -> usize {
+    table
+        .iter()
+        .filter_map(|(_, item)| item.as_table())
+        .map(last_position)
+        .chain(table.position())
+        .max()
+        .unwrap_or(0)
+}
+
+/// Remove the item at `path` in a table, along with the tables that this leaves empty.
+/// Returns false if there is no such item
+fn remove_path(table: &mut dyn TableLike, path: &[String]) -> bool {
+    let Some((name, rest)) = path.split_first() else {
+        return false;
+    };
+    if rest.is_empty() {
+        return table.remove(name).is_some();
+    }
+    let Some(child) = table.get_mut(name).and_then(Item::as_table_like_mut) else {
+        return false;
+    };
+    let removed = remove_path(child, rest);
+    if removed && child.is_empty() {
+        table.remove(name);
+    }
+    removed
+}
+
+/// Append a string to an array, formatted like the items already there: on a new line with
+/// the same indentation if the array spans several lines, or else after a space
+fn push_formatted_str(array: &mut Array, value: &str) {
+    let prefix = match array.iter().last() {
+        None => String::new(),
+        Some(last) => match last.decor().prefix().and_then(|prefix| prefix.as_str()) {
+            Some(prefix) if prefix.contains('\n') => {
+                format!("\n{}", prefix.rsplit('\n').next().unwrap_or_default())
+            }
+            _ => " ".to_string(),
+        },
+    };
+    let mut value = Value::from(value);
+    value.decor_mut().set_prefix(prefix);
+    value.decor_mut().set_suffix("");
+    array.push_formatted(value);
+}
+
+impl TomlFileMut {
+    /// Load a TOML file to edit it. A file that does not exist yet is considered empty
+    pub fn open(flake_root: &Path, rel_path: &str) -> Result<Self> {
+        let path = flake_root.join(rel_path);
+        let contents = if path.exists() {
+            std::fs::read_to_string(&path).into_diagnostic()?
+        } else {
+            String::new()
+        };
+        Self::parse(path, rel_path, &contents)
+    }
+
+    fn parse(path: PathBuf, name: &str, contents: &str) -> Result<Self> {
+        let doc = contents
+            .parse()
+            .map_err(|e| miette::miette!("Invalid TOML in {}: {}", name, e))?;
+        Ok(TomlFileMut {
+            path,
+            name: name.to_string(),
+            doc,
+            original: contents.to_string(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn rigs(&self) -> Option<&dyn TableLike> {
+        self.doc.as_table().get("rigs")?.as_table_like()
+    }
+
+    fn rig(&self, rig: &str) -> Option<&dyn TableLike> {
+        self.rigs()?.get(rig)?.as_table_like()
+    }
+
+    /// The names of the rigs defined in this file
+    pub fn rig_names(&self) -> Vec<String> {
+        rig_names(self.doc.as_table())
+    }
+
+    pub fn has_rig(&self, rig: &str) -> bool {
+        self.rigs().is_some_and(|rigs| rigs.contains_key(rig))
+    }
+
+    /// The names listed in the `<section>.<input>` entries of a rig (`section` being `riglets`
+    /// or `extends`), as `(<input>, <name>)` pairs
+    fn rig_entries(&self, rig: &str, section: &str) -> Vec<(String, String)> {
+        self.rig(rig)
+            .map(|table| section_entries(table, section))
+            .unwrap_or_default()
+    }
+
+    /// The riglets that a rig lists, as `(<input>, <riglet>)` pairs
+    pub fn rig_riglets(&self, rig: &str) -> Vec<(String, String)> {
+        self.rig_entries(rig, "riglets")
+    }
+
+    /// The rigs that a rig extends, as `(<input>, <rig>)` pairs
+    pub fn rig_base_rigs(&self, rig: &str) -> Vec<(String, String)> {
+        self.rig_entries(rig, "extends")
+    }
+
+    /// The rigs of this file that extend a rig of the project (i.e. list it in `extends.self`)
+    pub fn rigs_extending(&self, base_rig: &str) -> Vec<String> {
+        self.rig_names()
+            .into_iter()
+            .filter(|rig| {
+                self.rig_base_rigs(rig)
+                    .iter()
+                    .any(|(input, name)| input == "self" && name == base_rig)
+            })
+            .collect()
+    }
+
+    /// Define a new rig, with its `extends` and `riglets` entries given as `(<input>, <name>)`
+    /// pairs. Each entry is a single name if the input has only one, or else a list of names.
+    /// Returns false if the rig already exists
+    pub fn create_rig(
+        &mut self,
+        rig: &str,
+        extends: &[(&str, &str)],
+        riglets: &[(&str, &str)],
+    ) -> bool {
+        // Written after all the other tables
+        let position = last_position(self.doc.as_table()) + 1;
+        let Some(rigs) = child_table_with(self.doc.as_table_mut(), "rigs", false) else {
+            return false;
+        };
+        if rigs.contains_key(rig) {
+            return false;
+        }
+        let mut table = Table::new();
+        table.set_position(position);
+        for (section, entries) in [("extends", extends), ("riglets", riglets)] {
+            if entries.is_empty() {
+                continue;
+            }
+            let mut section_table = Table::new();
+            section_table.set_dotted(true);
+            // Names grouped by input, in order of appearance
+            let mut by_input: Vec<(&str, Vec<&str>)> = Vec::new();
+            for (input, name) in entries {
+                match by_input.iter_mut().find(|(i, _)| i == input) {
+                    Some((_, names)) => names.push(name),
+                    None => by_input.push((input, vec![name])),
+                }
+            }
+            for (input, names) in by_input {
+                let value = match names.as_slice() {
+                    [name] => Value::from(*name),
+                    names => {
+                        let mut array = Array::new();
+                        for name in names {
+                            push_formatted_str(&mut array, name);
+                        }
+                        array.into()
+                    }
+                };
+                section_table.insert(input, Item::Value(value));
+            }
+            table.insert(section, Item::Table(section_table));
+        }
+        rigs.insert(rig, Item::Table(table));
+        true
+    }
+
+    /// Remove a rig, with all its tables. Returns false if it does not exist
+    pub fn delete_rig(&mut self, rig: &str) -> bool {
+        self.doc
+            .as_table_mut()
+            .get_mut("rigs")
+            .and_then(Item::as_table_like_mut)
+            .is_some_and(|rigs| rigs.remove(rig).is_some())
+    }
+
+    /// Rename a rig, keeping its tables where they are. Returns false if it does not exist
+    pub fn rename_rig(&mut self, old: &str, new: &str) -> bool {
+        let Some(rigs) = self
+            .doc
+            .as_table_mut()
+            .get_mut("rigs")
+            .and_then(Item::as_table_like_mut)
+        else {
+            return false;
+        };
+        let Some(old_key) = rigs.get_key_value(old).map(|(key, _)| key.clone()) else {
+            return false;
+        };
+        let Some(item) = rigs.remove(old) else {
+            return false;
+        };
+        let new_key = Key::new(new).with_leaf_decor(old_key.leaf_decor().clone());
+        rigs.entry_format(&new_key).or_insert(item);
+        true
+    }
+
+    /// Make the rigs that extend the project's rig `old` (in `extends.self`) extend `new`
+    /// instead. Returns the names of the rigs that changed
+    pub fn replace_base_rig(&mut self, old: &str, new: &str) -> Vec<String> {
+        let Some(rigs) = self
+            .doc
+            .as_table_mut()
+            .get_mut("rigs")
+            .and_then(Item::as_table_like_mut)
+        else {
+            return Vec::new();
+        };
+        let mut changed = Vec::new();
+        for (rig, item) in rigs.iter_mut() {
+            let Some(names) = item
+                .as_table_like_mut()
+                .and_then(|rig| rig.get_mut("extends")?.as_table_like_mut())
+                .and_then(|extends| extends.get_mut("self")?.as_value_mut())
+            else {
+                continue;
+            };
+            let values: Vec<&mut Value> = match names {
+                Value::Array(names) => names.iter_mut().collect(),
+                name => vec![name],
+            };
+            let mut replaced = false;
+            for value in values {
+                if value.as_str() == Some(old) {
+                    let decor = value.decor().clone();
+                    *value = Value::from(new);
+                    *value.decor_mut() = decor;
+                    replaced = true;
+                }
+            }
+            if replaced {
+                changed.push(rig.get().to_string());
+            }
+        }
+        changed
+    }
+
+    fn rig_mut(&mut self, rig: &str) -> Option<&mut dyn TableLike> {
+        self.doc
+            .as_table_mut()
+            .get_mut("rigs")
+            .and_then(Item::as_table_like_mut)?
+            .get_mut(rig)?
+            .as_table_like_mut()
+    }
+
+    /// The paths of all the items that the `config` of a rig sets, tables included
+    pub fn config_paths(&self, rig: &str) -> Vec<String> {
+        self.rig(rig)
+            .map(|table| config_paths(table, true))
+            .unwrap_or_default()
+    }
+
+    /// Set `config.<path>` in a rig, creating the rig and the tables along the path if needed.
+    /// Returns false if some item along the path is not a table
+    pub fn set_config(&mut self, rig: &str, path: &[String], value: Value) -> bool {
+        let Some((name, parents)) = path.split_last() else {
+            return false;
+        };
+        let mut value_path = vec!["rigs", rig, "config"];
+        value_path.extend(path.iter().map(String::as_str));
+        let inline = in_inline_table(self.doc.as_table(), &value_path);
+        let Some(mut table) = child_table_with(self.doc.as_table_mut(), "rigs", false)
+            .and_then(|rigs| child_table(rigs, rig))
+            .and_then(|rig| child_table(rig, "config"))
+        else {
+            return false;
+        };
+        // Tables created below `config` are dotted, so the value is set in a single line
+        for parent in parents {
+            let Some(child) = child_table_with(table, parent, true) else {
+                return false;
+            };
+            table = child;
+        }
+        match table.get_mut(name).and_then(Item::as_value_mut) {
+            // Keep the comments around the value it replaces
+            Some(old) => {
+                let decor = old.decor().clone();
+                *old = value;
+                *old.decor_mut() = decor;
+            }
+            None => {
+                table.insert(name, Item::Value(value));
+                if inline {
+                    pass_closing_space(table);
+                }
+            }
+        }
+        true
+    }
+
+    /// Remove `config.<path>` from a rig, along with the tables that this leaves empty.
+    /// Returns false if the rig does not set it
+    pub fn unset_config(&mut self, rig: &str, path: &[String]) -> bool {
+        let Some(rig) = self.rig_mut(rig) else {
+            return false;
+        };
+        let mut config_path = vec!["config".to_string()];
+        config_path.extend(path.iter().cloned());
+        remove_path(rig, &config_path)
+    }
+
+    /// The `riglets.<input>` entry of a rig, creating the rig (and its `riglets` table) if needed
+    fn riglets_entry(&mut self, rig: &str, input: &str) -> Option<&mut Item> {
+        let rigs = child_table_with(self.doc.as_table_mut(), "rigs", false)?;
+        let riglets = child_table(child_table(rigs, rig)?, "riglets")?;
+        Some(
+            riglets
+                .entry(input)
+                .or_insert(Item::Value(Array::new().into())),
+        )
+    }
+
+    /// Add a riglet to the `riglets.<input>` list of a rig. Returns false if it was already there
+    pub fn add_riglet(&mut self, rig: &str, input: &str, riglet: &str) -> bool {
+        let Some(item) = self.riglets_entry(rig, input) else {
+            return false;
+        };
+        // A single name becomes a list
+        if let Some(Value::String(name)) = item.as_value() {
+            if name.value() == riglet {
+                return false;
+            }
+            let mut names = Array::new();
+            push_formatted_str(&mut names, name.value());
+            let decor = name.decor().clone();
+            *item = Item::Value(names.into());
+            if let Some(value) = item.as_value_mut() {
+                *value.decor_mut() = decor;
+            }
+        }
+        let Some(names) = item.as_array_mut() else {
+            return false;
+        };
+        if names.iter().any(|name| name.as_str() == Some(riglet)) {
+            return false;
+        }
+        push_formatted_str(names, riglet);
+        true
+    }
+
+    /// Remove a riglet from the `riglets.<input>` list of a rig. The list is removed if it ends
+    /// up empty. Returns false if the riglet was not listed
+    pub fn remove_riglet(&mut self, rig: &str, input: &str, riglet: &str) -> bool {
+        let Some(riglets) = self
+            .rig_mut(rig)
+            .and_then(|rig| rig.get_mut("riglets")?.as_table_like_mut())
+        else {
+            return false;
+        };
+        let Some(item) = riglets.get_mut(input) else {
+            return false;
+        };
+        let now_empty = match item.as_value_mut() {
+            Some(Value::Array(names)) => {
+                let Some(index) = names.iter().position(|name| name.as_str() == Some(riglet))
+                else {
+                    return false;
+                };
+                let removed = names.remove(index);
+                // The first item keeps the formatting of the one it replaces, unless it has
+                // its own comments
+                if let Some(first) = names.get_mut(0).filter(|_| index == 0) {
+                    let own_prefix = first.decor().prefix().and_then(|prefix| prefix.as_str());
+                    if own_prefix.is_none_or(|prefix| prefix.trim().is_empty()) {
+                        *first.decor_mut() = removed.decor().clone();
+                    }
+                }
+                names.is_empty()
+            }
+            Some(value) if value.as_str() == Some(riglet) => true,
+            _ => return false,
+        };
+        if now_empty {
+            riglets.remove(input);
+        }
+        true
+    }
+
+    /// Write the file back, if it changed
+    pub fn save(&self) -> Result<()> {
+        let contents = self.doc.to_string();
+        // A file that was not edited is left as it is, or not created if it did not exist
+        if contents != self.original {
+            std::fs::write(&self.path, contents)
+                .into_diagnostic()
+                .map_err(|e| e.wrap_err(format!("Cannot write {}", self.name)))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOML: &str = r#"
+[rigs.first.riglets]
+self = ["a", "b"]
+other = "c"
+
+[rigs.first.config]
+agent.identity.name = "Test Agent"
+
+[rigs.second]
+extends.self = "first"
+config = { foo = { bar = 1 } }
+"#;
+
+    fn text_at(span: Option<Range<usize>>) -> &'static str {
+        &TOML[span.expect("span not found")]
+    }
+
+    #[test]
+    fn test_spans() {
+        let file = TomlFile::parse("rigup.toml", TOML.to_string()).unwrap();
+        assert_eq!(text_at(file.rig_span("first")), "first");
+        assert_eq!(text_at(file.rig_span("second")), "second");
+        assert_eq!(
+            text_at(file.rig_entry_span("first", "riglets", "self", "b")),
+            r#""b""#
+        );
+        assert_eq!(
+            text_at(file.rig_entry_span("first", "riglets", "other", "c")),
+            r#""c""#
+        );
+        assert_eq!(
+            text_at(file.rig_entry_span("second", "extends", "self", "first")),
+            r#""first""#
+        );
+        let path = |p: &str| p.split('.').map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            text_at(file.config_span("first", &path("agent.identity.name"))),
+            "name"
+        );
+        assert_eq!(
+            text_at(file.config_span("first", &path("agent.identity.nam"))),
+            "identity"
+        );
+        assert_eq!(text_at(file.config_span("second", &path("foo.baz"))), "foo");
+        assert_eq!(file.rig_span("third"), None);
+        assert_eq!(file.config_value_paths("first"), ["agent.identity.name"]);
+        assert_eq!(file.config_value_paths("second"), ["foo.bar"]);
+    }
+
+    fn edit(contents: &str, f: impl FnOnce(&mut TomlFileMut)) -> String {
+        let mut file = TomlFileMut::parse(PathBuf::new(), "rigup.toml", contents).unwrap();
+        f(&mut file);
+        file.doc.to_string()
+    }
+
+    #[test]
+    fn test_project_riglet_names() {
+        let root = std::env::temp_dir().join(format!("rigup-riglets-test-{}", std::process::id()));
+        let riglets = root.join("riglets");
+        std::fs::create_dir_all(riglets.join("jj.basics")).unwrap();
+        std::fs::create_dir_all(riglets.join("empty")).unwrap();
+        for file in [
+            "jj.basics/default.nix",
+            "git.nix",
+            "default.nix",
+            "notes.md",
+        ] {
+            std::fs::write(riglets.join(file), "").unwrap();
+        }
+
+        let mut names = project_riglet_names(&root);
+        names.sort();
+        assert_eq!(names, ["git", "jj.basics"]);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_add_riglet() {
+        let contents = r#"[rigs.first.riglets]
+self = [
+  "a",
+  # Comment
+  "b",
+]
+other = "c"
+
+[rigs.second]
+extends.self = "first"
+"#;
+        assert_eq!(
+            edit(contents, |file| {
+                assert!(file.add_riglet("first", "self", "d"));
+                assert!(!file.add_riglet("first", "self", "a"));
+                assert!(file.add_riglet("first", "other", "e"));
+                assert!(file.add_riglet("second", "self", "f"));
+                assert!(file.add_riglet("third", "self", "g"));
+            }),
+            r#"[rigs.first.riglets]
+self = [
+  "a",
+  # Comment
+  "b",
+  "d",
+]
+other = ["c", "e"]
+
+[rigs.second]
+extends.self = "first"
+riglets.self = ["f"]
+
+[rigs.third.riglets]
+self = ["g"]
+"#
+        );
+        assert_eq!(
+            edit("", |file| {
+                file.add_riglet("new", "self", "a");
+            }),
+            "[rigs.new.riglets]\nself = [\"a\"]\n"
+        );
+    }
+
+    #[test]
+    fn test_remove_riglet() {
+        let contents = r#"[rigs.first.riglets]
+self = ["a", "b", "c"]
+other = "d"
+"#;
+        assert_eq!(
+            edit(contents, |file| {
+                assert!(file.remove_riglet("first", "self", "a"));
+                assert!(file.remove_riglet("first", "self", "c"));
+                assert!(file.remove_riglet("first", "other", "d"));
+                assert!(!file.remove_riglet("first", "other", "d"));
+                assert!(!file.remove_riglet("second", "self", "a"));
+            }),
+            "[rigs.first.riglets]\nself = [\"b\"]\n"
+        );
+        let file = TomlFileMut::parse(PathBuf::new(), "rigup.toml", contents).unwrap();
+        assert_eq!(
+            file.rig_riglets("first"),
+            [("self", "a"), ("self", "b"), ("self", "c"), ("other", "d")]
+                .map(|(input, name)| (input.to_string(), name.to_string()))
+        );
+    }
+
+    #[test]
+    fn test_set_config() {
+        let contents = r#"[rigs.first.riglets]
+self = ["a"]
+
+[rigs.first.config]
+agent.identity.name = "Test Agent" # The name
+
+[rigs.second]
+extends.self = "first"
+"#;
+        let path = |p: &str| p.split('.').map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            edit(contents, |file| {
+                assert!(file.set_config("first", &path("agent.identity.name"), "Bot".into()));
+                assert!(file.set_config("first", &path("models.default.modelId"), "opus".into()));
+                assert!(file.set_config("second", &path("agent.identity.email"), "a@b.c".into()));
+                assert!(!file.set_config("first", &path("agent.identity.name.x"), 1.into()));
+            }),
+            r#"[rigs.first.riglets]
+self = ["a"]
+
+[rigs.first.config]
+agent.identity.name = "Bot" # The name
+models.default.modelId = "opus"
+
+[rigs.second]
+extends.self = "first"
+config.agent.identity.email = "a@b.c"
+"#
+        );
+        assert_eq!(
+            edit("", |file| {
+                file.set_config("new", &path("a.b"), true.into());
+            }),
+            "[rigs.new.config]\na.b = true\n"
+        );
+        // Inline tables are edited in place
+        assert_eq!(
+            edit(
+                "[rigs.first]\nconfig = { agent.identity.name = \"Test Agent\" }\n\n[rigs.second]\nconfig.models = { default = { modelId = \"opus\" } }\n",
+                |file| {
+                    assert!(file.set_config("first", &path("agent.identity.name"), "Bot".into()));
+                    assert!(file.set_config("first", &path("models.default.modelId"), "opus".into()));
+                    assert!(file.set_config("second", &path("models.default.modelId"), "haiku".into()));
+                    assert!(file.set_config("second", &path("models.fast.modelId"), "haiku".into()));
+                }
+            ),
+            "[rigs.first]\nconfig = { agent.identity.name = \"Bot\", models = { default = { modelId = \"opus\" } } }\n\n[rigs.second]\nconfig.models = { default = { modelId = \"haiku\" }, fast = { modelId = \"haiku\" } }\n"
+        );
+    }
+
+    #[test]
+    fn test_unset_config() {
+        let contents = r#"[rigs.first.config]
+agent.identity.name = "Test Agent"
+agent.identity.email = "test@example.com"
+
+[rigs.first.config.agent.personality]
+tone = "dry"
+"#;
+        let path = |p: &str| p.split('.').map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            edit(contents, |file| {
+                assert!(file.unset_config("first", &path("agent.personality.tone")));
+                assert!(file.unset_config("first", &path("agent.identity.name")));
+                assert!(!file.unset_config("first", &path("agent.identity.name")));
+                assert!(!file.unset_config("second", &path("agent")));
+            }),
+            r#"[rigs.first.config]
+agent.identity.email = "test@example.com"
+"#
+        );
+        assert_eq!(
+            edit(contents, |file| {
+                assert!(file.unset_config("first", &path("agent")));
+            }),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_save_edited_only() {
+        let root = std::env::temp_dir().join(format!("rigup-save-test-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let mut file = TomlFileMut::open(&root, "rigup.local.toml").unwrap();
+        assert!(!file.rename_rig("old", "new"));
+        assert!(file.replace_base_rig("old", "new").is_empty());
+        file.save().unwrap();
+        assert!(!root.join("rigup.local.toml").exists());
+
+        file.create_rig("new", &[], &[("self", "a")]);
+        file.save().unwrap();
+        assert!(root.join("rigup.local.toml").exists());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_rig_lifecycle() {
+        let contents = r#"# Base rig
+[rigs.first.riglets]
+self = ["a"]
+
+[rigs.first.config]
+x = 1
+
+[rigs.second]
+extends.self = "first" # Comment
+"#;
+        assert_eq!(
+            edit(contents, |file| {
+                assert!(file.rename_rig("first", "base"));
+                assert_eq!(file.replace_base_rig("first", "base"), ["second"]);
+                assert!(!file.create_rig("second", &[], &[]));
+                assert!(file.create_rig(
+                    "third",
+                    &[("self", "base")],
+                    &[("self", "b"), ("other", "d"), ("self", "c")]
+                ));
+                assert_eq!(file.rigs_extending("base"), ["second", "third"]);
+            }),
+            r#"# Base rig
+[rigs.base.riglets]
+self = ["a"]
+
+[rigs.base.config]
+x = 1
+
+[rigs.second]
+extends.self = "base" # Comment
+
+[rigs.third]
+extends.self = "base"
+riglets.self = ["b", "c"]
+riglets.other = "d"
+"#
+        );
+        assert_eq!(
+            edit(contents, |file| {
+                assert!(file.delete_rig("first"));
+                assert!(!file.delete_rig("first"));
+            }),
+            r#"
+[rigs.second]
+extends.self = "first" # Comment
+"#
+        );
+    }
+}
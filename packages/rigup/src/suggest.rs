@@ -0,0 +1,54 @@
+use itertools::Itertools;
+
+/// The candidates closest to a (probably misspelled) name, by edit distance, best first
+pub fn closest_matches<'a, S: AsRef<str> + ?Sized + 'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a S>,
+) -> Vec<&'a str> {
+    // Beyond that, candidates have little to do with the name
+    let max_distance = (name.chars().count() / 3).max(2);
+    candidates
+        .into_iter()
+        .map(|candidate| {
+            let candidate = candidate.as_ref();
+            let distance = strsim::levenshtein(&name.to_lowercase(), &candidate.to_lowercase());
+            (distance, candidate)
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .sorted()
+        .dedup()
+        .take(3)
+        .map(|(_, candidate)| candidate)
+        .collect()
+}
+
+/// A "Did you mean ...?" help message, if some candidates are close to the name
+pub fn did_you_mean<'a, S: AsRef<str> + ?Sized + 'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a S>,
+) -> Option<String> {
+    let matches = closest_matches(name, candidates);
+    (!matches.is_empty()).then(|| {
+        format!(
+            "Did you mean {}?",
+            matches.iter().map(|m| format!("`{}`", m)).join(" or ")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closest_matches() {
+        let names = ["example-rig", "default", "claude-rig", "examples"];
+        assert_eq!(closest_matches("exmaple-rig", names), ["example-rig"]);
+        assert_eq!(closest_matches("Default", names), ["default"]);
+        assert!(closest_matches("something-else", names).is_empty());
+        assert_eq!(
+            did_you_mean("exampl", names).as_deref(),
+            Some("Did you mean `examples`?")
+        );
+    }
+}
@@ -1,8 +1,11 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
-#[derive(Deserialize, Debug)]
+// NOTE: BTreeMaps are used throughout so that both the tree and the JSON outputs
+// list riglets, rigs and options in a stable (alphabetical) order
+
+#[derive(Deserialize, Serialize, Debug)]
 pub struct RigletMeta {
     #[serde(default)]
     pub description: String,
@@ -26,22 +29,25 @@ pub struct RigletMeta {
     pub entrypoint: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct RigMeta {
-    pub riglets: HashMap<String, RigletMeta>,
+    pub riglets: BTreeMap<String, RigletMeta>,
     #[serde(default)]
     pub entrypoint: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Default)]
 pub struct InputData {
     #[serde(default)]
-    pub riglets: HashMap<String, RigletMeta>,
+    pub riglets: BTreeMap<String, RigletMeta>,
     #[serde(default)]
-    pub rigs: HashMap<String, RigMeta>,
+    pub rigs: BTreeMap<String, RigMeta>,
+    /// Set by the CLI when this input could not be evaluated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct ConfigOption {
     #[serde(default)]
     pub description: Option<String>,
@@ -59,19 +65,76 @@ pub struct ConfigOption {
 // Can be either a leaf option or a nested tree
 // IMPORTANT: Nested must come first so serde tries it before Option
 // (since ConfigOption has #[serde(default)] on all fields, it can match anything)
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(untagged)]
 pub enum ConfigValue {
-    Nested(HashMap<String, ConfigValue>),
+    Nested(BTreeMap<String, ConfigValue>),
     Option(ConfigOption),
 }
 
-#[derive(Deserialize, Debug)]
+/// Flatten a tree of config options into a map of dotted option path -> option
+pub fn flatten_options(options: &BTreeMap<String, ConfigValue>) -> BTreeMap<String, &ConfigOption> {
+    fn go<'a>(
+        prefix: &str,
+        options: &'a BTreeMap<String, ConfigValue>,
+        acc: &mut BTreeMap<String, &'a ConfigOption>,
+    ) {
+        for (key, value) in options {
+            let path = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+            match value {
+                ConfigValue::Option(opt) => {
+                    acc.insert(path, opt);
+                }
+                ConfigValue::Nested(nested) => go(&path, nested, acc),
+            }
+        }
+    }
+    let mut acc = BTreeMap::new();
+    go("", options, &mut acc);
+    acc
+}
+
+/// An MCP server declared by the rig, in displayable form
+#[derive(Deserialize, Serialize, Debug)]
+pub struct McpServerInfo {
+    /// "stdio" for local servers, "http" or "sse" for remote ones
+    pub transport: String,
+    /// Executable started by a local server
+    #[serde(default)]
+    pub command: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Names of the HTTP headers sent to a remote server (values may be secrets so are not fetched)
+    #[serde(default)]
+    pub headers: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct PromptCommandInfo {
+    #[serde(default)]
+    pub description: String,
+    #[serde(rename = "useSubAgent", default)]
+    pub use_sub_agent: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
 pub struct RigInspection {
     pub name: String,
-    pub riglets: HashMap<String, RigletMeta>,
+    pub riglets: BTreeMap<String, RigletMeta>,
     #[serde(default)]
     pub entrypoint: Option<String>,
     #[serde(default)]
-    pub options: HashMap<String, ConfigValue>,
+    pub options: BTreeMap<String, ConfigValue>,
+    #[serde(rename = "mcpServers", default)]
+    pub mcp_servers: BTreeMap<String, McpServerInfo>,
+    /// Prompt commands, by namespaced `riglet:command` name
+    #[serde(rename = "promptCommands", default)]
+    pub prompt_commands: BTreeMap<String, PromptCommandInfo>,
+    /// Denied command patterns, by tool name
+    #[serde(rename = "denyRules", default)]
+    pub deny_rules: BTreeMap<String, Vec<String>>,
 }